@@ -15,21 +15,24 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 /////
 
+use crate::bibiman::{attach, import};
 use crate::bibiman::{CurrentArea, FormerArea};
 use color_eyre::eyre::{Context, Ok, Result};
 // use super::Event;
 use crate::cliargs::CLIArgs;
+use crate::config::BibiConfig;
 use crate::tui::commands::InputCmdAction;
-use crate::tui::popup::PopupKind;
+use crate::tui::keymap::{KeyChord, KeyLookup};
+use crate::tui::popup::{InputAction, ListAction, PopupKind};
 use crate::tui::{self, Tui};
 use crate::{bibiman::Bibiman, tui::commands::CmdAction};
-use ratatui::crossterm::event::KeyCode;
+use ratatui::crossterm::event::{Event as CrosstermEvent, KeyCode, KeyEvent};
 use std::ffi::OsStr;
 use std::path::PathBuf;
 use std::process::{Command, Stdio};
 use tui::Event;
 use tui_input::backend::crossterm::EventHandler;
-use tui_input::Input;
+use tui_input::{Input, InputRequest};
 
 // Application.
 #[derive(Debug)]
@@ -42,34 +45,46 @@ pub struct App {
     pub input: Input,
     // Input mode bool
     pub input_mode: bool,
+    // Keys of a not yet completed multi-key binding
+    pub pending_keys: Vec<KeyChord>,
+    // Bibfiles changed on disk, but weren't reloaded yet
+    pub reload_pending: bool,
 }
 
 impl App {
     // Constructs a new instance of [`App`].
-    pub fn new(args: &CLIArgs) -> Result<Self> {
+    pub fn new(args: &CLIArgs, cfg: &BibiConfig) -> Result<Self> {
         // Self::default()
         let running = true;
         let input = Input::default();
-        let bibiman = Bibiman::new(args)?;
+        let bibiman = Bibiman::new(args, cfg)?;
         Ok(Self {
             running,
             bibiman,
             input,
             input_mode: false,
+            pending_keys: Vec::new(),
+            reload_pending: false,
         })
     }
 
-    pub async fn run(&mut self, args: &CLIArgs) -> Result<()> {
+    pub async fn run(&mut self, args: &mut CLIArgs, cfg: &BibiConfig) -> Result<()> {
         let mut tui = tui::Tui::new()?;
+        if cfg.general.watch_files {
+            tui.watch(args.pos_args.clone(), self.bibiman.files_loaded.clone());
+        }
+        if cfg.general.fulltext_index {
+            self.bibiman.start_fulltext_index(tui.sender());
+        }
         tui.enter()?;
 
         // Start the main loop.
         while self.running {
             // Render the user interface.
-            tui.draw(self, args)?;
+            tui.draw(self, args, cfg)?;
             // Handle events.
             match tui.next().await? {
-                Event::Tick => self.tick(),
+                Event::Tick => self.tick(args, cfg),
                 // Event::Key(key_event) => handle_key_events(key_event, self, &mut tui)?,
                 // Event::Mouse(_) => {}
                 Event::Key(key_event) => {
@@ -81,6 +96,7 @@ impl App {
                         self.bibiman.close_popup()
                     } else if let Some(PopupKind::AddEntry) = self.bibiman.popup_area.popup_kind {
                         // Handle key events for AddEntry popup
+                        #[allow(clippy::collapsible_match)]
                         match key_event.code {
                             KeyCode::Char(c) => {
                                 let index = self.bibiman.popup_area.add_entry_cursor_position;
@@ -108,8 +124,7 @@ impl App {
                             }
                             KeyCode::Enter => {
                                 // Handle submission of the new entry
-                                self.bibiman.handle_new_entry_submission(args);
-                                self.bibiman.close_popup();
+                                self.bibiman.handle_new_entry_submission(cfg, tui.sender());
                                 self.input_mode = false;
                             }
                             KeyCode::Esc => {
@@ -119,13 +134,38 @@ impl App {
                             }
                             _ => {}
                         }
+                    } else if let Some(PopupKind::Lookup) = self.bibiman.popup_area.popup_kind {
+                        // Other keys are ignored until the lookup is finished
+                        if key_event.code == KeyCode::Esc {
+                            self.bibiman.cancel_lookup();
+                        }
+                    } else if let Some(PopupKind::Input(_)) = self.bibiman.popup_area.popup_kind {
+                        // Keys of the popup input can't be remapped, like the search input
+                        match key_event.code {
+                            KeyCode::Enter => self.bibiman.confirm_popup_input(args, cfg),
+                            KeyCode::Esc => self.bibiman.close_popup(),
+                            KeyCode::Tab
+                                if matches!(
+                                    self.bibiman.popup_area.popup_kind,
+                                    Some(PopupKind::Input(InputAction::AttachFile))
+                                ) =>
+                            {
+                                self.bibiman.complete_attach_path()
+                            }
+                            _ => {
+                                self.bibiman
+                                    .popup_area
+                                    .popup_input
+                                    .handle_event(&CrosstermEvent::Key(key_event));
+                            }
+                        }
                     } else {
                         let command = if self.input_mode {
                             CmdAction::Input(InputCmdAction::parse(key_event, &self.input))
                         } else {
-                            CmdAction::from(key_event)
+                            self.command_from_keymap(key_event, cfg)
                         };
-                        self.run_command(command, args, &mut tui)?
+                        self.run_command(command, args, cfg, &mut tui)?
                     }
                 }
                 // Event::Key(key_event) => {
@@ -142,13 +182,21 @@ impl App {
                 //     } else {
                 //         CmdAction::from(key_event)
                 //     };
-                //     self.run_command(command, args, &mut tui)?
+                //     self.run_command(command, args, cfg, &mut tui)?
                 // }
                 Event::Mouse(mouse_event) => {
-                    self.run_command(CmdAction::from(mouse_event), args, &mut tui)?
+                    self.run_command(CmdAction::from(mouse_event), args, cfg, &mut tui)?
                 }
 
                 Event::Resize(_, _) => {}
+                Event::LookupFinished(result) => self.bibiman.finish_lookup(cfg, result),
+                Event::Paste(text) => self.paste(args, cfg, &text),
+                Event::FulltextIndexed(texts) => self.bibiman.set_fulltext(texts),
+                Event::FilesChanged(files) => {
+                    args.files = files;
+                    self.reload_pending = true;
+                    self.tick(args, cfg);
+                }
             }
         }
 
@@ -158,7 +206,74 @@ impl App {
     }
 
     // Handles the tick event of the terminal.
-    pub fn tick(&self) {}
+    pub fn tick(&mut self, args: &CLIArgs, cfg: &BibiConfig) {
+        // Popups may refer to entries by index, thus, wait until they are
+        // closed before reloading the bibfiles
+        if self.reload_pending && self.bibiman.popup_area.popup_kind.is_none() {
+            self.bibiman.update_lists(args, cfg);
+            self.reload_pending = false;
+        }
+    }
+
+    // Translate the key event into a command using the keymap. Keys of
+    // multi-key bindings are collected until the sequence is complete
+    pub fn command_from_keymap(&mut self, key_event: KeyEvent, cfg: &BibiConfig) -> CmdAction {
+        self.pending_keys.push(KeyChord::from(key_event));
+        match cfg.keymap.lookup(&self.pending_keys) {
+            KeyLookup::Action(action) => {
+                self.pending_keys.clear();
+                action.into()
+            }
+            KeyLookup::Pending => CmdAction::Nothing,
+            KeyLookup::NoMatch => {
+                // If a started sequence is broken, try the last key on its own
+                let retry = self.pending_keys.len() > 1;
+                self.pending_keys.clear();
+                if retry {
+                    self.command_from_keymap(key_event, cfg)
+                } else {
+                    CmdAction::Nothing
+                }
+            }
+        }
+    }
+
+    // Pasted text is inserted into the focused input. BibTeX entries or RIS
+    // records pasted into the AddEntry popup are imported directly
+    pub fn paste(&mut self, args: &CLIArgs, cfg: &BibiConfig, text: &str) {
+        // Terminals may send line breaks as carriage returns
+        let text = &text.replace("\r\n", "\n").replace('\r', "\n");
+        match self.bibiman.popup_area.popup_kind {
+            Some(PopupKind::AddEntry) if import::is_import(text) => {
+                self.bibiman.import_text(args, cfg, text);
+                self.input_mode = false;
+            }
+            Some(PopupKind::AddEntry) => {
+                let text = text.replace('\n', " ");
+                let index = self.bibiman.popup_area.add_entry_cursor_position;
+                self.bibiman
+                    .popup_area
+                    .add_entry_input
+                    .insert_str(index, &text);
+                self.bibiman.popup_area.add_entry_cursor_position += text.len();
+            }
+            Some(PopupKind::Input(_)) => {
+                for c in text.chars().filter(|c| !c.is_control()) {
+                    self.bibiman
+                        .popup_area
+                        .popup_input
+                        .handle(InputRequest::InsertChar(c));
+                }
+            }
+            None if self.input_mode => {
+                for c in text.chars().filter(|c| !c.is_control()) {
+                    self.input.handle(InputRequest::InsertChar(c));
+                }
+                self.bibiman.search_list_by_pattern(&self.input);
+            }
+            _ => {}
+        }
+    }
 
     // General commands
 
@@ -167,7 +282,13 @@ impl App {
         self.running = false;
     }
 
-    pub fn run_command(&mut self, cmd: CmdAction, args: &CLIArgs, tui: &mut Tui) -> Result<()> {
+    pub fn run_command(
+        &mut self,
+        cmd: CmdAction,
+        args: &CLIArgs,
+        cfg: &BibiConfig,
+        tui: &mut Tui,
+    ) -> Result<()> {
         match cmd {
             CmdAction::Input(cmd) => match cmd {
                 InputCmdAction::Nothing => {}
@@ -189,7 +310,7 @@ impl App {
                 InputCmdAction::Exit => {
                     self.input = Input::default();
                     self.input_mode = false;
-                    self.bibiman.break_search();
+                    self.bibiman.break_search(cfg);
                 }
             },
             CmdAction::SelectNextRow(amount) => match self.bibiman.current_area {
@@ -203,7 +324,9 @@ impl App {
                 CurrentArea::PopupArea => {
                     if let Some(PopupKind::Help) = self.bibiman.popup_area.popup_kind {
                         self.bibiman.popup_area.popup_scroll_down();
-                    } else if let Some(PopupKind::Selection) = self.bibiman.popup_area.popup_kind {
+                    } else if let Some(PopupKind::Selection | PopupKind::ListSelection(_)) =
+                        self.bibiman.popup_area.popup_kind
+                    {
                         self.bibiman.popup_area.popup_state.scroll_down_by(1)
                     }
                 }
@@ -220,7 +343,9 @@ impl App {
                 CurrentArea::PopupArea => {
                     if let Some(PopupKind::Help) = self.bibiman.popup_area.popup_kind {
                         self.bibiman.popup_area.popup_scroll_up();
-                    } else if let Some(PopupKind::Selection) = self.bibiman.popup_area.popup_kind {
+                    } else if let Some(PopupKind::Selection | PopupKind::ListSelection(_)) =
+                        self.bibiman.popup_area.popup_kind
+                    {
                         self.bibiman.popup_area.popup_state.scroll_up_by(1)
                     }
                 }
@@ -269,11 +394,16 @@ impl App {
                     if let Some(PopupKind::Help) = self.bibiman.popup_area.popup_kind {
                         self.bibiman.popup_area.popup_scroll_pos = 0;
                         self.bibiman.close_popup()
-                    } else if let Some(PopupKind::Selection) = self.bibiman.popup_area.popup_kind {
+                    } else if let Some(PopupKind::Selection | PopupKind::ListSelection(_)) =
+                        self.bibiman.popup_area.popup_kind
+                    {
                         self.bibiman.close_popup()
                     }
+                } else if self.bibiman.entry_table.has_marks() {
+                    // Clear the marks first, the lists with the next press
+                    self.bibiman.entry_table.clear_marks();
                 } else {
-                    self.bibiman.reset_current_list();
+                    self.bibiman.reset_current_list(cfg);
                 }
             }
             CmdAction::Confirm => {
@@ -299,16 +429,31 @@ impl App {
                             let object =
                                 self.bibiman.entry_table.entry_table_items[entry_idx].doi_url();
                             let url = prepare_weblink(object);
-                            open_connected_link(&url)?;
-                        } else if self.bibiman.popup_area.popup_list[popup_idx].contains("File") {
-                            let object =
-                                self.bibiman.entry_table.entry_table_items[entry_idx].filepath();
-                            open_connected_file(object)?;
+                            open_connected_link(cfg, &url)?;
+                        } else if let Some(file) =
+                            self.bibiman.popup_area.popup_list[popup_idx].strip_prefix("File: ")
+                        {
+                            open_connected_file(cfg, OsStr::new(file))?;
+                        } else if let Some(file) = self.bibiman.popup_area.popup_list[popup_idx]
+                            .strip_prefix("File (missing): ")
+                        {
+                            let file = file.to_string();
+                            self.bibiman.close_popup();
+                            self.bibiman.popup_area.popup_message(
+                                "File doesn't exist: ",
+                                &file,
+                                false,
+                            );
+                            return Ok(());
                         } else {
                             eprintln!("Unable to find ressource to open");
                         };
                         // run command to open file/Url
                         self.bibiman.close_popup()
+                    } else if let Some(PopupKind::ListSelection(_)) =
+                        self.bibiman.popup_area.popup_kind
+                    {
+                        self.bibiman.confirm_list_selection(args, cfg);
                     }
                 }
             }
@@ -319,27 +464,96 @@ impl App {
             }
             CmdAction::YankItem => {
                 if let CurrentArea::EntryArea = self.bibiman.current_area {
-                    let citekey: &str = &self.bibiman.entry_table.entry_table_items[self
-                        .bibiman
-                        .entry_table
-                        .entry_table_state
-                        .selected()
-                        .unwrap()]
-                    .citekey;
-
-                    Bibiman::yank_text(citekey);
-                    self.bibiman.popup_area.popup_message(
-                        "Yanked citekey to clipboard: ",
-                        citekey, // self.bibiman.get_selected_citekey(),
-                        true,
-                    );
+                    self.bibiman.yank_citekeys();
+                }
+            }
+            CmdAction::YankReference => {
+                if let CurrentArea::EntryArea = self.bibiman.current_area {
+                    self.bibiman.yank_reference();
+                }
+            }
+            CmdAction::ToggleMark => {
+                if let CurrentArea::EntryArea = self.bibiman.current_area {
+                    self.bibiman.toggle_mark();
+                }
+            }
+            CmdAction::ToggleVisual => {
+                if let CurrentArea::EntryArea = self.bibiman.current_area {
+                    self.bibiman.entry_table.toggle_visual();
+                }
+            }
+            CmdAction::DeleteEntries => {
+                if let CurrentArea::EntryArea = self.bibiman.current_area {
+                    self.bibiman.delete_entries();
+                }
+            }
+            CmdAction::MoveEntries => {
+                if let CurrentArea::EntryArea = self.bibiman.current_area {
+                    self.bibiman
+                        .select_target_file(args, ListAction::MoveEntries);
+                }
+            }
+            CmdAction::CopyEntries => {
+                if let CurrentArea::EntryArea = self.bibiman.current_area {
+                    self.bibiman
+                        .select_target_file(args, ListAction::CopyEntries);
+                }
+            }
+            CmdAction::ShowDuplicates => {
+                if let CurrentArea::EntryArea = self.bibiman.current_area {
+                    self.bibiman.show_duplicates(args);
+                }
+            }
+            CmdAction::ShowLint => {
+                if let CurrentArea::EntryArea = self.bibiman.current_area {
+                    self.bibiman.show_lint(cfg);
+                }
+            }
+            CmdAction::RegenerateCitekeys => {
+                if let CurrentArea::EntryArea = self.bibiman.current_area {
+                    self.bibiman.show_citekey_renames(args, cfg);
                 }
             }
             CmdAction::EditFile => {
                 if let CurrentArea::EntryArea = self.bibiman.current_area {
-                    self.bibiman.run_editor(args, tui)?;
+                    self.bibiman.run_editor(args, cfg, tui)?;
+                }
+            }
+            CmdAction::EditField => {
+                if let CurrentArea::EntryArea = self.bibiman.current_area {
+                    self.bibiman.select_field_to_edit(args);
+                }
+            }
+            CmdAction::AttachFile => {
+                if let CurrentArea::EntryArea = self.bibiman.current_area {
+                    self.bibiman.select_file_to_attach(args);
+                }
+            }
+            CmdAction::AddKeyword(scope) => {
+                if let CurrentArea::EntryArea = self.bibiman.current_area {
+                    self.bibiman.add_keyword(scope);
+                }
+            }
+            CmdAction::RemoveKeyword(scope) => {
+                if let CurrentArea::EntryArea = self.bibiman.current_area {
+                    self.bibiman.remove_keyword(scope);
                 }
             }
+            CmdAction::RenameKeyword => match self.bibiman.current_area {
+                CurrentArea::EntryArea | CurrentArea::TagArea => self.bibiman.rename_keyword(),
+                _ => {}
+            },
+            CmdAction::FilterTags(op) => {
+                if let CurrentArea::TagArea = self.bibiman.current_area {
+                    self.bibiman.add_tag_filter(op);
+                }
+            }
+            CmdAction::PopTagFilter => match self.bibiman.current_area {
+                CurrentArea::EntryArea | CurrentArea::TagArea => {
+                    self.bibiman.remove_last_tag_filter()
+                }
+                _ => {}
+            },
             CmdAction::Open => {
                 if let CurrentArea::EntryArea = self.bibiman.current_area {
                     let idx = self
@@ -349,13 +563,19 @@ impl App {
                         .selected()
                         .unwrap();
                     let entry = self.bibiman.entry_table.entry_table_items[idx].clone();
-                    if entry.filepath.is_some() || entry.doi_url.is_some() {
+                    let files = entry.files();
+                    if !files.is_empty() || entry.doi_url.is_some() {
                         let mut items = vec![];
                         if entry.doi_url.is_some() {
                             items.push("Weblink (DOI/URL)".to_owned())
                         }
-                        if entry.filepath.is_some() {
-                            items.push("File (PDF/EPUB)".to_owned())
+                        // Each file of the entry can be opened on its own
+                        for file in files {
+                            if attach::file_exists(&file) {
+                                items.push(format!("File: {}", file))
+                            } else {
+                                items.push(format!("File (missing): {}", file))
+                            }
                         }
                         self.bibiman.popup_area.popup_selection(items);
                         self.bibiman.former_area = Some(FormerArea::EntryArea);
@@ -370,12 +590,20 @@ impl App {
                     }
                 }
             }
+            CmdAction::Export => {
+                if let CurrentArea::EntryArea = self.bibiman.current_area {
+                    self.bibiman.select_export_format();
+                }
+            }
             CmdAction::AddEntry => {
                 self.bibiman.add_entry();
             }
             CmdAction::ShowHelp => {
                 self.bibiman.show_help();
             }
+            CmdAction::ShowDiagnostics => {
+                self.bibiman.show_diagnostics();
+            }
             CmdAction::Exit => {
                 self.quit();
             }
@@ -385,17 +613,10 @@ impl App {
     }
 }
 
-pub fn open_connected_file(file: &OsStr) -> Result<()> {
+pub fn open_connected_file(cfg: &BibiConfig, file: &OsStr) -> Result<()> {
     // Build command to execute pdf-reader. 'xdg-open' is Linux standard
-    // TODO: make custom opener command possible through config
-    let cmd = {
-        match std::env::consts::OS {
-            "linux" => String::from("xdg-open"),
-            "macos" => String::from("open"),
-            "windows" => String::from("start"),
-            _ => panic!("Couldn't detect OS for setting correct opener"),
-        }
-    };
+    // and used as default if no other opener is set in the config file
+    let (cmd, cmd_args) = split_opener(&cfg.general.pdf_opener);
 
     // If necessary, replace ~ with /home dir
     let file = PathBuf::from(file);
@@ -404,7 +625,8 @@ pub fn open_connected_file(file: &OsStr) -> Result<()> {
 
     // Pass filepath as argument, pipe stdout and stderr to /dev/null
     // to keep the TUI clean (where is it piped on Windows???)
-    let _ = Command::new(&cmd)
+    let _ = Command::new(cmd)
+        .args(cmd_args)
         .arg(file)
         .stdout(Stdio::null())
         .stderr(Stdio::null())
@@ -414,21 +636,15 @@ pub fn open_connected_file(file: &OsStr) -> Result<()> {
     Ok(())
 }
 
-pub fn open_connected_link(link: &str) -> Result<()> {
-    // Build command to execute pdf-reader. 'xdg-open' is Linux standard
-    // TODO: make custom opener command possible through config
-    let cmd = {
-        match std::env::consts::OS {
-            "linux" => String::from("xdg-open"),
-            "macos" => String::from("open"),
-            "windows" => String::from("start"),
-            _ => panic!("Couldn't detect OS for setting correct opener"),
-        }
-    };
+pub fn open_connected_link(cfg: &BibiConfig, link: &str) -> Result<()> {
+    // Build command to open the link. 'xdg-open' is Linux standard
+    // and used as default if no other opener is set in the config file
+    let (cmd, cmd_args) = split_opener(&cfg.general.url_opener);
 
     // Pass filepath as argument, pipe stdout and stderr to /dev/null
     // to keep the TUI clean (where is it piped on Windows???)
-    let _ = Command::new(&cmd)
+    let _ = Command::new(cmd)
+        .args(cmd_args)
         .arg(link)
         .stdout(Stdio::null())
         .stderr(Stdio::null())
@@ -438,6 +654,14 @@ pub fn open_connected_link(link: &str) -> Result<()> {
     Ok(())
 }
 
+// Split opener command set in config into program and its arguments,
+// e.g. "zathura --fork" becomes ("zathura", ["--fork"])
+fn split_opener(opener: &str) -> (&str, Vec<&str>) {
+    let mut parts = opener.split_whitespace();
+    let cmd = parts.next().unwrap_or_default();
+    (cmd, parts.collect())
+}
+
 pub fn prepare_weblink(url: &str) -> String {
     if url.starts_with("10.") {
         "https://doi.org/".to_string() + url
@@ -448,7 +672,7 @@ pub fn prepare_weblink(url: &str) -> String {
     }
 }
 
-fn expand_home(path: &PathBuf) -> PathBuf {
+pub fn expand_home(path: &PathBuf) -> PathBuf {
     // let path = PathBuf::from(path);
     if path.starts_with("~") {
         let mut home = dirs::home_dir().unwrap();
@@ -476,4 +700,13 @@ mod test {
 
         assert_eq!(path, PathBuf::from(full_path))
     }
+
+    #[test]
+    fn test_split_opener() {
+        assert_eq!(split_opener("xdg-open"), ("xdg-open", vec![]));
+        assert_eq!(
+            split_opener("zathura --fork -P 1"),
+            ("zathura", vec!["--fork", "-P", "1"])
+        );
+    }
 }
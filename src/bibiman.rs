@@ -15,30 +15,63 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 /////
 
+use crate::app::expand_home;
+use crate::bibiman::citation::CitationPreview;
+use crate::bibiman::duplicates::{DuplicateGroup, Merge};
 use crate::bibiman::entries::EntryTableColumn;
+use crate::bibiman::entries::EntryTableItem;
+use crate::bibiman::export::ExportFormat;
+use crate::bibiman::fulltext::{EntryTexts, FulltextIndexer};
+use crate::bibiman::keywords::{FilterOp, KeywordFilter};
+use crate::bibiman::lint::LintIssue;
+use crate::bibiman::lookup::{LookupError, LookupResult, PendingLookup};
+use crate::bibiman::newentry::{citekey_problem, NewEntry};
+use crate::bibiman::query::Query;
 use crate::bibiman::{bibisetup::*, search::BibiSearch};
 use crate::cliargs::CLIArgs;
-use crate::tui::popup::{PopupArea, PopupKind};
-use crate::tui::Tui;
+use crate::config::BibiConfig;
+use crate::tui::keymap::KeyAction;
+use crate::tui::popup::{InputAction, KeywordScope, ListAction, PopupArea, PopupKind};
+use crate::tui::{Event, Tui};
 use crate::{bibiman::entries::EntryTable, bibiman::keywords::TagList};
 use arboard::Clipboard;
 use color_eyre::eyre::Result;
-use doi2bib;
 use editor_command::EditorBuilder;
-use futures::executor::block_on;
 use ratatui::widgets::ScrollbarState;
 use std::fs;
-use std::fs::OpenOptions;
-use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::result::Result::Ok;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
 use tui_input::Input;
 
+pub mod attach;
+pub mod bibedit;
 pub mod bibisetup;
+pub mod bibyaml;
+pub mod citation;
+pub mod citekey;
+pub mod diagnostics;
+pub mod duplicates;
 pub mod entries;
+pub mod export;
+pub mod fulltext;
+pub mod import;
 pub mod keywords;
+pub mod lint;
+pub mod lookup;
+pub mod newentry;
+pub mod query;
 pub mod search;
 
+// Fields offered by the field editor even if not set for an entry
+const EDITABLE_FIELDS: [&str; 8] = [
+    "author", "title", "date", "keywords", "doi", "url", "file", "abstract",
+];
+
 // Areas in which actions are possible
 #[derive(Debug)]
 pub enum CurrentArea {
@@ -77,18 +110,40 @@ pub struct Bibiman {
     pub former_area: Option<FormerArea>,
     // active popup
     pub popup_area: PopupArea,
+    // formatted reference of the selected entry
+    pub citation_preview: CitationPreview,
+    // groups of duplicate entries listed in the duplicates popup
+    pub duplicates: Vec<DuplicateGroup>,
+    // merge of duplicates in progress
+    pub merge: Option<Merge>,
+    // issues listed in the lint popup
+    pub lint_issues: Vec<LintIssue>,
+    // lookup of a new entry running in the background
+    pub lookup: Option<PendingLookup>,
+    // fetched entry which is reviewed before it is written
+    pub new_entry: Option<NewEntry>,
+    // Entries imported at once, written after the target file is chosen
+    pub import_entries: Vec<NewEntry>,
+    pub import_duplicates: Vec<String>,
+    // old and new citekeys listed in the regenerate citekeys popup
+    pub citekey_renames: Vec<(String, String)>,
+    // Set after the bibfiles were loaded again, thus, the file watcher
+    // doesn't report changes bibiman made itself
+    pub files_loaded: Arc<AtomicBool>,
+    // Updates the full-text index in the background, if enabled
+    pub fulltext_indexer: Option<FulltextIndexer>,
 }
 
 impl Bibiman {
     // Constructs a new instance of [`App`].
-    pub fn new(args: &CLIArgs) -> Result<Self> {
+    pub fn new(args: &CLIArgs, cfg: &BibiConfig) -> Result<Self> {
         // let main_bibfiles = args.fileargs.clone();
         let main_biblio = BibiSetup::new(&args.files);
         let tag_list = TagList::new(main_biblio.keyword_list.clone());
         let search_struct = BibiSearch::default();
-        let entry_table = EntryTable::new(&main_biblio.entry_list);
+        let entry_table = EntryTable::new(&main_biblio.entry_list, &cfg.general.sort_column);
         let current_area = CurrentArea::EntryArea;
-        Ok(Self {
+        let mut bibiman = Self {
             // main_bibfiles,
             main_biblio,
             tag_list,
@@ -98,7 +153,33 @@ impl Bibiman {
             current_area,
             former_area: None,
             popup_area: PopupArea::default(),
-        })
+            citation_preview: CitationPreview::new(&cfg.general.citation_style),
+            duplicates: Vec::new(),
+            merge: None,
+            lint_issues: Vec::new(),
+            lookup: None,
+            new_entry: None,
+            import_entries: Vec::new(),
+            import_duplicates: Vec::new(),
+            citekey_renames: Vec::new(),
+            files_loaded: Arc::default(),
+            fulltext_indexer: None,
+        };
+        // Point to the skipped entries on startup
+        if !bibiman.main_biblio.diagnostics.is_empty() {
+            bibiman.former_area = Some(FormerArea::EntryArea);
+            bibiman.current_area = CurrentArea::PopupArea;
+            bibiman.popup_area.popup_message(
+                &format!(
+                    "{} problem(s) found in the bibfiles, the affected entries are skipped. Press {} to list them",
+                    bibiman.main_biblio.diagnostics.len(),
+                    cfg.keymap.keys_for(KeyAction::Diagnostics)
+                ),
+                "",
+                false,
+            );
+        }
+        Ok(bibiman)
     }
 
     pub fn show_help(&mut self) {
@@ -112,6 +193,29 @@ impl Bibiman {
         self.popup_area.popup_kind = Some(PopupKind::Help);
     }
 
+    /// List the problems found while loading the bibfiles
+    pub fn show_diagnostics(&mut self) {
+        if let CurrentArea::EntryArea = self.current_area {
+            self.former_area = Some(FormerArea::EntryArea);
+        } else if let CurrentArea::TagArea = self.current_area {
+            self.former_area = Some(FormerArea::TagArea);
+        }
+        self.current_area = CurrentArea::PopupArea;
+        if self.main_biblio.diagnostics.is_empty() {
+            self.popup_area
+                .popup_message("No problems found in the bibfiles", "", true);
+            return;
+        }
+        let problems = self
+            .main_biblio
+            .diagnostics
+            .iter()
+            .map(|d| d.summary())
+            .collect();
+        self.popup_area
+            .popup_list_selection(problems, ListAction::Diagnostics);
+    }
+
     pub fn add_entry(&mut self) {
         if let CurrentArea::EntryArea = self.current_area {
             self.former_area = Some(FormerArea::EntryArea);
@@ -123,24 +227,184 @@ impl Bibiman {
         self.popup_area.popup_kind = Some(PopupKind::AddEntry);
     }
 
-    pub fn handle_new_entry_submission(&mut self, args: &CLIArgs) {
-        let new_entry_title = self.popup_area.add_entry_input.trim();
-        let doi2bib = doi2bib::Doi2Bib::new().unwrap();
-        let new_entry_future = doi2bib.resolve_doi(new_entry_title);
-        let new_entry = block_on(new_entry_future);
+    /// Start looking up the identifier entered in the add entry popup. The
+    /// result is sent as event, thus, the TUI isn't blocked meanwhile
+    pub fn handle_new_entry_submission(
+        &mut self,
+        cfg: &BibiConfig,
+        sender: UnboundedSender<Event>,
+    ) {
+        let input = self.popup_area.add_entry_input.trim().to_string();
+        self.close_popup();
+        let resolvers = lookup::resolvers(&cfg.lookup);
+        let Some((resolver, id)) = lookup::detect(&resolvers, &input) else {
+            match lookup::malformed(&resolvers, &input) {
+                Some(resolver) => self.popup_area.popup_message(
+                    &format!("Malformed {}: ", resolver.name()),
+                    &input,
+                    false,
+                ),
+                None => self.popup_area.popup_message(
+                    "Not a DOI, ISBN, arXiv ID or PubMed ID: ",
+                    &input,
+                    false,
+                ),
+            }
+            return;
+        };
+        let progress = sender.clone();
+        self.lookup = Some(PendingLookup::spawn(
+            resolver,
+            id,
+            Duration::from_secs(cfg.lookup.timeout),
+            // Redraw the spinner
+            move || {
+                let _ = progress.send(Event::Tick);
+            },
+            move |result| {
+                let _ = sender.send(Event::LookupFinished(result));
+            },
+        ));
+        self.former_area = Some(FormerArea::EntryArea);
+        self.current_area = CurrentArea::PopupArea;
+        self.popup_area.popup_kind = Some(PopupKind::Lookup);
+        self.popup_area.is_popup = true;
+    }
 
-        if let Ok(entry) = new_entry {
-            // TODO: Add error handling for failed insert
-            let formatted_content = Self::format_bibtex_entry(&entry, "");
+    /// Review the fetched entry or show why the lookup failed
+    pub fn finish_lookup(&mut self, cfg: &BibiConfig, result: LookupResult) {
+        // Results of cancelled lookups which were already sent are dropped
+        let Some(lookup) = self.lookup.take() else {
+            return;
+        };
+        self.close_popup();
+        let (name, id) = (lookup.name, &lookup.id);
+        let entry = result.and_then(|entry| {
+            NewEntry::new(&entry, &self.main_biblio, &cfg.citekey)
+                .map_err(|e| LookupError::Malformed(e.to_string()))
+        });
+        let (message, details) = match entry {
+            Ok(entry) => return self.review_new_entry(entry),
+            Err(LookupError::NotFound) => (format!("No entry found for {}: ", name), id.clone()),
+            Err(LookupError::Network(e)) => (format!("Looking up {} {} failed: ", name, id), e),
+            Err(LookupError::Malformed(e)) => {
+                (format!("Unexpected answer for {} {}: ", name, id), e)
+            }
+            Err(LookupError::TimedOut) => (
+                format!(
+                    "Looking up {} {} timed out after {} s",
+                    name, id, cfg.lookup.timeout
+                ),
+                String::new(),
+            ),
+        };
+        self.popup_area.popup_message(&message, &details, false);
+    }
 
-            if self.append_to_file(args, &formatted_content).is_err() {
-                self.popup_area.popup_kind = Some(PopupKind::MessageError);
-                self.popup_area.popup_message = "Failed to add new entry".to_string();
+    /// Stop the running lookup and close its popup
+    pub fn cancel_lookup(&mut self) {
+        if let Some(lookup) = self.lookup.take() {
+            lookup.cancel();
+        }
+        self.close_popup();
+    }
+
+    /// Show the fetched entry together with its citekey, which can be
+    /// changed before the entry is written
+    fn review_new_entry(&mut self, entry: NewEntry) {
+        self.former_area = Some(FormerArea::EntryArea);
+        self.current_area = CurrentArea::PopupArea;
+        self.popup_area
+            .popup_input(&entry.citekey, InputAction::NewEntryCitekey);
+        self.popup_area.popup_input_info = entry.content.lines().map(String::from).collect();
+        self.popup_area.popup_input_warnings = entry.warnings(&self.main_biblio);
+        self.new_entry = Some(entry);
+    }
+
+    fn write_new_entry(&mut self, args: &CLIArgs, cfg: &BibiConfig, entry: &NewEntry, file: &Path) {
+        let content = Self::format_bibtex_entry(&entry.with_citekey(), "");
+        match self.append_to_file(args, cfg, file, &content) {
+            Ok(()) => {
+                self.select_entry_by_citekey(&entry.citekey);
+                self.popup_area
+                    .popup_message("Added new entry: ", &entry.citekey, true);
+            }
+            Err(e) => {
+                self.popup_area
+                    .popup_message("Failed to add new entry: ", &e.to_string(), false)
+            }
+        }
+    }
+
+    /// Import the BibTeX entries or RIS records pasted into the add entry
+    /// popup. A single entry is reviewed like a fetched one
+    pub fn import_text(&mut self, args: &CLIArgs, cfg: &BibiConfig, text: &str) {
+        self.close_popup();
+        let import = match import::read_import(text, &self.main_biblio, &cfg.citekey) {
+            Ok(import) => import,
+            Err(e) => {
+                self.popup_area
+                    .popup_message("Failed to import entries: ", &e.to_string(), false);
+                return;
+            }
+        };
+        if import.entries.is_empty() {
+            self.popup_area.popup_message(
+                "Entries already exist: ",
+                &import.duplicates.join(", "),
+                false,
+            );
+            return;
+        }
+        let mut entries = import.entries;
+        if entries.len() == 1 && import.duplicates.is_empty() {
+            return self.review_new_entry(entries.remove(0));
+        }
+        self.import_entries = entries;
+        self.import_duplicates = import.duplicates;
+        if args.files.len() > 1 {
+            let files = args
+                .files
+                .iter()
+                .map(|f| f.to_string_lossy().to_string())
+                .collect();
+            self.former_area = Some(FormerArea::EntryArea);
+            self.current_area = CurrentArea::PopupArea;
+            self.popup_area
+                .popup_list_selection(files, ListAction::ImportFile);
+        } else if let Some(file) = args.files.first() {
+            self.write_imported_entries(args, cfg, &file.clone());
+        }
+    }
+
+    fn write_imported_entries(&mut self, args: &CLIArgs, cfg: &BibiConfig, file: &Path) {
+        let entries = std::mem::take(&mut self.import_entries);
+        let duplicates = std::mem::take(&mut self.import_duplicates);
+        let content = entries
+            .iter()
+            .map(|e| Self::format_bibtex_entry(&e.with_citekey(), ""))
+            .collect::<Vec<String>>()
+            .join("\n\n");
+        match self.append_to_file(args, cfg, file, &content) {
+            Ok(()) => {
+                if let Some(entry) = entries.first() {
+                    self.select_entry_by_citekey(&entry.citekey);
+                }
+                let message = format!("Imported {} entries", entries.len());
+                if duplicates.is_empty() {
+                    self.popup_area.popup_message(&message, "", true);
+                } else {
+                    self.popup_area.popup_message(
+                        &format!("{}, skipped existing: ", message),
+                        &duplicates.join(", "),
+                        true,
+                    );
+                }
+            }
+            Err(e) => {
+                self.popup_area
+                    .popup_message("Failed to import entries: ", &e.to_string(), false)
             }
-        // TODO: Add error handling for failed DOI lookup
-        } else {
-            self.popup_area.popup_kind = Some(PopupKind::MessageError);
-            self.popup_area.popup_message = "Failed to add new entry".to_string();
         }
     }
 
@@ -159,10 +423,129 @@ impl Bibiman {
         self.former_area = None;
     }
 
-    pub fn update_lists(&mut self, args: &CLIArgs) {
+    /// Load the bibfiles again, e.g. after they were changed. The view is
+    /// kept: searches, keyword filters and sorting are applied to the new
+    /// entries and the selected entry stays selected. If it is gone, a
+    /// nearby entry is selected
+    pub fn update_lists(&mut self, args: &CLIArgs, cfg: &BibiConfig) {
+        let old_citekeys: Vec<String> = self
+            .entry_table
+            .entry_table_items
+            .iter()
+            .map(|e| e.citekey.clone())
+            .collect();
+        let old_table = std::mem::replace(
+            &mut self.entry_table,
+            EntryTable::new(&[], &cfg.general.sort_column),
+        );
+        let keyword_filter = std::mem::take(&mut self.tag_list.keyword_filter);
+        let old_tag_idx = self.tag_list.tag_list_state.selected();
+        let old_tag = old_tag_idx.and_then(|idx| self.tag_list.tag_list_items.get(idx).cloned());
+
+        // The texts are kept until the index is updated in the background
+        let texts: EntryTexts = std::mem::take(&mut self.main_biblio.entry_list)
+            .into_iter()
+            .filter_map(|e| Some((e.citekey, e.fulltext?)))
+            .collect();
         self.main_biblio = BibiSetup::new(&args.files);
+        self.files_loaded.store(true, Ordering::Relaxed);
+        for entry in self.main_biblio.entry_list.iter_mut() {
+            entry.fulltext = texts.get(&entry.citekey).cloned();
+        }
+        if let Some(indexer) = &self.fulltext_indexer {
+            indexer.update(&self.main_biblio.entry_list);
+        }
         self.tag_list = TagList::new(self.main_biblio.keyword_list.clone());
-        self.entry_table = EntryTable::new(&self.main_biblio.entry_list);
+        self.entry_table = EntryTable::new(&self.main_biblio.entry_list, &cfg.general.sort_column);
+        self.citation_preview.clear_cache();
+
+        // Apply searches and keyword filters in the order they were applied
+        // before
+        let searches = self.search_struct.applied_searches.clone();
+        let (before_filter, after_filter) =
+            searches.split_at(self.search_struct.filter_start.min(searches.len()));
+        for search in before_filter {
+            let items = std::mem::take(&mut self.entry_table.entry_table_items);
+            self.entry_table.entry_table_items = BibiSearch::search_entry_list(search, items);
+        }
+        if !keyword_filter.is_empty() {
+            self.entry_table.entry_table_at_filter_start =
+                self.entry_table.entry_table_items.clone();
+            self.tag_list.keyword_filter = keyword_filter;
+            self.apply_tag_filter();
+        }
+        for search in after_filter {
+            let items = std::mem::take(&mut self.entry_table.entry_table_items);
+            self.entry_table.entry_table_items = BibiSearch::search_entry_list(search, items);
+        }
+
+        // Sort by the former column, even if another one is selected now
+        let table = &mut self.entry_table;
+        table.entry_table_selected_column = old_table.entry_table_sorted_by_col.clone();
+        table.entry_table_sorted_by_col = old_table.entry_table_sorted_by_col;
+        table.entry_table_reversed_sort = old_table.entry_table_reversed_sort;
+        match (&self.current_area, &self.former_area) {
+            // A search which is still being typed is applied to the new list
+            (CurrentArea::SearchArea, Some(FormerArea::EntryArea)) => {
+                table.entry_table_at_search_start = table.entry_table_items.clone();
+                self.search_entries();
+            }
+            (CurrentArea::SearchArea, Some(FormerArea::TagArea)) => {
+                table.sort_entry_table(false);
+                self.tag_list.tag_list_at_search_start = self.tag_list.tag_list_items.clone();
+                self.search_tags();
+            }
+            _ => table.sort_entry_table(false),
+        }
+
+        let table = &mut self.entry_table;
+        table.entry_table_selected_column = old_table.entry_table_selected_column;
+        table.entry_table_state = old_table.entry_table_state;
+        table.entry_info_scroll = old_table.entry_info_scroll;
+        table.marked = old_table.marked;
+        table.visual_anchor = old_table.visual_anchor;
+        let old_idx = table.entry_table_state.selected().unwrap_or(0);
+        table.select_nearby(&old_citekeys, old_idx);
+
+        // The tag list is only selected in the tag area
+        if let Some(idx) = old_tag_idx {
+            let items = &self.tag_list.tag_list_items;
+            let idx = old_tag
+                .and_then(|tag| items.iter().position(|t| *t == tag))
+                .unwrap_or(idx.min(items.len().saturating_sub(1)));
+            self.tag_list.tag_list_state.select(Some(idx));
+            self.tag_list.tag_scroll_state = self.tag_list.tag_scroll_state.position(idx);
+        }
+    }
+
+    /// Index the PDFs of the entries in the background. The index is
+    /// updated whenever the bibfiles are loaded again
+    pub fn start_fulltext_index(&mut self, sender: UnboundedSender<Event>) {
+        let Some(cache) = fulltext::default_cache_path() else {
+            return;
+        };
+        let indexer = FulltextIndexer::new(cache, move |texts| {
+            let _ = sender.send(Event::FulltextIndexed(texts));
+        });
+        indexer.update(&self.main_biblio.entry_list);
+        self.fulltext_indexer = Some(indexer);
+    }
+
+    /// Attach the indexed texts to the entries. The lists are changed in
+    /// place, thus, open popups still refer to the right entries
+    pub fn set_fulltext(&mut self, texts: EntryTexts) {
+        for entry in self.main_biblio.entry_list.iter_mut() {
+            entry.fulltext = texts.get(&entry.citekey).cloned();
+        }
+        let table = &mut self.entry_table;
+        for item in table
+            .entry_table_items
+            .iter_mut()
+            .chain(table.entry_table_at_search_start.iter_mut())
+            .chain(table.entry_table_at_filter_start.iter_mut())
+        {
+            item.fulltext = texts.get(&item.citekey).cloned();
+        }
     }
 
     /// Toggle moveable list between entries and tags
@@ -185,14 +568,19 @@ impl Bibiman {
         }
     }
 
-    pub fn reset_current_list(&mut self) {
-        self.entry_table = EntryTable::new(&self.main_biblio.entry_list);
+    pub fn reset_current_list(&mut self, cfg: &BibiConfig) {
+        let marked = std::mem::take(&mut self.entry_table.marked);
+        self.entry_table = EntryTable::new(&self.main_biblio.entry_list, &cfg.general.sort_column);
+        self.entry_table.marked = marked;
         self.tag_list = TagList::new(self.main_biblio.keyword_list.clone());
         if let CurrentArea::TagArea = self.current_area {
             self.tag_list.tag_list_state.select(Some(0))
         }
         self.entry_table.entry_table_at_search_start.clear();
         self.search_struct.filtered_tag_list.clear();
+        self.search_struct.fulltext_phrases.clear();
+        self.search_struct.applied_searches.clear();
+        self.search_struct.filter_start = 0;
         self.search_struct.inner_search = false;
         self.former_area = None
     }
@@ -204,6 +592,110 @@ impl Bibiman {
         clipboard.set_text(yanked_text).unwrap();
     }
 
+    /// Mark or unmark the selected entry and move on to the next one
+    pub fn toggle_mark(&mut self) {
+        self.entry_table.toggle_mark();
+        self.select_next_entry(1);
+    }
+
+    // Bibfile the entry was read from. Entries of YAML files can't be edited
+    // in place. If the source is unknown, the first .bib file containing the
+    // citekey is used
+    fn bibfile_of_entry(&self, args: &CLIArgs, citekey: &str) -> Option<PathBuf> {
+        let source = self
+            .main_biblio
+            .entry_list
+            .iter()
+            .find(|e| e.citekey == citekey)
+            .and_then(|e| e.source.as_ref());
+        match source {
+            Some(source) => Some(source.file.clone()).filter(|f| !bibyaml::is_yaml(f)),
+            None => bibedit::bibfile_of_entry(&args.files, citekey),
+        }
+    }
+
+    /// Citekeys of the marked entries. If no entry is marked, the citekey
+    /// of the selected entry
+    pub fn selected_citekeys(&self) -> Vec<String> {
+        if self.entry_table.entry_table_items.is_empty() {
+            return Vec::new();
+        }
+        let marked = self.entry_table.marked_citekeys();
+        if marked.is_empty() {
+            vec![self.get_selected_citekey().to_string()]
+        } else {
+            marked
+        }
+    }
+
+    /// Yank the citekeys of the marked or selected entries separated by
+    /// commas, thus, they can be pasted into a `\cite{}` command
+    pub fn yank_citekeys(&mut self) {
+        let citekeys = self.selected_citekeys();
+        if citekeys.is_empty() {
+            return;
+        }
+        let text = citekeys.join(",");
+        Self::yank_text(&text);
+        if citekeys.len() == 1 {
+            self.popup_area
+                .popup_message("Yanked citekey to clipboard: ", &text, true);
+        } else {
+            self.popup_area.popup_message(
+                &format!("Yanked {} citekeys to clipboard: ", citekeys.len()),
+                &text,
+                true,
+            );
+        }
+    }
+
+    /// Formatted reference of the selected entry, or an error message if
+    /// it couldn't be rendered. `None` if no entry is listed
+    pub fn selected_reference(&mut self) -> Option<Result<String, String>> {
+        if self.entry_table.entry_table_items.is_empty() {
+            return None;
+        }
+        let citekey = self.get_selected_citekey().to_string();
+        Some(
+            self.citation_preview
+                .reference(&self.main_biblio.bibliography, &citekey),
+        )
+    }
+
+    /// Yank the formatted references of the marked or selected entries,
+    /// one per line
+    pub fn yank_reference(&mut self) {
+        let citekeys = self.selected_citekeys();
+        if citekeys.is_empty() {
+            return;
+        }
+        let references: Result<Vec<String>, String> = citekeys
+            .iter()
+            .map(|c| {
+                self.citation_preview
+                    .reference(&self.main_biblio.bibliography, c)
+            })
+            .collect();
+        match references {
+            Ok(references) => {
+                let text = references.join("\n");
+                Self::yank_text(&text);
+                self.popup_area.popup_message(
+                    if references.len() == 1 {
+                        "Yanked formatted reference to clipboard: "
+                    } else {
+                        "Yanked formatted references to clipboard: "
+                    },
+                    &text,
+                    true,
+                );
+            }
+            Err(e) => self
+                .popup_area
+                .popup_message("Formatting reference failed: ", &e, false),
+        }
+    }
+
     pub fn scroll_info_down(&mut self) {
         self.entry_table.entry_info_scroll = self.entry_table.entry_info_scroll.saturating_add(1);
         self.entry_table.entry_info_scroll_state = self
@@ -336,52 +828,30 @@ impl Bibiman {
         }
     }
 
-    pub fn run_editor(&mut self, args: &CLIArgs, tui: &mut Tui) -> Result<()> {
-        // get filecontent and citekey for calculating line number
-        let citekey: &str = &self.entry_table.entry_table_items
-            [self.entry_table.entry_table_state.selected().unwrap()]
-        .citekey
-        .clone();
-
-        // Add curly brace as prefix and comma as suffix that only
-        // main citekeys are matched, not other fields like crossref
-        let citekey_pattern: String = format!("{{{},", citekey);
-
-        // Check if multiple files were passed to bibiman and
-        // return the correct file path
-        let filepath = if args.files.len() == 1 {
-            args.files.first().unwrap().as_os_str()
-        } else {
-            let mut idx = 0;
-            for f in &args.files {
-                if search::search_pattern_in_file(&citekey_pattern, &f).is_some() {
-                    break;
-                }
-                idx += 1;
-            }
-            args.files[idx].as_os_str()
+    pub fn run_editor(&mut self, args: &CLIArgs, cfg: &BibiConfig, tui: &mut Tui) -> Result<()> {
+        let Some(entry) = self
+            .entry_table
+            .entry_table_state
+            .selected()
+            .and_then(|idx| self.entry_table.entry_table_items.get(idx))
+        else {
+            return Ok(());
         };
-        let filecontent = fs::read_to_string(&filepath).unwrap();
-
-        // Search the line number to place the cursor at
-        let mut line_count = 0;
-
-        for line in filecontent.lines() {
-            line_count += 1;
-            // if reaching the citekey break the loop
-            // if reaching end of lines without match, reset to 0
-            if line.contains(&citekey_pattern) {
-                break;
-            } else if line_count == filecontent.len() {
-                eprintln!(
-                    "Citekey {} not found, opening file {} at line 1",
-                    citekey,
-                    filepath.to_string_lossy()
+
+        // Open the file the entry was read from at the line it starts. If
+        // the source is unknown, open the first file at its beginning
+        let (filepath, line_count) = match (&entry.source, args.files.first()) {
+            (Some(source), _) => (source.file.clone(), source.line),
+            (None, Some(file)) => (file.clone(), 1),
+            (None, None) => {
+                self.popup_area.popup_message(
+                    "No bibfile to open for entry: ",
+                    &entry.citekey,
+                    false,
                 );
-                line_count = 0;
-                break;
+                return Ok(());
             }
-        }
+        };
 
         // Exit TUI to enter editor
         tui.exit()?;
@@ -392,49 +862,895 @@ impl Bibiman {
             .build()
             .unwrap();
         // Prepare arguments to open file at specific line
-        let status = cmd.arg(format!("+{}", line_count)).arg(filepath).status()?;
-        if !status.success() {
-            eprintln!("Spawning editor failed with status {}", status);
-        }
+        let status = cmd.arg(format!("+{}", line_count)).arg(&filepath).status();
 
         // Enter TUI again
         tui.enter()?;
         tui.terminal.clear()?;
 
-        // Update the database and the lists to show changes
-        Self::update_lists(self, args);
+        // Update the database and the lists to show changes. The edited
+        // entry stays selected, or a nearby one if its citekey was changed
+        Self::update_lists(self, args, cfg);
+        match status {
+            Ok(status) if !status.success() => {
+                self.popup_area
+                    .popup_message("Editor exited with ", &status.to_string(), false)
+            }
+            Ok(_) => {}
+            Err(e) => {
+                self.popup_area
+                    .popup_message("Spawning editor failed: ", &e.to_string(), false)
+            }
+        }
+        Ok(())
+    }
+
+    /// Open a popup listing the fields of the selected entry which can be
+    /// edited. Common fields are offered even if the entry lacks them
+    pub fn select_field_to_edit(&mut self, args: &CLIArgs) {
+        if self.entry_table.entry_table_items.is_empty() {
+            return;
+        }
+        let citekey = self.get_selected_citekey().to_string();
+        let Some(content) = self
+            .bibfile_of_entry(args, &citekey)
+            .and_then(|file| fs::read_to_string(file).ok())
+        else {
+            self.popup_area.popup_message(
+                "Only entries of .bib files can be edited, not found: ",
+                &citekey,
+                false,
+            );
+            return;
+        };
 
-        // Search for entry, selected before editing, by matching citekeys
-        // Use earlier saved copy of citekey to match
-        let mut idx_count = 0;
-        loop {
-            if self.entry_table.entry_table_items[idx_count]
-                .citekey
-                .contains(citekey)
-            {
-                break;
+        let mut fields = bibedit::get_fields(&content, &citekey);
+        for name in EDITABLE_FIELDS {
+            if !fields.iter().any(|(f, _)| f == name) {
+                fields.push((name.to_string(), String::new()));
             }
-            idx_count += 1
         }
+        let items = fields
+            .into_iter()
+            .map(|(field, value)| format!("{}: {}", field, value.replace('\n', " ")))
+            .collect();
 
-        // Set selected entry to vec-index of match
-        self.entry_table.entry_table_state.select(Some(idx_count));
+        self.former_area = Some(FormerArea::EntryArea);
+        self.current_area = CurrentArea::PopupArea;
+        self.popup_area
+            .popup_list_selection(items, ListAction::EditField);
+    }
 
-        Ok(())
+    /// Run the action of a list selection popup for the selected item
+    pub fn confirm_list_selection(&mut self, args: &CLIArgs, cfg: &BibiConfig) {
+        let Some(PopupKind::ListSelection(action)) = self.popup_area.popup_kind.clone() else {
+            return;
+        };
+        let Some(item) = self.popup_area.selected_item().map(|i| i.to_string()) else {
+            return;
+        };
+
+        match action {
+            ListAction::EditField => {
+                let Some((field, _)) = item.split_once(": ") else {
+                    return;
+                };
+                // Take the raw value from the file, thus, LaTeX markup is kept
+                let citekey = self.get_selected_citekey();
+                let value = self
+                    .bibfile_of_entry(args, citekey)
+                    .and_then(|file| fs::read_to_string(file).ok())
+                    .and_then(|content| bibedit::get_field(&content, citekey, field))
+                    .unwrap_or_default();
+                self.popup_area
+                    .popup_input(&value, InputAction::EditField(field.to_string()));
+            }
+            ListAction::RemoveKeyword(scope) => {
+                self.close_popup();
+                let citekeys = self.keyword_scope_citekeys(scope);
+                let result = bibedit::update_keywords_in_files(&args.files, &citekeys, |k| {
+                    k.retain(|k| *k != item)
+                });
+                self.finish_keyword_update(args, cfg, result, &format!("Removed \"{}\"", item));
+            }
+            ListAction::RenameKeyword => {
+                self.popup_area
+                    .popup_input(&item, InputAction::RenameKeyword(item.clone()));
+            }
+            ListAction::DeleteEntries => {
+                // The first item confirms, the second one cancels
+                let confirmed = self.popup_area.popup_state.selected() == Some(0);
+                self.close_popup();
+                if confirmed {
+                    self.remove_selected_entries(args, cfg);
+                }
+            }
+            ListAction::MoveEntries => {
+                self.close_popup();
+                self.transfer_selected_entries(args, cfg, &item, true);
+            }
+            ListAction::CopyEntries => {
+                self.close_popup();
+                self.transfer_selected_entries(args, cfg, &item, false);
+            }
+            ListAction::NewEntryFile => {
+                self.close_popup();
+                if let Some(entry) = self.new_entry.take() {
+                    self.write_new_entry(args, cfg, &entry, &PathBuf::from(item));
+                }
+            }
+            ListAction::AttachFile(path) => {
+                // The first item adds the file, the second one replaces the files
+                let append = self.popup_area.popup_state.selected() == Some(0);
+                self.close_popup();
+                self.write_attached_file(args, cfg, &path, append);
+            }
+            ListAction::OverwriteExport(format, path) => {
+                // The first item overwrites the file, the second one cancels
+                let confirmed = self.popup_area.popup_state.selected() == Some(0);
+                self.close_popup();
+                if confirmed {
+                    self.export_listed_entries(format, &path);
+                }
+            }
+            ListAction::ImportFile => {
+                self.close_popup();
+                self.write_imported_entries(args, cfg, &PathBuf::from(item));
+            }
+            ListAction::RegenerateCitekeys => {
+                self.close_popup();
+                self.rename_citekeys(args, cfg);
+            }
+            ListAction::Diagnostics => self.close_popup(),
+            ListAction::Lint => {
+                let Some(issue) = self
+                    .popup_area
+                    .popup_state
+                    .selected()
+                    .and_then(|idx| self.lint_issues.get(idx))
+                else {
+                    return;
+                };
+                let citekey = issue.citekey.clone();
+                self.close_popup();
+                // Show all entries if the filter hides the one with the issue
+                if !self
+                    .entry_table
+                    .entry_table_items
+                    .iter()
+                    .any(|e| e.citekey == citekey)
+                {
+                    self.reset_current_list(cfg);
+                }
+                self.select_entry_by_citekey(&citekey);
+            }
+            ListAction::Duplicates => {
+                let Some(group) = self
+                    .popup_area
+                    .popup_state
+                    .selected()
+                    .and_then(|idx| self.duplicates.get(idx))
+                else {
+                    return;
+                };
+                let merge = Merge::new(group.clone());
+                let labels = merge.group.entries.iter().map(|e| e.label()).collect();
+                self.merge = Some(merge);
+                self.popup_area
+                    .popup_list_selection(labels, ListAction::MergeBase);
+            }
+            ListAction::MergeBase => {
+                let idx = self.popup_area.popup_state.selected().unwrap_or(0);
+                if let Some(merge) = &mut self.merge {
+                    merge.set_base(idx);
+                }
+                self.next_merge_step(args, cfg);
+            }
+            ListAction::MergeField(_) => {
+                let idx = self.popup_area.popup_state.selected().unwrap_or(0);
+                if let Some(merge) = &mut self.merge {
+                    merge.choose(idx);
+                }
+                self.next_merge_step(args, cfg);
+            }
+            ListAction::Export => {
+                let Some(format) = ExportFormat::from_name(&item) else {
+                    return;
+                };
+                self.popup_area.popup_input(
+                    &format!("bibiman-export.{}", format.extension()),
+                    InputAction::Export(format),
+                );
+            }
+        }
+    }
+
+    /// Run the action of an input popup with the entered value
+    pub fn confirm_popup_input(&mut self, args: &CLIArgs, cfg: &BibiConfig) {
+        let Some(PopupKind::Input(action)) = self.popup_area.popup_kind.clone() else {
+            return;
+        };
+        let value = self.popup_area.popup_input.value().trim().to_string();
+        self.close_popup();
+
+        match action {
+            InputAction::EditField(field) => self.write_edited_field(args, cfg, &field, &value),
+            InputAction::AddKeyword(scope) => {
+                let citekeys = self.keyword_scope_citekeys(scope);
+                let new_keywords = bibedit::split_keywords(&value);
+                let result = bibedit::update_keywords_in_files(&args.files, &citekeys, |k| {
+                    k.extend(new_keywords.iter().cloned())
+                });
+                self.finish_keyword_update(args, cfg, result, &format!("Added \"{}\"", value));
+            }
+            InputAction::RenameKeyword(old) if !value.is_empty() && value != old => {
+                // All entries are affected, regardless of any filter
+                let citekeys = self.main_biblio.citekeys.clone();
+                let result = bibedit::update_keywords_in_files(&args.files, &citekeys, |k| {
+                    k.iter_mut()
+                        .filter(|k| **k == old)
+                        .for_each(|k| *k = value.clone())
+                });
+                self.finish_keyword_update(
+                    args,
+                    cfg,
+                    result,
+                    &format!("Renamed \"{}\" to \"{}\"", old, value),
+                );
+            }
+            InputAction::RenameKeyword(_) => {}
+            InputAction::AttachFile if value.is_empty() => {}
+            InputAction::AttachFile => self.confirm_attached_file(args, cfg, &value),
+            InputAction::Export(_) if value.is_empty() => {}
+            InputAction::Export(format) => self.confirm_export(args, format, &value),
+            InputAction::NewEntryCitekey => {
+                let Some(mut entry) = self.new_entry.take() else {
+                    return;
+                };
+                entry.citekey = value;
+                if citekey_problem(&entry.citekey, &self.main_biblio).is_some() {
+                    // Ask again, the problem is listed above the input
+                    self.review_new_entry(entry);
+                } else if args.files.len() > 1 {
+                    let files = args
+                        .files
+                        .iter()
+                        .map(|f| f.to_string_lossy().to_string())
+                        .collect();
+                    self.new_entry = Some(entry);
+                    self.former_area = Some(FormerArea::EntryArea);
+                    self.current_area = CurrentArea::PopupArea;
+                    self.popup_area
+                        .popup_list_selection(files, ListAction::NewEntryFile);
+                } else if let Some(file) = args.files.first() {
+                    self.write_new_entry(args, cfg, &entry, file);
+                }
+            }
+        }
+    }
+
+    /// Open a list of the formats the listed entries can be exported to
+    pub fn select_export_format(&mut self) {
+        if self.entry_table.entry_table_items.is_empty() {
+            return;
+        }
+        let formats = ExportFormat::ALL
+            .iter()
+            .map(|f| f.name().to_string())
+            .collect();
+        self.former_area = Some(FormerArea::EntryArea);
+        self.current_area = CurrentArea::PopupArea;
+        self.popup_area
+            .popup_list_selection(formats, ListAction::Export);
+    }
+
+    /// Export to the file, asking before an existing file is overwritten
+    fn confirm_export(&mut self, args: &CLIArgs, format: ExportFormat, path: &str) {
+        let target = expand_home(&PathBuf::from(path));
+        // Never overwrite one of the opened bibfiles
+        let canonical = fs::canonicalize(&target).ok();
+        if canonical.is_some()
+            && args
+                .files
+                .iter()
+                .any(|f| fs::canonicalize(f).ok() == canonical)
+        {
+            self.popup_area.popup_message(
+                "Export failed: ",
+                "Can't overwrite an opened bibfile",
+                false,
+            );
+        } else if target.exists() {
+            self.former_area = Some(FormerArea::EntryArea);
+            self.current_area = CurrentArea::PopupArea;
+            self.popup_area.popup_list_selection(
+                vec![
+                    format!("Overwrite {}", target.to_string_lossy()),
+                    "Cancel".to_string(),
+                ],
+                ListAction::OverwriteExport(format, path.to_string()),
+            );
+        } else {
+            self.export_listed_entries(format, path);
+        }
+    }
+
+    /// Write the marked entries to the file. If no entry is marked, all
+    /// entries currently listed in the entry table are written
+    fn export_listed_entries(&mut self, format: ExportFormat, path: &str) {
+        let path = expand_home(&PathBuf::from(path));
+        let mut citekeys = self.entry_table.marked_citekeys();
+        if citekeys.is_empty() {
+            citekeys = self
+                .entry_table
+                .entry_table_items
+                .iter()
+                .map(|e| e.citekey.clone())
+                .collect();
+        }
+
+        let result = export::export_entries(&self.main_biblio.bibliography, &citekeys, format)
+            .and_then(|content| Ok(fs::write(&path, content)?));
+
+        match result {
+            Ok(()) => self.popup_area.popup_message(
+                &format!("Exported {} entries to: ", citekeys.len()),
+                &path.to_string_lossy(),
+                true,
+            ),
+            Err(e) => self
+                .popup_area
+                .popup_message("Export failed: ", &e.to_string(), false),
+        }
+    }
+
+    /// Write a new value of a field back into the bibfile of the selected
+    /// entry. An empty value removes the field
+    fn write_edited_field(&mut self, args: &CLIArgs, cfg: &BibiConfig, field: &str, value: &str) {
+        let citekey = self.get_selected_citekey().to_string();
+        let result = match self.bibfile_of_entry(args, &citekey) {
+            Some(file) => bibedit::set_field_in_file(&file, &citekey, field, value),
+            None => Err(color_eyre::eyre::eyre!("Couldn't find bibfile of entry")),
+        };
+
+        match result {
+            Ok(()) => {
+                self.update_lists(args, cfg);
+                self.select_entry_by_citekey(&citekey);
+                self.popup_area.popup_message(
+                    &format!("Updated field \"{}\" of entry: ", field),
+                    &citekey,
+                    true,
+                );
+            }
+            Err(e) => self.popup_area.popup_message(
+                &format!("Editing field \"{}\" failed: ", field),
+                &e.to_string(),
+                false,
+            ),
+        }
+    }
+
+    /// Ask for the path of the file attached to the selected entry. The
+    /// input starts in the directory of its first file or the home dir
+    pub fn select_file_to_attach(&mut self, args: &CLIArgs) {
+        if self.entry_table.entry_table_items.is_empty() {
+            return;
+        }
+        let citekey = self.get_selected_citekey().to_string();
+        if self.bibfile_of_entry(args, &citekey).is_none() {
+            self.popup_area.popup_message(
+                "Only entries of .bib files can be edited, not found: ",
+                &citekey,
+                false,
+            );
+            return;
+        }
+        let dir = self
+            .main_biblio
+            .bibliography
+            .get(&citekey)
+            .and_then(|entry| entry.file().ok())
+            .and_then(|value| {
+                attach::split_files(&value)
+                    .first()
+                    .and_then(|file| Path::new(file).parent())
+                    .map(|dir| dir.to_string_lossy().to_string())
+            })
+            .filter(|dir| !dir.is_empty())
+            .map_or_else(|| String::from("~/"), |dir| format!("{}/", dir));
+
+        self.former_area = Some(FormerArea::EntryArea);
+        self.current_area = CurrentArea::PopupArea;
+        self.popup_area.popup_input(&dir, InputAction::AttachFile);
+    }
+
+    /// Complete the path in the attach file input. Matching files are
+    /// listed above the input
+    pub fn complete_attach_path(&mut self) {
+        let (completed, names) = attach::complete_path(self.popup_area.popup_input.value());
+        self.popup_area.popup_input = Input::new(completed);
+        let mut info: Vec<String> = names.iter().take(10).cloned().collect();
+        if names.len() > info.len() {
+            info.push(format!("… {} more", names.len() - info.len()));
+        }
+        self.popup_area.popup_input_info = info;
+    }
+
+    // Ask whether to replace the files of the entry or to add the new one
+    fn confirm_attached_file(&mut self, args: &CLIArgs, cfg: &BibiConfig, path: &str) {
+        if !expand_home(&PathBuf::from(path)).is_file() {
+            self.popup_area
+                .popup_message("File doesn't exist: ", path, false);
+            return;
+        }
+        let citekey = self.get_selected_citekey();
+        let files = self
+            .main_biblio
+            .bibliography
+            .get(citekey)
+            .and_then(|entry| entry.file().ok())
+            .unwrap_or_default();
+        if attach::split_files(&files).is_empty() {
+            self.write_attached_file(args, cfg, path, false);
+        } else {
+            self.former_area = Some(FormerArea::EntryArea);
+            self.current_area = CurrentArea::PopupArea;
+            self.popup_area.popup_list_selection(
+                vec![
+                    "Add to the files of the entry".to_string(),
+                    "Replace the files of the entry".to_string(),
+                ],
+                ListAction::AttachFile(path.to_string()),
+            );
+        }
+    }
+
+    fn write_attached_file(&mut self, args: &CLIArgs, cfg: &BibiConfig, path: &str, append: bool) {
+        let citekey = self.get_selected_citekey().to_string();
+        match self.attach_file(args, cfg, &citekey, path, append) {
+            Ok(file) => {
+                self.update_lists(args, cfg);
+                self.select_entry_by_citekey(&citekey);
+                self.popup_area.popup_message(
+                    &format!("Attached file to entry {}: ", citekey),
+                    &file,
+                    true,
+                );
+            }
+            Err(e) => {
+                self.popup_area
+                    .popup_message("Attaching file failed: ", &e.to_string(), false)
+            }
+        }
+    }
+
+    // Store the file in the library directory, if one is set, and update the
+    // file field. Returns the path written to the field
+    fn attach_file(
+        &self,
+        args: &CLIArgs,
+        cfg: &BibiConfig,
+        citekey: &str,
+        path: &str,
+        append: bool,
+    ) -> Result<String> {
+        let bibfile = self
+            .bibfile_of_entry(args, citekey)
+            .ok_or_else(|| color_eyre::eyre::eyre!("Couldn't find bibfile of entry"))?;
+        let source = expand_home(&PathBuf::from(path));
+        let file = match (&cfg.library.dir, self.main_biblio.bibliography.get(citekey)) {
+            (Some(dir), Some(entry)) => {
+                let target = attach::library_path(dir, entry, &cfg.library.pattern, &source);
+                attach::store_file(&source, &target, cfg.library.move_files)?;
+                target.to_string_lossy().to_string()
+            }
+            // Relative paths would depend on the working directory
+            _ if Path::new(path).is_relative() && !path.starts_with('~') => {
+                fs::canonicalize(&source)?.to_string_lossy().to_string()
+            }
+            _ => path.to_string(),
+        };
+        let content = fs::read_to_string(&bibfile)?;
+        let files = bibedit::get_field(&content, citekey, "file").unwrap_or_default();
+        bibedit::set_field_in_file(
+            &bibfile,
+            citekey,
+            "file",
+            &attach::add_file(&files, &file, append),
+        )?;
+        Ok(file)
+    }
+
+    /// Ask for confirmation before deleting the marked or selected entries
+    pub fn delete_entries(&mut self) {
+        let citekeys = self.selected_citekeys();
+        let what = match citekeys.as_slice() {
+            [] => return,
+            [citekey] => format!("Delete entry {}", citekey),
+            _ => format!("Delete {} marked entries", citekeys.len()),
+        };
+        self.former_area = Some(FormerArea::EntryArea);
+        self.current_area = CurrentArea::PopupArea;
+        self.popup_area
+            .popup_list_selection(vec![what, "Cancel".to_string()], ListAction::DeleteEntries);
+    }
+
+    /// Open a list of the opened bibfiles the marked or selected entries
+    /// can be moved or copied to
+    pub fn select_target_file(&mut self, args: &CLIArgs, action: ListAction) {
+        if self.entry_table.entry_table_items.is_empty() {
+            return;
+        }
+        if args.files.len() < 2 {
+            self.popup_area
+                .popup_message("Only one bibfile is opened", "", false);
+            return;
+        }
+        let files = args
+            .files
+            .iter()
+            .map(|f| f.to_string_lossy().to_string())
+            .collect();
+        self.former_area = Some(FormerArea::EntryArea);
+        self.current_area = CurrentArea::PopupArea;
+        self.popup_area.popup_list_selection(files, action);
+    }
+
+    fn remove_selected_entries(&mut self, args: &CLIArgs, cfg: &BibiConfig) {
+        let citekeys = self.selected_citekeys();
+        // Select the next entry which isn't deleted afterwards
+        let idx = self
+            .entry_table
+            .entry_table_state
+            .selected()
+            .unwrap_or(0)
+            .min(self.entry_table.entry_table_items.len());
+        let next = self.entry_table.entry_table_items[idx..]
+            .iter()
+            .chain(self.entry_table.entry_table_items[..idx].iter().rev())
+            .find(|e| !citekeys.contains(&e.citekey))
+            .map(|e| e.citekey.clone());
+
+        let entries: Vec<(String, PathBuf)> = citekeys
+            .iter()
+            .filter_map(|key| Some((key.clone(), self.bibfile_of_entry(args, key)?)))
+            .collect();
+        match bibedit::remove_entries_from_files(&entries) {
+            Ok(removed) => {
+                self.entry_table.clear_marks();
+                self.update_lists(args, cfg);
+                if let Some(citekey) = next {
+                    self.select_entry_by_citekey(&citekey);
+                }
+                if removed < citekeys.len() {
+                    self.popup_area.popup_message(
+                        "Entries of YAML files can't be deleted, number of deleted entries: ",
+                        &removed.to_string(),
+                        false,
+                    );
+                } else {
+                    self.popup_area.popup_message(
+                        "Number of deleted entries: ",
+                        &removed.to_string(),
+                        true,
+                    );
+                }
+            }
+            Err(e) => {
+                self.popup_area
+                    .popup_message("Deleting entries failed: ", &e.to_string(), false)
+            }
+        }
+    }
+
+    fn transfer_selected_entries(
+        &mut self,
+        args: &CLIArgs,
+        cfg: &BibiConfig,
+        target: &str,
+        remove: bool,
+    ) {
+        let citekeys = self.selected_citekeys();
+        let citekey = self.get_selected_citekey().to_string();
+        let verb = if remove { "Moved" } else { "Copied" };
+
+        let entries: Option<Vec<(String, PathBuf)>> = citekeys
+            .iter()
+            .map(|key| Some((key.clone(), self.bibfile_of_entry(args, key)?)))
+            .collect();
+        let result = match entries {
+            Some(entries) => bibedit::transfer_entries(&entries, &PathBuf::from(target), remove),
+            None => Err(color_eyre::eyre::eyre!(
+                "Only entries of .bib files can be moved or copied"
+            )),
+        };
+        match result {
+            Ok(0) => self
+                .popup_area
+                .popup_message("All entries already exist in: ", target, false),
+            Ok(count) => {
+                self.update_lists(args, cfg);
+                self.select_entry_by_citekey(&citekey);
+                self.popup_area.popup_message(
+                    &format!("{} {} entries to: ", verb, count),
+                    target,
+                    true,
+                );
+            }
+            Err(e) => self.popup_area.popup_message(
+                &format!(
+                    "{} entries failed: ",
+                    if remove { "Moving" } else { "Copying" }
+                ),
+                &e.to_string(),
+                false,
+            ),
+        }
+    }
+
+    /// Open a list of all groups of duplicate entries in the bibfiles
+    pub fn show_duplicates(&mut self, args: &CLIArgs) {
+        match duplicates::read_entries(&args.files) {
+            Ok(entries) => self.duplicates = duplicates::find_duplicates(entries),
+            Err(e) => {
+                self.popup_area
+                    .popup_message("Reading bibfiles failed: ", &e.to_string(), false);
+                return;
+            }
+        }
+        if self.duplicates.is_empty() {
+            self.popup_area
+                .popup_message("No duplicate entries found", "", true);
+            return;
+        }
+        let groups = self.duplicates.iter().map(|g| g.summary()).collect();
+        self.former_area = Some(FormerArea::EntryArea);
+        self.current_area = CurrentArea::PopupArea;
+        self.popup_area
+            .popup_list_selection(groups, ListAction::Duplicates);
+    }
+
+    /// Run the lint rules and list the issues
+    pub fn show_lint(&mut self, cfg: &BibiConfig) {
+        self.lint_issues = lint::lint(&self.main_biblio, &cfg.lint);
+        self.former_area = Some(FormerArea::EntryArea);
+        self.current_area = CurrentArea::PopupArea;
+        if self.lint_issues.is_empty() {
+            self.popup_area
+                .popup_message("No lint issues found", "", true);
+            return;
+        }
+        let issues = self.lint_issues.iter().map(|i| i.summary()).collect();
+        self.popup_area
+            .popup_list_selection(issues, ListAction::Lint);
+    }
+
+    /// List the citekeys of the marked or selected entries which differ
+    /// from the configured pattern. Confirming renames all of them
+    pub fn show_citekey_renames(&mut self, args: &CLIArgs, cfg: &BibiConfig) {
+        let citekeys = self.selected_citekeys();
+        if citekeys.is_empty() {
+            return;
+        }
+        // Only entries of .bib files can be renamed in place
+        let bib_keys: Vec<String> = args
+            .files
+            .iter()
+            .filter(|f| !bibyaml::is_yaml(f))
+            .filter_map(|f| fs::read_to_string(f).ok())
+            .flat_map(|content| bibedit::entries(&content).into_iter().map(|(key, _)| key))
+            .collect();
+        let citekeys: Vec<String> = citekeys
+            .into_iter()
+            .filter(|key| bib_keys.contains(key))
+            .collect();
+        self.citekey_renames =
+            citekey::regenerate_citekeys(&self.main_biblio, &citekeys, &cfg.citekey);
+        self.former_area = Some(FormerArea::EntryArea);
+        self.current_area = CurrentArea::PopupArea;
+        if self.citekey_renames.is_empty() {
+            self.popup_area
+                .popup_message("Citekeys already match the pattern", "", true);
+            return;
+        }
+        let renames = self
+            .citekey_renames
+            .iter()
+            .map(|(old, new)| format!("{} → {}", old, new))
+            .collect();
+        self.popup_area
+            .popup_list_selection(renames, ListAction::RegenerateCitekeys);
+    }
+
+    fn rename_citekeys(&mut self, args: &CLIArgs, cfg: &BibiConfig) {
+        let renames = std::mem::take(&mut self.citekey_renames);
+        match bibedit::rename_citekeys_in_files(&args.files, &renames) {
+            Ok(renamed) => {
+                // Keep the selected entry under its new citekey
+                let selected = self.get_selected_citekey().to_string();
+                let selected = renames
+                    .iter()
+                    .find(|(old, _)| *old == selected)
+                    .map_or(selected, |(_, new)| new.clone());
+                self.update_lists(args, cfg);
+                self.select_entry_by_citekey(&selected);
+                self.popup_area.popup_message(
+                    "Regenerated citekeys, number of renamed entries: ",
+                    &renamed.to_string(),
+                    true,
+                );
+            }
+            Err(e) => {
+                self.popup_area
+                    .popup_message("Renaming citekeys failed: ", &e.to_string(), false)
+            }
+        }
+    }
+
+    // Ask for the value of the next conflicting field. If all values are
+    // chosen, write the merged entry
+    fn next_merge_step(&mut self, args: &CLIArgs, cfg: &BibiConfig) {
+        let Some(merge) = &self.merge else {
+            return;
+        };
+        if let Some((field, values)) = merge.next_conflict() {
+            // Values spanning several lines are shown in one line
+            let values = values
+                .iter()
+                .map(|v| v.split_whitespace().collect::<Vec<&str>>().join(" "))
+                .collect();
+            self.popup_area
+                .popup_list_selection(values, ListAction::MergeField(field.clone()));
+            return;
+        }
+
+        let count = merge.group.entries.len();
+        let result = merge.write();
+        self.merge = None;
+        self.close_popup();
+        match result {
+            Ok(citekey) => {
+                self.update_lists(args, cfg);
+                self.select_entry_by_citekey(&citekey);
+                self.popup_area.popup_message(
+                    &format!("Merged {} entries into: ", count),
+                    &citekey,
+                    true,
+                );
+            }
+            Err(e) => {
+                self.popup_area
+                    .popup_message("Merging entries failed: ", &e.to_string(), false)
+            }
+        }
+    }
+
+    /// Open the input for new keywords. Several keywords can be separated
+    /// by commas
+    pub fn add_keyword(&mut self, scope: KeywordScope) {
+        if self.entry_table.entry_table_items.is_empty() {
+            return;
+        }
+        self.former_area = Some(FormerArea::EntryArea);
+        self.current_area = CurrentArea::PopupArea;
+        self.popup_area
+            .popup_input("", InputAction::AddKeyword(scope));
+    }
+
+    /// Open a list of the keywords set for the entries in scope
+    pub fn remove_keyword(&mut self, scope: KeywordScope) {
+        let citekeys = self.keyword_scope_citekeys(scope);
+        let mut keywords: Vec<String> = self
+            .entry_table
+            .entry_table_items
+            .iter()
+            .filter(|e| citekeys.contains(&e.citekey))
+            .flat_map(|e| bibedit::split_keywords(&e.keywords))
+            .collect();
+        keywords.sort_by_key(|k| k.to_lowercase());
+        keywords.dedup();
+
+        if keywords.is_empty() {
+            self.popup_area
+                .popup_message("No keywords set for the selected entries", "", false);
+            return;
+        }
+        self.former_area = Some(FormerArea::EntryArea);
+        self.current_area = CurrentArea::PopupArea;
+        self.popup_area
+            .popup_list_selection(keywords, ListAction::RemoveKeyword(scope));
+    }
+
+    /// Rename the keyword selected in the keyword list. If the entry list
+    /// is active, choose the keyword from a list of all keywords first
+    pub fn rename_keyword(&mut self) {
+        if let CurrentArea::TagArea = self.current_area {
+            let keyword = self.get_selected_tag().to_string();
+            self.former_area = Some(FormerArea::TagArea);
+            self.current_area = CurrentArea::PopupArea;
+            self.popup_area
+                .popup_input(&keyword, InputAction::RenameKeyword(keyword.clone()));
+        } else if !self.main_biblio.keyword_list.is_empty() {
+            self.former_area = Some(FormerArea::EntryArea);
+            self.current_area = CurrentArea::PopupArea;
+            self.popup_area.popup_list_selection(
+                self.main_biblio.keyword_list.clone(),
+                ListAction::RenameKeyword,
+            );
+        }
     }
 
-    pub fn append_to_file(&mut self, args: &CLIArgs, content: &str) -> Result<()> {
-        // Determine the file path to append to
-        let file_path = args.files.first().unwrap();
-        // Open the file in append mode
-        let mut file = OpenOptions::new().append(true).open(file_path).unwrap();
-        // Optionally, add a newline before the content
-        file.write_all(b"\n")?;
-        // Format the content
-        // Write the formatted content to the file
-        file.write_all(content.as_bytes())?;
+    fn keyword_scope_citekeys(&self, scope: KeywordScope) -> Vec<String> {
+        match scope {
+            KeywordScope::Selected => self.selected_citekeys(),
+            KeywordScope::Filtered => self
+                .entry_table
+                .entry_table_items
+                .iter()
+                .map(|e| e.citekey.clone())
+                .collect(),
+        }
+    }
+
+    fn finish_keyword_update(
+        &mut self,
+        args: &CLIArgs,
+        cfg: &BibiConfig,
+        result: Result<usize>,
+        message: &str,
+    ) {
+        match result {
+            Ok(changed) => {
+                let citekey = self.get_selected_citekey().to_string();
+                self.update_lists(args, cfg);
+                self.select_entry_by_citekey(&citekey);
+                self.popup_area.popup_message(
+                    &format!("{}, number of changed entries: ", message),
+                    &changed.to_string(),
+                    true,
+                );
+            }
+            Err(e) => {
+                self.popup_area
+                    .popup_message("Changing keywords failed: ", &e.to_string(), false)
+            }
+        }
+    }
+
+    pub fn get_selected_citekey(&self) -> &str {
+        let idx = self.entry_table.entry_table_state.selected().unwrap_or(0);
+        self.entry_table
+            .entry_table_items
+            .get(idx)
+            .map(|e| e.citekey.as_str())
+            .unwrap_or_default()
+    }
+
+    /// Select the entry with the given citekey. Keep the current selection
+    /// if it isn't part of the table anymore
+    pub fn select_entry_by_citekey(&mut self, citekey: &str) {
+        if let Some(idx) = self
+            .entry_table
+            .entry_table_items
+            .iter()
+            .position(|e| e.citekey == citekey)
+        {
+            self.entry_table.entry_table_state.select(Some(idx));
+            self.entry_table.entry_scroll_state = self.entry_table.entry_scroll_state.position(idx);
+        }
+    }
+
+    pub fn append_to_file(
+        &mut self,
+        args: &CLIArgs,
+        cfg: &BibiConfig,
+        file_path: &Path,
+        content: &str,
+    ) -> Result<()> {
+        bibedit::append_entries(file_path, content)?;
         // Update the database and the lists to reflect the new content
-        self.update_lists(args);
+        self.update_lists(args, cfg);
         Ok(())
     }
 
@@ -454,11 +1770,7 @@ impl Bibiman {
             let rest = &entry[start_brace_pos + 1..];
             // Remove the last '}' at the end, if present
             let rest = rest.trim_end();
-            let rest = if rest.ends_with('}') {
-                &rest[..rest.len() - 1]
-            } else {
-                rest
-            };
+            let rest = rest.strip_suffix('}').unwrap_or(rest);
 
             // Parse the fields, considering braces and quotes
             let mut fields = Vec::new();
@@ -494,36 +1806,6 @@ impl Bibiman {
                 fields.push(current_field.trim().to_string());
             }
 
-            // **Conditionally Clean the Citation Key**
-            if let Some(citation_key) = fields.get_mut(0) {
-                // Check if the citation key contains any non-alphanumerical characters except underscores
-                let needs_cleaning = citation_key
-                    .chars()
-                    .any(|c| !c.is_alphanumeric() && c != '_');
-                if needs_cleaning {
-                    // Retain only alphanumerical characters and underscores
-                    let cleaned_key: String = citation_key
-                        .chars()
-                        .filter(|c| c.is_alphanumeric() || *c == '_')
-                        .collect();
-                    // If the cleaned key is longer than 14 characters, retain only the last 14
-                    let limited_key = if cleaned_key.len() > 14 {
-                        cleaned_key
-                            .chars()
-                            .rev()
-                            .take(14)
-                            .collect::<String>()
-                            .chars()
-                            .rev()
-                            .collect()
-                    } else {
-                        cleaned_key
-                    };
-                    // Replace the original citation key with the cleaned and possibly limited key
-                    *citation_key = limited_key;
-                }
-            }
-
             // Add the new 'file' field
             let file_field = format!("file = {{{}}}", file_path);
             fields.push(file_field);
@@ -550,6 +1832,8 @@ impl Bibiman {
         // Use snapshot of entry list saved when starting the search
         // so deleting a char, will show former entries too
         let orig_list = self.entry_table.entry_table_at_search_start.clone();
+        self.search_struct.fulltext_phrases =
+            Query::parse(&self.search_struct.search_string).fulltext_phrases();
         let filtered_list =
             BibiSearch::search_entry_list(&self.search_struct.search_string, orig_list.clone());
         self.entry_table.entry_table_items = filtered_list;
@@ -615,11 +1899,15 @@ impl Bibiman {
     }
 
     pub fn filter_tags_by_entries(&mut self) {
-        let mut filtered_keywords: Vec<String> = Vec::new();
+        let filtered_keywords = Self::keywords_of_entries(&self.entry_table.entry_table_items);
+        self.set_tag_list_items(filtered_keywords);
+    }
 
-        let orig_list = &self.entry_table.entry_table_items;
+    // Sorted list of all keywords set for the passed entries
+    fn keywords_of_entries(entries: &[EntryTableItem]) -> Vec<String> {
+        let mut keywords: Vec<String> = Vec::new();
 
-        for e in orig_list {
+        for e in entries {
             if !e.keywords.is_empty() {
                 let mut key_vec: Vec<String> = e
                     .keywords
@@ -627,15 +1915,18 @@ impl Bibiman {
                     .map(|s| s.trim().to_string())
                     .filter(|s| !s.is_empty())
                     .collect();
-                filtered_keywords.append(&mut key_vec);
+                keywords.append(&mut key_vec);
             }
         }
 
-        filtered_keywords.sort_by_key(|a| a.to_lowercase());
-        filtered_keywords.dedup();
+        keywords.sort_by_key(|a| a.to_lowercase());
+        keywords.dedup();
+        keywords
+    }
 
-        self.search_struct.filtered_tag_list = filtered_keywords.clone();
-        self.tag_list.tag_list_items = filtered_keywords;
+    fn set_tag_list_items(&mut self, keywords: Vec<String>) {
+        self.search_struct.filtered_tag_list = keywords.clone();
+        self.tag_list.tag_list_items = keywords;
         self.tag_list.tag_scroll_state = ScrollbarState::content_length(
             self.tag_list.tag_scroll_state,
             self.tag_list.tag_list_items.len(),
@@ -646,21 +1937,81 @@ impl Bibiman {
     // If already inside a filtered tag or entry list, apply the filtering
     // to the already filtered list only
     pub fn filter_for_tags(&mut self) {
-        let orig_list = &self.entry_table.entry_table_items;
-        let keyword = self.get_selected_tag();
-        let filtered_list = BibiSearch::filter_entries_by_tag(keyword, orig_list);
-        // self.tag_list.selected_keyword = keyword.to_string();
-        self.tag_list.selected_keywords.push(keyword.to_string());
-        self.entry_table.entry_table_items = filtered_list;
+        if !self.add_tag_filter(FilterOp::And) {
+            return;
+        }
+        self.toggle_area();
+        self.entry_table.entry_table_state.select(Some(0));
+        self.former_area = Some(FormerArea::TagArea);
+    }
+
+    /// Add the selected keyword to the filter chain. Returns false if no
+    /// keyword is selected or it is already part of the chain
+    pub fn add_tag_filter(&mut self, op: FilterOp) -> bool {
+        let Some(keyword) = self
+            .tag_list
+            .tag_list_state
+            .selected()
+            .and_then(|idx| self.tag_list.tag_list_items.get(idx))
+            .cloned()
+        else {
+            return false;
+        };
+        if self
+            .tag_list
+            .keyword_filter
+            .iter()
+            .any(|f| f.keyword == keyword)
+        {
+            return false;
+        }
+
+        // The whole chain is always applied to the list from before the
+        // first keyword filter, otherwise OR couldn't extend the list
+        if self.tag_list.keyword_filter.is_empty() {
+            self.entry_table.entry_table_at_filter_start =
+                self.entry_table.entry_table_items.clone();
+            self.search_struct.filter_start = self.search_struct.applied_searches.len();
+        }
+        self.tag_list
+            .keyword_filter
+            .push(KeywordFilter::new(op, &keyword));
+        self.apply_tag_filter();
+        true
+    }
+
+    /// Remove the last keyword of the filter chain
+    pub fn remove_last_tag_filter(&mut self) {
+        if self.tag_list.keyword_filter.pop().is_some() {
+            self.apply_tag_filter();
+        }
+    }
+
+    fn apply_tag_filter(&mut self) {
+        let orig_list = &self.entry_table.entry_table_at_filter_start;
+        // All keywords of the unfiltered list stay selectable
+        let keywords = Self::keywords_of_entries(orig_list);
+        self.entry_table.entry_table_items =
+            BibiSearch::filter_entries_by_tags(&self.tag_list.keyword_filter, orig_list);
+        if self.tag_list.keyword_filter.is_empty() {
+            self.entry_table.entry_table_at_filter_start.clear();
+            // Searches made after filtering are undone too
+            self.search_struct
+                .applied_searches
+                .truncate(self.search_struct.filter_start);
+        }
         // Update scrollbar state with new lenght of itemlist
         self.entry_table.entry_scroll_state = ScrollbarState::content_length(
             self.entry_table.entry_scroll_state,
             self.entry_table.entry_table_items.len(),
         );
-        self.filter_tags_by_entries();
-        self.toggle_area();
         self.entry_table.entry_table_state.select(Some(0));
-        self.former_area = Some(FormerArea::TagArea);
+
+        self.set_tag_list_items(keywords);
+        if let Some(idx) = self.tag_list.tag_list_state.selected() {
+            let last = self.tag_list.tag_list_items.len().saturating_sub(1);
+            self.tag_list.tag_list_state.select(Some(idx.min(last)));
+        }
     }
 }
 
@@ -689,6 +2040,9 @@ impl Bibiman {
             self.current_area = CurrentArea::EntryArea;
             self.entry_table.entry_table_state.select(Some(0));
             self.entry_table.entry_table_at_search_start.clear();
+            self.search_struct
+                .applied_searches
+                .push(self.search_struct.search_string.clone());
         } else if let Some(FormerArea::TagArea) = self.former_area {
             self.current_area = CurrentArea::TagArea;
             self.tag_list.tag_list_state.select(Some(0));
@@ -699,7 +2053,7 @@ impl Bibiman {
     }
 
     // Break search: leave search area without filtering list
-    pub fn break_search(&mut self) {
+    pub fn break_search(&mut self, cfg: &BibiConfig) {
         if let Some(FormerArea::EntryArea) = self.former_area {
             self.current_area = CurrentArea::EntryArea;
             self.entry_table.entry_table_state.select(Some(0));
@@ -711,7 +2065,7 @@ impl Bibiman {
         }
         // But keep filtering by tag if applied before entering search area
         if !self.search_struct.inner_search {
-            self.reset_current_list();
+            self.reset_current_list(cfg);
         }
         self.former_area = None;
         // If search is canceled, reset default status of struct
@@ -753,7 +2107,8 @@ impl Bibiman {
 
 #[cfg(test)]
 mod tests {
-    // use super::*;
+    use super::*;
+    use std::fs;
 
     #[test]
     fn citekey_pattern() {
@@ -761,4 +2116,64 @@ mod tests {
 
         assert_eq!(citekey, "{a_key_2001,")
     }
+
+    #[test]
+    fn keep_view_on_reload() {
+        let entry = |key: &str, author: &str, year: u16, keyword: &str| {
+            format!(
+                "@misc{{{},\n  author = {{{}}},\n  title = {{Title}},\n  year = {{{}}},\n  keywords = {{{}}},\n}}\n\n",
+                key, author, year, keyword
+            )
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("reload.bib");
+        fs::write(
+            &file,
+            entry("a", "Adams", 2001, "x")
+                + &entry("b", "Baker", 2002, "x")
+                + &entry("c", "Clark", 2003, "x")
+                + &entry("d", "Doe", 2004, "y"),
+        )
+        .unwrap();
+        let args = CLIArgs {
+            files: vec![file.clone()],
+            ..Default::default()
+        };
+        let cfg = BibiConfig::default();
+        let mut bibiman = Bibiman::new(&args, &cfg).unwrap();
+        let citekeys = |bibiman: &Bibiman| -> Vec<String> {
+            bibiman
+                .entry_table
+                .entry_table_items
+                .iter()
+                .map(|e| e.citekey.clone())
+                .collect()
+        };
+
+        bibiman.enter_search_area();
+        bibiman.search_struct.search_string = "kw:x".to_string();
+        bibiman.search_entries();
+        bibiman.confirm_search();
+        bibiman.entry_table.entry_table_selected_column = EntryTableColumn::Year;
+        bibiman.entry_table.sort_entry_table(false);
+        bibiman.entry_table.sort_entry_table(true);
+        bibiman.select_entry_by_citekey("b");
+        assert_eq!(citekeys(&bibiman), vec!["c", "b", "a"]);
+
+        // The selected entry is renamed and a new one matching the search
+        // is added
+        fs::write(
+            &file,
+            entry("a", "Adams", 2001, "x")
+                + &entry("b2", "Baker", 2002, "x")
+                + &entry("c", "Clark", 2003, "x")
+                + &entry("d", "Doe", 2004, "y")
+                + &entry("e", "Evans", 2005, "x"),
+        )
+        .unwrap();
+        bibiman.update_lists(&args, &cfg);
+        assert_eq!(citekeys(&bibiman), vec!["e", "c", "b2", "a"]);
+        assert!(bibiman.entry_table.entry_table_reversed_sort);
+        assert_eq!(bibiman.get_selected_citekey(), "a");
+    }
 }
@@ -0,0 +1,189 @@
+// bibiman - a TUI for managing BibLaTeX databases
+// Copyright (C) 2024  lukeflo
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+/////
+
+//! Files connected to entries via the `file` field.
+//!
+//! Several files are separated by `;`. Attached files can be copied or moved
+//! into a library directory and renamed after a pattern.
+
+use biblatex::Entry;
+use color_eyre::eyre::{eyre, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::citekey::{format_filename, unique_citekey};
+use crate::app::expand_home;
+
+pub const SEPARATOR: char = ';';
+
+/// Paths listed in the value of a `file` field
+pub fn split_files(value: &str) -> Vec<&str> {
+    value
+        .split(SEPARATOR)
+        .map(str::trim)
+        .filter(|f| !f.is_empty())
+        .collect()
+}
+
+/// Whether the file exists. `~` is expanded like when opening the file
+pub fn file_exists(file: &str) -> bool {
+    expand_home(&PathBuf::from(file)).exists()
+}
+
+/// Value of the `file` field after attaching the file. Files which are
+/// already listed aren't added twice
+pub fn add_file(value: &str, file: &str, append: bool) -> String {
+    let mut files = if append {
+        split_files(value)
+    } else {
+        Vec::new()
+    };
+    if !files.contains(&file) {
+        files.push(file);
+    }
+    files.join(&SEPARATOR.to_string())
+}
+
+/// Path of the file in the library directory, named after the pattern. A
+/// letter is appended to the name if it's taken by another file
+pub fn library_path(dir: &Path, entry: &Entry, pattern: &str, source: &Path) -> PathBuf {
+    let mut name = format_filename(entry, pattern);
+    if name.is_empty() {
+        name = entry.key.clone();
+    }
+    let path = |name: &str| match source.extension() {
+        Some(ext) => dir.join(format!("{}.{}", name, ext.to_string_lossy())),
+        None => dir.join(name),
+    };
+    let name = unique_citekey(&name, |name| {
+        let target = expand_home(&path(name));
+        target.exists() && !same_file(&target, source)
+    });
+    path(&name)
+}
+
+/// Copy or move the file to the target path. Moving between file systems
+/// falls back to copying and removing the file
+pub fn store_file(source: &Path, target: &Path, move_file: bool) -> Result<()> {
+    let target = expand_home(&target.to_path_buf());
+    if same_file(source, &target) {
+        return Ok(());
+    }
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| eyre!("Can't create {}: {}", parent.display(), e))?;
+    }
+    if move_file && fs::rename(source, &target).is_ok() {
+        return Ok(());
+    }
+    fs::copy(source, &target).map_err(|e| eyre!("Can't copy to {}: {}", target.display(), e))?;
+    if move_file {
+        fs::remove_file(source)?;
+    }
+    Ok(())
+}
+
+/// Complete the typed path to the longest common prefix of the matching
+/// files and directories. Returns the completion and the matching names,
+/// directories end with a `/`
+pub fn complete_path(input: &str) -> (String, Vec<String>) {
+    let (dir, prefix) = match input.rfind('/') {
+        Some(i) => input.split_at(i + 1),
+        None => ("", input),
+    };
+    let read_dir = if dir.is_empty() {
+        PathBuf::from(".")
+    } else {
+        expand_home(&PathBuf::from(dir))
+    };
+    let mut names: Vec<String> = fs::read_dir(read_dir)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .filter_map(|e| {
+                    let name = e.file_name().to_string_lossy().to_string();
+                    // Hidden files are only listed if asked for
+                    let hidden = name.starts_with('.') && !prefix.starts_with('.');
+                    (name.starts_with(prefix) && !hidden).then(|| {
+                        if e.path().is_dir() {
+                            format!("{}/", name)
+                        } else {
+                            name
+                        }
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort_unstable();
+
+    let common = match names.split_first() {
+        Some((first, rest)) => rest.iter().fold(first.as_str(), |common, name| {
+            let len = common
+                .char_indices()
+                .zip(name.chars())
+                .take_while(|((_, a), b)| a == b)
+                .last()
+                .map_or(0, |((i, a), _)| i + a.len_utf8());
+            &common[..len]
+        }),
+        None => prefix,
+    };
+    (format!("{}{}", dir, common), names)
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use biblatex::Bibliography;
+
+    #[test]
+    fn attach_files() {
+        assert_eq!(split_files("a.pdf; b.epub;"), ["a.pdf", "b.epub"]);
+        assert_eq!(add_file("a.pdf", "b.pdf", true), "a.pdf;b.pdf");
+        assert_eq!(add_file("a.pdf;b.pdf", "b.pdf", true), "a.pdf;b.pdf");
+        assert_eq!(add_file("a.pdf", "b.pdf", false), "b.pdf");
+
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        fs::create_dir(dir.join("sub")).unwrap();
+        let source = dir.join("download.pdf");
+        fs::write(&source, "pdf").unwrap();
+        fs::write(dir.join("doe2020.pdf"), "other").unwrap();
+
+        let input = format!("{}/d", dir.display());
+        let (completed, names) = complete_path(&input);
+        assert_eq!(completed, format!("{}/do", dir.display()));
+        assert_eq!(names, ["doe2020.pdf", "download.pdf"]);
+        let (completed, _) = complete_path(&format!("{}/s", dir.display()));
+        assert_eq!(completed, format!("{}/sub/", dir.display()));
+
+        // The name of the other file is taken
+        let bib = Bibliography::parse("@article{doe2020,\n  title = {Things},\n}\n").unwrap();
+        let target = library_path(dir, bib.get("doe2020").unwrap(), "{citekey}", &source);
+        assert_eq!(target, dir.join("doe2020a.pdf"));
+        store_file(&source, &target, true).unwrap();
+        assert!(target.is_file() && !source.exists());
+    }
+}
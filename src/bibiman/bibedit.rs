@@ -0,0 +1,831 @@
+// bibiman - a TUI for managing BibLaTeX databases
+// Copyright (C) 2024  lukeflo
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+/////
+
+//! Functions to edit the source text of bibfiles.
+//!
+//! The biblatex crate can only serialize a whole bibliography, which would
+//! drop comments and the custom formatting of the file. Thus, changes are
+//! spliced into the original text and everything else is left untouched.
+
+use std::fs;
+use std::io::Write;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{eyre, Result};
+
+use super::bibyaml::{self, is_yaml};
+
+/// Position of a single entry inside the source text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntrySpan {
+    // Whole entry from `@` to the closing delimiter (inclusive)
+    pub range: Range<usize>,
+    // End of the citekey, including the following comma if present
+    pub key_end: usize,
+    // Position of the closing delimiter
+    pub body_end: usize,
+    pub fields: Vec<FieldSpan>,
+}
+
+/// Position of a single field inside the source text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldSpan {
+    pub name: String,
+    // From the first char of the field name to the end of the value
+    pub range: Range<usize>,
+    // Raw value including its delimiters
+    pub value: Range<usize>,
+}
+
+/// Find the entry with the given citekey in the source text
+pub fn find_entry(content: &str, citekey: &str) -> Option<EntrySpan> {
+    entries(content)
+        .into_iter()
+        .find(|(key, _)| key == citekey)
+        .map(|(_, span)| span)
+}
+
+/// Collect all entries of the source text together with their citekeys.
+/// `@string`, `@preamble` and `@comment` blocks are skipped.
+pub fn entries(content: &str) -> Vec<(String, EntrySpan)> {
+    let bytes = content.as_bytes();
+    let mut entries = Vec::new();
+    let mut pos = 0;
+
+    while let Some(offset) = content[pos..].find('@') {
+        let start = pos + offset;
+        pos = start + 1;
+
+        // Entry type
+        let type_start = skip_whitespace(content, start + 1);
+        let type_end = scan_while(content, type_start, |c| c.is_alphanumeric() || c == '_');
+        if type_end == type_start {
+            continue;
+        }
+        let entry_type = content[type_start..type_end].to_lowercase();
+
+        // Opening delimiter
+        let open = skip_whitespace(content, type_end);
+        let close_char = match bytes.get(open) {
+            Some(b'{') => b'}',
+            Some(b'(') => b')',
+            _ => continue,
+        };
+        let Some(body_end) = matching_delimiter(content, open, close_char) else {
+            continue;
+        };
+        if ["string", "preamble", "comment"].contains(&entry_type.as_str()) {
+            pos = body_end + 1;
+            continue;
+        }
+
+        // Citekey
+        let key_start = skip_whitespace(content, open + 1);
+        let key_end = scan_while(content, key_start, |c| {
+            !c.is_whitespace() && c != ',' && c != '}' && c != ')'
+        });
+        let citekey = content[key_start..key_end].to_string();
+        let mut after_key = skip_whitespace(content, key_end);
+        if bytes.get(after_key) == Some(&b',') {
+            after_key += 1;
+        } else {
+            after_key = key_end;
+        }
+
+        let fields = parse_fields(content, after_key, body_end);
+        entries.push((
+            citekey,
+            EntrySpan {
+                range: start..body_end + 1,
+                key_end: after_key,
+                body_end,
+                fields,
+            },
+        ));
+        pos = body_end + 1;
+    }
+
+    entries
+}
+
+/// Return the first of the passed bibfiles containing the citekey. Only
+/// `.bib` files are considered, YAML files can't be edited in place
+pub fn bibfile_of_entry(files: &[PathBuf], citekey: &str) -> Option<PathBuf> {
+    files
+        .iter()
+        .filter(|f| !is_yaml(f))
+        .find(|f| {
+            fs::read_to_string(f)
+                .map(|content| find_entry(&content, citekey).is_some())
+                .unwrap_or(false)
+        })
+        .cloned()
+}
+
+/// Set the value of a field directly in the bibfile
+pub fn set_field_in_file(file: &Path, citekey: &str, field: &str, value: &str) -> Result<()> {
+    let content = fs::read_to_string(file)?;
+    let new_content = set_field(&content, citekey, field, value)?;
+    fs::write(file, new_content)?;
+    Ok(())
+}
+
+/// Return the raw value of a field without its outer braces or quotes
+pub fn get_field(content: &str, citekey: &str, field: &str) -> Option<String> {
+    let entry = find_entry(content, citekey)?;
+    let span = entry
+        .fields
+        .iter()
+        .find(|f| f.name.eq_ignore_ascii_case(field))?;
+    Some(strip_delimiters(&content[span.value.clone()]).to_string())
+}
+
+/// List all fields of an entry with their raw values
+pub fn get_fields(content: &str, citekey: &str) -> Vec<(String, String)> {
+    find_entry(content, citekey)
+        .map(|entry| {
+            entry
+                .fields
+                .iter()
+                .map(|f| {
+                    (
+                        f.name.to_lowercase(),
+                        strip_delimiters(&content[f.value.clone()]).to_string(),
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Set the value of a field and return the changed source text.
+///
+/// An existing value is replaced, a missing field is appended to the entry
+/// with the same indentation as the other fields. An empty value removes
+/// the field completely.
+pub fn set_field(content: &str, citekey: &str, field: &str, value: &str) -> Result<String> {
+    if !braces_balanced(value) {
+        return Err(eyre!("Unbalanced braces in value of field {}", field));
+    }
+    let raw = if value.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", value)
+    };
+    set_raw_field(content, citekey, field, &raw)
+}
+
+/// Like [`set_field`], but the value is written as passed, including its
+/// delimiters. Thus, macros like `jan` and concatenations with `#` are kept
+pub fn set_raw_field(content: &str, citekey: &str, field: &str, value: &str) -> Result<String> {
+    let entry =
+        find_entry(content, citekey).ok_or_else(|| eyre!("Citekey {} not found", citekey))?;
+    let idx = entry
+        .fields
+        .iter()
+        .position(|f| f.name.eq_ignore_ascii_case(field));
+
+    let mut new_content = content.to_string();
+    match idx {
+        // Remove field
+        Some(i) if value.is_empty() => {
+            let span = &entry.fields[i];
+            let range = if i > 0 {
+                // Remove from end of previous value, thus, the separating comma
+                // of the removed field is deleted, a trailing comma is kept
+                entry.fields[i - 1].value.end..span.value.end
+            } else if let Some(next) = entry.fields.get(1) {
+                span.range.start..next.range.start
+            } else {
+                let end = skip_whitespace(content, span.value.end);
+                let end = if content.as_bytes().get(end) == Some(&b',') {
+                    end + 1
+                } else {
+                    span.value.end
+                };
+                entry.key_end..end
+            };
+            new_content.replace_range(range, "");
+        }
+        // Replace value
+        Some(i) => {
+            new_content.replace_range(entry.fields[i].value.clone(), value);
+        }
+        // Nothing to remove
+        None if value.is_empty() => {}
+        // Append new field
+        None => {
+            let indent = entry
+                .fields
+                .first()
+                .map(|f| indentation(content, f.range.start))
+                .unwrap_or("    ");
+            let new_field = format!("{}{} = {}", indent, field, value);
+            match entry.fields.last() {
+                Some(last) => {
+                    new_content.insert_str(last.value.end, &format!(",\n{}", new_field));
+                }
+                None if content[..entry.key_end].ends_with(',') => {
+                    new_content.insert_str(entry.key_end, &format!("\n{}\n", new_field));
+                }
+                None => {
+                    new_content.insert_str(entry.key_end, &format!(",\n{}\n", new_field));
+                }
+            }
+        }
+    }
+
+    Ok(new_content)
+}
+
+/// Split the value of a `keywords` field into single keywords
+pub fn split_keywords(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|k| k.trim().to_string())
+        .filter(|k| !k.is_empty())
+        .collect()
+}
+
+/// Change the keywords of an entry through the passed closure. Duplicates
+/// are removed afterwards, thus, renaming a keyword to an existing one
+/// merges both. Returns `None` if the keywords didn't change
+pub fn update_keywords(
+    content: &str,
+    citekey: &str,
+    f: impl Fn(&mut Vec<String>),
+) -> Result<Option<String>> {
+    let old = split_keywords(&get_field(content, citekey, "keywords").unwrap_or_default());
+    let mut keywords = old.clone();
+    f(&mut keywords);
+    let mut seen = Vec::new();
+    keywords.retain(|k| {
+        let new = !seen.contains(k);
+        seen.push(k.clone());
+        new
+    });
+
+    if keywords == old {
+        return Ok(None);
+    }
+    set_field(content, citekey, "keywords", &keywords.join(", ")).map(Some)
+}
+
+/// Change the keywords of all passed entries in the bibfiles. Returns the
+/// number of changed entries
+pub fn update_keywords_in_files(
+    files: &[PathBuf],
+    citekeys: &[String],
+    f: impl Fn(&mut Vec<String>),
+) -> Result<usize> {
+    let mut changed = 0;
+    for file in files.iter().filter(|f| !is_yaml(f)) {
+        let mut content = fs::read_to_string(file)?;
+        let mut file_changed = false;
+        // Entries are looked up by citekey, thus, a citekey which occurs
+        // several times in the file is only updated once
+        let mut keys: Vec<String> = entries(&content).into_iter().map(|(key, _)| key).collect();
+        keys.sort_unstable();
+        keys.dedup();
+        for key in keys.iter().filter(|k| citekeys.contains(k)) {
+            if let Some(new_content) = update_keywords(&content, key, &f)? {
+                content = new_content;
+                file_changed = true;
+                changed += 1;
+            }
+        }
+        if file_changed {
+            fs::write(file, content)?;
+        }
+    }
+    Ok(changed)
+}
+
+/// Remove the entry and return the changed source text together with the
+/// source of the removed entry. The line of the entry is removed, too,
+/// thus, no additional blank line is left
+pub fn remove_entry(content: &str, citekey: &str) -> Result<(String, String)> {
+    let entry =
+        find_entry(content, citekey).ok_or_else(|| eyre!("Citekey {} not found", citekey))?;
+    let mut new_content = content.to_string();
+    new_content.replace_range(removal_range(content, entry.range.clone()), "");
+    Ok((new_content, content[entry.range].to_string()))
+}
+
+/// Extend the range of an entry to the rest of its line and a following
+/// blank line. Removing it doesn't leave an additional blank line
+pub fn removal_range(content: &str, range: Range<usize>) -> Range<usize> {
+    let start = range.start;
+    let mut end = scan_while(content, range.end, |c| c == ' ' || c == '\t');
+    if content[end..].starts_with('\n') {
+        end += 1;
+    }
+    // Collapse the blank lines before and after the entry into one
+    if (start == 0 || content[..start].ends_with("\n\n")) && content[end..].starts_with('\n') {
+        end += 1;
+    }
+    start..end
+}
+
+/// Remove the entries, given by citekey and the bibfile they were read
+/// from. If a citekey exists several times in the file, the first entry is
+/// removed. Returns the number of removed entries
+pub fn remove_entries_from_files(entries: &[(String, PathBuf)]) -> Result<usize> {
+    let mut removed = 0;
+    let mut files: Vec<&PathBuf> = entries.iter().map(|(_, file)| file).collect();
+    files.sort();
+    files.dedup();
+    for file in files.into_iter().filter(|f| !is_yaml(f)) {
+        let mut content = fs::read_to_string(file)?;
+        for (key, _) in entries.iter().filter(|(_, f)| f == file) {
+            if find_entry(&content, key).is_some() {
+                content = remove_entry(&content, key)?.0;
+                removed += 1;
+            }
+        }
+        fs::write(file, content)?;
+    }
+    Ok(removed)
+}
+
+/// Copy the entries, given by citekey and the bibfile they were read from,
+/// to the target file, or move them if `remove` is set. Entries already
+/// existing in the target file are skipped. Returns the number of copied or
+/// moved entries
+pub fn transfer_entries(
+    selected: &[(String, PathBuf)],
+    target: &Path,
+    remove: bool,
+) -> Result<usize> {
+    let target_content = fs::read_to_string(target)?;
+    let target_keys: Vec<String> = if is_yaml(target) {
+        entries(&bibyaml::yaml_to_biblatex(&target_content)?)
+    } else {
+        entries(&target_content)
+    }
+    .into_iter()
+    .map(|(key, _)| key)
+    .collect();
+
+    let transferred: Vec<(String, PathBuf)> = selected
+        .iter()
+        .filter(|(key, _)| !target_keys.contains(key))
+        .cloned()
+        .collect();
+    let mut sources = Vec::new();
+    for (citekey, file) in &transferred {
+        let source = Some(file)
+            .filter(|file| !is_yaml(file))
+            .and_then(|file| fs::read_to_string(file).ok())
+            .and_then(|content| {
+                find_entry(&content, citekey).map(|span| content[span.range].to_string())
+            })
+            .ok_or_else(|| eyre!("Entry {} isn't part of a .bib file", citekey))?;
+        sources.push(source);
+    }
+    if sources.is_empty() {
+        return Ok(0);
+    }
+
+    // Write the target first, thus, if anything fails afterwards, the
+    // entries are duplicated instead of lost
+    if is_yaml(target) {
+        bibyaml::append_to_yaml_file(target, &sources.join("\n\n"))?;
+    } else {
+        let mut new_content = target_content;
+        if !new_content.is_empty() && !new_content.ends_with('\n') {
+            new_content.push('\n');
+        }
+        for source in &sources {
+            if !new_content.is_empty() {
+                new_content.push('\n');
+            }
+            new_content.push_str(source);
+            new_content.push('\n');
+        }
+        fs::write(target, new_content)?;
+    }
+
+    if remove {
+        let moved: Vec<(String, PathBuf)> = transferred
+            .into_iter()
+            .filter(|(_, file)| fs::canonicalize(file).ok() != fs::canonicalize(target).ok())
+            .collect();
+        remove_entries_from_files(&moved)?;
+    }
+    Ok(sources.len())
+}
+
+// Fields whose values are citekeys of other entries
+const REFERENCE_FIELDS: [&str; 5] = ["crossref", "xref", "xdata", "related", "set"];
+
+/// Rename the citekeys of the entries. Other entries referring to a
+/// renamed one, e.g. through `crossref`, are updated too. Returns the
+/// changed source text and the number of renamed entries
+pub fn rename_citekeys(content: &str, renames: &[(String, String)]) -> (String, usize) {
+    let new_key = |key: &str| {
+        renames
+            .iter()
+            .find(|(old, _)| old == key)
+            .map(|(_, new)| new.clone())
+    };
+    let mut new_content = content.to_string();
+    let mut renamed = 0;
+    // Changes are spliced in from the end, thus, the spans stay valid
+    for (key, span) in entries(content).into_iter().rev() {
+        for field in span
+            .fields
+            .iter()
+            .rev()
+            .filter(|f| REFERENCE_FIELDS.contains(&f.name.to_lowercase().as_str()))
+        {
+            let keys: Vec<&str> = strip_delimiters(&content[field.value.clone()])
+                .split(',')
+                .map(str::trim)
+                .collect();
+            if keys.iter().any(|k| new_key(k).is_some()) {
+                let value = keys
+                    .iter()
+                    .map(|k| new_key(k).unwrap_or(k.to_string()))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                new_content.replace_range(field.value.clone(), &format!("{{{}}}", value));
+            }
+        }
+        let Some(new) = new_key(&key) else {
+            continue;
+        };
+        let key_start = content[span.range.start..span.key_end]
+            .find(['{', '('])
+            .map(|idx| span.range.start + idx + 1)
+            .and_then(|open| content[open..span.key_end].find(&key).map(|idx| open + idx));
+        if let Some(start) = key_start {
+            new_content.replace_range(start..start + key.len(), &new);
+            renamed += 1;
+        }
+    }
+    (new_content, renamed)
+}
+
+/// Rename the citekeys in all passed `.bib` files. Returns the number of
+/// renamed entries
+pub fn rename_citekeys_in_files(files: &[PathBuf], renames: &[(String, String)]) -> Result<usize> {
+    let mut renamed = 0;
+    for file in files.iter().filter(|f| !is_yaml(f)) {
+        let content = fs::read_to_string(file)?;
+        let (new_content, count) = rename_citekeys(&content, renames);
+        if new_content != content {
+            fs::write(file, new_content)?;
+        }
+        renamed += count;
+    }
+    Ok(renamed)
+}
+
+/// Append the BibLaTeX source of new entries to the bibfile. YAML files
+/// get the entries converted to the Hayagriva format
+pub fn append_entries(file: &Path, content: &str) -> Result<()> {
+    if is_yaml(file) {
+        return bibyaml::append_to_yaml_file(file, content);
+    }
+    let mut file = fs::OpenOptions::new().append(true).open(file)?;
+    file.write_all(b"\n")?;
+    file.write_all(content.as_bytes())?;
+    Ok(())
+}
+
+/// Strip one pair of outer braces or quotes from a raw field value
+pub fn strip_delimiters(value: &str) -> &str {
+    let value = value.trim();
+    if (value.starts_with('{') && value.ends_with('}')
+        || value.starts_with('"') && value.ends_with('"'))
+        && value.len() >= 2
+        && matching_delimiter(value, 0, if value.starts_with('{') { b'}' } else { b'"' })
+            == Some(value.len() - 1)
+    {
+        &value[1..value.len() - 1]
+    } else {
+        value
+    }
+}
+
+pub fn braces_balanced(value: &str) -> bool {
+    let mut depth: i32 = 0;
+    for c in value.chars() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            _ => {}
+        }
+        if depth < 0 {
+            return false;
+        }
+    }
+    depth == 0
+}
+
+// Parse all fields between the citekey and the closing delimiter
+fn parse_fields(content: &str, start: usize, end: usize) -> Vec<FieldSpan> {
+    let bytes = content.as_bytes();
+    let mut fields = Vec::new();
+    let mut pos = start;
+
+    loop {
+        pos = scan_while(content, pos, |c| c.is_whitespace() || c == ',');
+        if pos >= end {
+            break;
+        }
+        let name_start = pos;
+        let name_end = scan_while(content, pos, |c| {
+            !c.is_whitespace() && c != '=' && c != ',' && c != '}' && c != ')'
+        });
+        let eq = skip_whitespace(content, name_end);
+        if name_end == name_start || bytes.get(eq) != Some(&b'=') {
+            break;
+        }
+        let value_start = skip_whitespace(content, eq + 1);
+        let Some(value_end) = value_end(content, value_start, end) else {
+            break;
+        };
+        fields.push(FieldSpan {
+            name: content[name_start..name_end].to_string(),
+            range: name_start..value_end,
+            value: value_start..value_end,
+        });
+        pos = value_end;
+    }
+
+    fields
+}
+
+// Find the end of a value which may consist of several parts
+// concatenated with `#`, e.g. `{Part one } # var # "part two"`
+fn value_end(content: &str, start: usize, limit: usize) -> Option<usize> {
+    let bytes = content.as_bytes();
+    let mut pos = start;
+    loop {
+        let part_end = match bytes.get(pos)? {
+            b'{' => matching_delimiter(content, pos, b'}')? + 1,
+            b'"' => matching_delimiter(content, pos, b'"')? + 1,
+            _ => scan_while(content, pos, |c| {
+                !c.is_whitespace() && c != ',' && c != '#' && c != '}' && c != ')'
+            }),
+        };
+        if part_end > limit || part_end == pos {
+            return None;
+        }
+        let next = skip_whitespace(content, part_end);
+        if bytes.get(next) == Some(&b'#') {
+            pos = skip_whitespace(content, next + 1);
+        } else {
+            return Some(part_end);
+        }
+    }
+}
+
+// Position of the delimiter closing the one at `open`. Nested braces are
+// respected; a closing quote or parenthesis only counts outside of braces.
+// A closing parenthesis doesn't count inside of quoted values either
+fn matching_delimiter(content: &str, open: usize, close: u8) -> Option<usize> {
+    let mut depth = 0;
+    let mut quoted = false;
+    for (i, b) in content.as_bytes().iter().enumerate().skip(open + 1) {
+        match b {
+            b'{' => depth += 1,
+            b'}' if depth == 0 && close == b'}' => return Some(i),
+            b'}' => depth -= 1,
+            b'"' if depth == 0 && close == b')' => quoted = !quoted,
+            b if *b == close && depth == 0 && !quoted => return Some(i),
+            _ => {}
+        }
+        if depth < 0 {
+            return None;
+        }
+    }
+    None
+}
+
+fn skip_whitespace(content: &str, pos: usize) -> usize {
+    scan_while(content, pos, char::is_whitespace)
+}
+
+fn scan_while(content: &str, pos: usize, pred: impl Fn(char) -> bool) -> usize {
+    content[pos.min(content.len())..]
+        .char_indices()
+        .find(|(_, c)| !pred(*c))
+        .map(|(i, _)| pos + i)
+        .unwrap_or(content.len())
+}
+
+// Whitespace between the start of the line and the given position
+fn indentation(content: &str, pos: usize) -> &str {
+    let line_start = content[..pos].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let indent = &content[line_start..pos];
+    if indent.chars().all(char::is_whitespace) {
+        indent
+    } else {
+        "    "
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BIB: &str = r#"% A comment which must survive
+@string{jan = "January"}
+
+@article{miller_2000,
+  author = {Miller, Tom},
+  title = {A {Nested} Title},
+  month = jan,
+  note = "Quoted " # jan,
+}
+
+@book(other,
+    title = {Other}
+)
+"#;
+
+    #[test]
+    fn parse_entries() {
+        let entries = entries(BIB);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].0, "miller_2000");
+        assert_eq!(entries[1].0, "other");
+
+        let fields = get_fields(BIB, "miller_2000");
+        assert_eq!(
+            fields,
+            vec![
+                ("author".to_string(), "Miller, Tom".to_string()),
+                ("title".to_string(), "A {Nested} Title".to_string()),
+                ("month".to_string(), "jan".to_string()),
+                ("note".to_string(), "\"Quoted \" # jan".to_string()),
+            ]
+        );
+        assert_eq!(get_field(BIB, "other", "TITLE").unwrap(), "Other");
+        assert!(find_entry(BIB, "miller").is_none());
+    }
+
+    #[test]
+    fn replace_field() {
+        let new = set_field(BIB, "miller_2000", "title", "New Title").unwrap();
+        assert!(new.contains("  title = {New Title},\n  month = jan,"));
+        assert!(new.starts_with("% A comment which must survive\n@string{jan = \"January\"}"));
+        assert_eq!(
+            new.len(),
+            BIB.len() - "A {Nested} Title".len() + "New Title".len()
+        );
+    }
+
+    #[test]
+    fn add_field() {
+        let new = set_field(BIB, "miller_2000", "doi", "10.1000/182").unwrap();
+        assert!(new.contains("  note = \"Quoted \" # jan,\n  doi = {10.1000/182},\n}"));
+
+        let new = set_field(BIB, "other", "year", "2001").unwrap();
+        assert!(new.contains("@book(other,\n    title = {Other},\n    year = {2001}\n)"));
+    }
+
+    #[test]
+    fn remove_field() {
+        let new = set_field(BIB, "miller_2000", "note", "").unwrap();
+        assert!(new.contains("  month = jan,\n}"));
+
+        let new = set_field(BIB, "miller_2000", "author", "").unwrap();
+        assert!(new.contains("@article{miller_2000,\n  title = {A {Nested} Title},"));
+
+        let new = set_field(BIB, "other", "title", "").unwrap();
+        assert!(new.contains("@book(other,\n)"));
+    }
+
+    #[test]
+    fn change_keywords() {
+        let bib = "@misc{a,\n  keywords = {ML, statistics},\n}\n@misc{b,\n  title = {B},\n}\n";
+
+        let rename = |k: &mut Vec<String>| {
+            k.iter_mut()
+                .filter(|k| *k == "ML")
+                .for_each(|k| *k = "statistics".to_string())
+        };
+        let new = update_keywords(bib, "a", rename).unwrap().unwrap();
+        assert!(new.contains("keywords = {statistics},"));
+        assert!(update_keywords(bib, "b", rename).unwrap().is_none());
+
+        let new = update_keywords(bib, "b", |k| k.push("new".to_string()))
+            .unwrap()
+            .unwrap();
+        assert!(new.contains("  title = {B},\n  keywords = {new},\n}"));
+
+        let new = update_keywords(bib, "a", |k| k.clear()).unwrap().unwrap();
+        assert!(new.starts_with("@misc{a,\n}"));
+
+        // The entry of a citekey which occurs twice is only updated once
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("keywords.bib");
+        fs::write(&file, format!("{}@misc{{a,\n}}\n", bib)).unwrap();
+        let calls = std::cell::Cell::new(0);
+        let changed = update_keywords_in_files(&[file], &["a".to_string()], |k| {
+            calls.set(calls.get() + 1);
+            k.push("new".to_string())
+        });
+        assert_eq!(changed.unwrap(), 1);
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn delete_entry() {
+        let (new, removed) = remove_entry(BIB, "miller_2000").unwrap();
+        assert!(removed.starts_with("@article{miller_2000,"));
+        assert!(removed.ends_with("jan,\n}"));
+        assert!(
+            new.ends_with("@string{jan = \"January\"}\n\n@book(other,\n    title = {Other}\n)\n")
+        );
+
+        let (new, _) = remove_entry(&new, "other").unwrap();
+        assert_eq!(
+            new,
+            "% A comment which must survive\n@string{jan = \"January\"}\n\n"
+        );
+        assert!(remove_entry(BIB, "unknown").is_err());
+    }
+
+    #[test]
+    fn rename_entries() {
+        let bib = "@inproceedings{part,\n  crossref = {book},\n  related = {other, book},\n}\n@book{ book ,\n  title = {Book},\n}\n";
+        let renames = vec![("book".to_string(), "miller2000".to_string())];
+        let (new, renamed) = rename_citekeys(bib, &renames);
+        assert_eq!(renamed, 1);
+        assert_eq!(
+            new,
+            "@inproceedings{part,\n  crossref = {miller2000},\n  related = {other, miller2000},\n}\n@book{ miller2000 ,\n  title = {Book},\n}\n"
+        );
+    }
+
+    #[test]
+    fn move_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source.bib");
+        let target = dir.path().join("target.bib");
+        fs::write(&source, BIB).unwrap();
+        fs::write(&target, "@misc{other,\n  title = {Other},\n}\n").unwrap();
+        let selected = vec![
+            ("miller_2000".to_string(), source.clone()),
+            ("other".to_string(), source.clone()),
+        ];
+
+        // `other` already exists in the target and is skipped
+        assert_eq!(transfer_entries(&selected, &target, true).unwrap(), 1);
+        let source_content = fs::read_to_string(&source).unwrap();
+        let target_content = fs::read_to_string(&target).unwrap();
+        assert!(find_entry(&source_content, "miller_2000").is_none());
+        assert!(find_entry(&source_content, "other").is_some());
+        assert!(target_content
+            .starts_with("@misc{other,\n  title = {Other},\n}\n\n@article{miller_2000,"));
+
+        assert_eq!(transfer_entries(&selected, &target, false).unwrap(), 0);
+        let selected = vec![
+            ("miller_2000".to_string(), target.clone()),
+            ("other".to_string(), source.clone()),
+        ];
+        assert_eq!(remove_entries_from_files(&selected).unwrap(), 2);
+        assert!(entries(&fs::read_to_string(&target).unwrap()).len() == 1);
+    }
+
+    #[test]
+    fn paren_delimited_entry() {
+        let bib = "@book(key,\n  title = {A (B) C},\n  note = \"D (E) F\",\n)\n@misc{next,\n}\n";
+        let (key, span) = entries(bib).remove(0);
+        assert_eq!(key, "key");
+        assert_eq!(span.range, 0..bib.find("\n@misc").unwrap());
+        assert_eq!(span.fields.len(), 2);
+        assert_eq!(get_field(bib, "key", "note").unwrap(), "D (E) F");
+
+        let new = set_field(bib, "key", "year", "2001").unwrap();
+        assert!(new.contains("  note = \"D (E) F\",\n  year = {2001},\n)\n@misc{next,"));
+        let (new, _) = remove_entry(bib, "key").unwrap();
+        assert_eq!(new, "@misc{next,\n}\n");
+    }
+
+    #[test]
+    fn reject_unbalanced_braces() {
+        assert!(set_field(BIB, "miller_2000", "title", "A {broken").is_err());
+        assert!(set_field(BIB, "unknown", "title", "Title").is_err());
+    }
+}
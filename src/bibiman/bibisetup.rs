@@ -16,12 +16,18 @@
 /////
 
 use biblatex::{self, Bibliography};
-use biblatex::{ChunksExt, Type};
+use biblatex::{ChunksExt, DateValue, PermissiveType};
 use color_eyre::owo_colors::OwoColorize;
 use itertools::Itertools;
+use std::collections::{HashMap, HashSet};
 use std::ffi::OsString;
-use std::{fs, path::PathBuf};
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
+use super::diagnostics::{self, BibSource, Diagnostic};
+use super::{bibedit, bibyaml, fulltext};
 use crate::cliargs;
 
 // Set necessary fields
@@ -34,6 +40,8 @@ pub struct BibiSetup {
     pub citekeys: Vec<String>,      // list of all citekeys
     pub keyword_list: Vec<String>,  // list of all available keywords
     pub entry_list: Vec<BibiData>,  // List of all entries
+    // Problems found while loading the files, affected entries are skipped
+    pub diagnostics: Vec<Diagnostic>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -48,17 +56,73 @@ pub struct BibiData {
     pub doi_url: Option<String>,
     pub filepath: Option<OsString>,
     pub subtitle: Option<String>,
+    // All fields of the entry as plain text, used for searching
+    pub other_fields: Vec<(String, String)>,
+    // Text of the connected PDF, if the full-text index is enabled
+    pub fulltext: Option<Arc<str>>,
+    // File the entry was read from
+    pub source: Option<EntrySource>,
+}
+
+/// Bibfile an entry was read from and its position inside of it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntrySource {
+    pub file: PathBuf,
+    // Byte range of the entry inside the file
+    pub span: Range<usize>,
+    // Line of the first char of the entry, starting at 1
+    pub line: usize,
+}
+
+// Entries are ordered as they appear in the files
+impl Ord for EntrySource {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (&self.file, self.span.start, self.span.end).cmp(&(
+            &other.file,
+            other.span.start,
+            other.span.end,
+        ))
+    }
+}
+
+impl PartialOrd for EntrySource {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl EntrySource {
+    fn new(file: &Path, content: &str, span: Range<usize>) -> Self {
+        Self {
+            file: file.to_path_buf(),
+            line: content[..span.start].matches('\n').count() + 1,
+            span,
+        }
+    }
+
+    /// Name of the file and line of the entry, e.g. `thesis.bib:42`
+    pub fn location(&self) -> String {
+        format!(
+            "{}:{}",
+            self.file.file_name().unwrap_or_default().to_string_lossy(),
+            self.line
+        )
+    }
 }
 
 impl BibiSetup {
     pub fn new(main_bibfiles: &[PathBuf]) -> Self {
         // TODO: Needs check for config file path as soon as config file is impl
         Self::check_files(main_bibfiles);
-        let bibfilestring = Self::bibfiles_to_string(main_bibfiles);
-        let bibliography = biblatex::Bibliography::parse(&bibfilestring).unwrap();
+        let mut diagnostics = Vec::new();
+        let (mut bib_sources, sources) = Self::load_sources(main_bibfiles, &mut diagnostics);
+        let (bibfilestring, bibliography) =
+            diagnostics::parse_sources(&mut bib_sources, &mut diagnostics);
+        // Problems are listed in the order of the files
+        diagnostics.sort_by_key(|d| (main_bibfiles.iter().position(|f| f == &d.file), d.line));
         let citekeys = Self::get_citekeys(&bibliography);
         let keyword_list = Self::collect_tag_list(&citekeys, &bibliography);
-        let entry_list = Self::create_entry_list(&citekeys, &bibliography);
+        let entry_list = Self::create_entry_list(&citekeys, &bibliography, &sources);
         Self {
             // bibfile,
             bibfilestring,
@@ -66,6 +130,15 @@ impl BibiSetup {
             citekeys,
             keyword_list,
             entry_list,
+            diagnostics,
+        }
+    }
+
+    /// Attach the text of the connected PDFs to the entries, using the
+    /// cached full-text index
+    pub fn index_fulltext(&mut self) {
+        if let Some(cache) = fulltext::default_cache_path() {
+            fulltext::index_entries(&mut self.entry_list, &cache);
         }
     }
 
@@ -84,10 +157,13 @@ impl BibiSetup {
         } else {
             // Loop over all files and check for the correct extension
             main_bibfiles.iter().for_each(|f| {
-                if f.extension().is_some() && f.extension().unwrap() != "bib" {
+                if f.extension().is_some()
+                    && f.extension().unwrap() != "bib"
+                    && !bibyaml::is_yaml(f)
+                {
                     println!(
                         "{}\n{}",
-                        "The passed file has no valid extension. You need a \'.bib\' file:"
+                        "The passed file has no valid extension. You need a \'.bib\' or \'.yml\' file:"
                             .red()
                             .bold(),
                         f.as_os_str().to_string_lossy().bright_red().italic()
@@ -100,18 +176,112 @@ impl BibiSetup {
         }
     }
 
-    fn bibfiles_to_string(main_bibfiles: &[PathBuf]) -> String {
-        // Map PathBufs to String anc join the vector into one big string
-        // This behaviour is needed by the biblatex crate for parsing
-        let file_strings: Vec<String> = main_bibfiles
-            .iter()
-            .map(|f| fs::read_to_string(f).unwrap())
-            .collect();
+    fn load_sources(
+        main_bibfiles: &[PathBuf],
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> (Vec<BibSource>, HashMap<String, EntrySource>) {
+        // Read the content of all files. It is joined into one big string
+        // later, which is needed by the biblatex crate for parsing.
+        // Hayagriva YAML files are converted to BibLaTeX first.
+        // The position of each entry in its file is recorded before, thus,
+        // the origin of the entries isn't lost. If a citekey exists in
+        // several files, the first one is kept. Unreadable files are skipped
+        let mut sources: HashMap<String, EntrySource> = HashMap::new();
+        let mut citekeys: HashSet<String> = HashSet::new();
+        let mut bib_sources: Vec<BibSource> = Vec::new();
+        for f in main_bibfiles {
+            let content = match fs::read_to_string(f) {
+                Ok(content) => content,
+                Err(e) => {
+                    diagnostics.push(Diagnostic::new(f, &format!("Can't read file: {}", e)));
+                    continue;
+                }
+            };
+            let yaml = bibyaml::is_yaml(f);
+            let spans = if yaml {
+                bibyaml::entry_spans(&content)
+            } else {
+                bibedit::entries(&content)
+                    .into_iter()
+                    .map(|(key, span)| (key, span.range))
+                    .collect()
+            };
+            for (key, span) in spans {
+                sources
+                    .entry(key)
+                    .or_insert_with(|| EntrySource::new(f, &content, span));
+            }
+            let content = if !yaml {
+                content
+            } else {
+                match bibyaml::yaml_to_biblatex(&content) {
+                    Ok(content) => content,
+                    Err(e) => {
+                        diagnostics.push(Diagnostic::new(
+                            f,
+                            &format!("No valid Hayagriva bibliography: {}", e),
+                        ));
+                        continue;
+                    }
+                }
+            };
+            bib_sources.push(BibSource {
+                file: f.to_path_buf(),
+                content: Self::remove_duplicate_citekeys(
+                    content,
+                    f,
+                    yaml,
+                    &mut citekeys,
+                    &sources,
+                    diagnostics,
+                ),
+                yaml,
+            });
+        }
+        (bib_sources, sources)
+    }
 
-        file_strings.join("\n")
+    // The biblatex crate refuses to parse a bibliography with duplicate
+    // citekeys. Thus, all but the first entry with the same citekey are
+    // left out and reported. They can be merged through the duplicates view
+    fn remove_duplicate_citekeys(
+        mut content: String,
+        file: &Path,
+        yaml: bool,
+        citekeys: &mut HashSet<String>,
+        sources: &HashMap<String, EntrySource>,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> String {
+        let duplicates: Vec<(String, Range<usize>)> = bibedit::entries(&content)
+            .into_iter()
+            .filter(|(key, _)| !citekeys.insert(key.clone()))
+            .map(|(key, span)| (key, span.range))
+            .collect();
+        for (key, range) in duplicates {
+            let message = match sources.get(&key) {
+                Some(first) => format!(
+                    "Duplicate citekey {}, first defined at {}",
+                    key,
+                    first.location()
+                ),
+                None => format!("Duplicate citekey {}", key),
+            };
+            diagnostics.push(Diagnostic {
+                file: file.to_path_buf(),
+                line: (!yaml).then(|| content[..range.start].matches('\n').count() + 1),
+                citekey: Some(key),
+                message,
+            });
+            diagnostics::blank(&mut content, range);
+        }
+        content
     }
 
-    fn create_entry_list(citekeys: &[String], bibliography: &Bibliography) -> Vec<BibiData> {
+    fn create_entry_list(
+        citekeys: &[String],
+        bibliography: &Bibliography,
+        sources: &HashMap<String, EntrySource>,
+    ) -> Vec<BibiData> {
         citekeys
             .iter()
             .map(|k| BibiData {
@@ -125,6 +295,9 @@ impl BibiSetup {
                 doi_url: Self::get_weblink(k, bibliography),
                 filepath: Self::get_filepath(k, bibliography),
                 subtitle: Self::get_subtitle(k, bibliography),
+                other_fields: Self::get_other_fields(k, bibliography),
+                fulltext: None,
+                source: sources.get(k).cloned(),
             })
             .collect()
     }
@@ -177,7 +350,12 @@ impl BibiSetup {
             } else {
                 "empty".to_string()
             }
-        } else if !biblio.get(citekey).unwrap().editors().unwrap().is_empty() {
+        } else if biblio
+            .get(citekey)
+            .unwrap()
+            .editors()
+            .is_ok_and(|editors| !editors.is_empty())
+        {
             let editors = biblio.get(citekey).unwrap().editors().unwrap();
             if editors[0].0.len() > 1 {
                 format!("{} (ed.)", editors[0].0.iter().map(|e| &e.name).join(", "))
@@ -206,11 +384,18 @@ impl BibiSetup {
 
     pub fn get_year(citekey: &str, biblio: &Bibliography) -> String {
         let bib = biblio.get(citekey).unwrap();
-        if bib.date().is_ok() {
-            let year = bib.date().unwrap().to_chunks().format_verbatim();
-            year[..4].to_string()
-        } else {
-            "n.d.".to_string()
+        match bib.date() {
+            // For ranges, the start year is used
+            Ok(PermissiveType::Typed(date)) => match date.value {
+                DateValue::At(start)
+                | DateValue::After(start)
+                | DateValue::Before(start)
+                | DateValue::Between(start, _) => start.year.to_string(),
+            },
+            // Values which aren't dates, e.g. "forthcoming", are kept as they
+            // are. They are listed by the malformed date lint
+            Ok(PermissiveType::Chunks(chunks)) => chunks.format_verbatim(),
+            Err(_) => "n.d.".to_string(),
         }
     }
 
@@ -263,6 +448,16 @@ impl BibiSetup {
         }
     }
 
+    pub fn get_other_fields(citekey: &str, biblio: &Bibliography) -> Vec<(String, String)> {
+        biblio
+            .get(citekey)
+            .unwrap()
+            .fields
+            .iter()
+            .map(|(name, value)| (name.to_lowercase(), value.format_verbatim()))
+            .collect()
+    }
+
     pub fn get_subtitle(citekey: &str, biblio: &Bibliography) -> Option<String> {
         if biblio.get(citekey).unwrap().subtitle().is_ok() {
             Some(
@@ -0,0 +1,314 @@
+// bibiman - a TUI for managing BibLaTeX databases
+// Copyright (C) 2024  lukeflo
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+/////
+
+//! Support for bibliographies in the Hayagriva YAML format.
+//!
+//! YAML files are converted to BibLaTeX source when loaded. Thus, the
+//! entries pass through the same parser as entries of `.bib` files.
+
+use std::fmt::Write;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+use color_eyre::eyre::{eyre, Result};
+use hayagriva::types::{EntryType, Person};
+use hayagriva::{Entry, Library};
+
+use super::bibedit::braces_balanced;
+
+/// Check if the file has a `.yml` or `.yaml` extension
+pub fn is_yaml(file: &Path) -> bool {
+    file.extension()
+        .is_some_and(|ext| ext == "yml" || ext == "yaml")
+}
+
+/// Convert the content of a Hayagriva YAML file to BibLaTeX source
+pub fn yaml_to_biblatex(content: &str) -> Result<String> {
+    let library = hayagriva::io::from_yaml_str(content)?;
+    let mut bibtex = String::new();
+    for entry in library.iter() {
+        write_entry(&mut bibtex, entry)?;
+    }
+    Ok(bibtex)
+}
+
+/// Citekeys of all entries together with their position in the YAML
+/// source. An entry starts with its unindented citekey and ends before the
+/// next unindented line
+pub fn entry_spans(content: &str) -> Vec<(String, Range<usize>)> {
+    let mut spans: Vec<(String, Range<usize>)> = Vec::new();
+    let mut pos = 0;
+    for line in content.split_inclusive('\n') {
+        let is_top_level = !line.starts_with([' ', '\t', '#', '\n', '\r']) && !line.is_empty();
+        if is_top_level {
+            if let Some((_, span)) = spans.last_mut() {
+                span.end = pos;
+            }
+            if let Some((key, _)) = line.split_once(':') {
+                let key = key.trim().trim_matches(['"', '\'']);
+                spans.push((key.to_string(), pos..content.len()));
+            }
+        }
+        pos += line.len();
+    }
+    spans
+}
+
+/// Add BibLaTeX entries to a Hayagriva YAML file. The new entries are
+/// appended to the text, thus, comments and formatting of the file are kept
+pub fn append_to_yaml_file(file: &Path, bibtex: &str) -> Result<()> {
+    let new_entries = hayagriva::io::from_biblatex_str(bibtex).map_err(|errors| {
+        eyre!(
+            "Couldn't convert entry: {}",
+            errors
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    })?;
+    let mut library = Library::new();
+    for entry in new_entries.iter() {
+        library.push(entry);
+    }
+    let yaml = hayagriva::io::to_yaml_str(&library)?;
+    // Only the entries themselves are appended, not a document start
+    let yaml = yaml.strip_prefix("---\n").unwrap_or(&yaml);
+
+    let mut content = fs::read_to_string(file)?;
+    if !content.trim().is_empty() {
+        // Appending to anything but a block mapping would break the file
+        hayagriva::io::from_yaml_str(&content)?;
+        if !content.ends_with('\n') {
+            content.push('\n');
+        }
+        content.push('\n');
+    }
+    content.push_str(yaml);
+    fs::write(file, content)?;
+    Ok(())
+}
+
+fn write_entry(out: &mut String, entry: &Entry) -> Result<()> {
+    let parent = entry.parents().first();
+    let parent_type = parent.map(|p| p.entry_type());
+
+    let pubtype = match (entry.entry_type(), parent_type) {
+        (EntryType::Article, Some(EntryType::Proceedings | EntryType::Conference)) => {
+            "inproceedings"
+        }
+        (EntryType::Article, _) => "article",
+        (EntryType::Chapter, Some(EntryType::Anthology)) => "incollection",
+        (EntryType::Chapter, _) => "inbook",
+        (EntryType::Anthos, _) => "incollection",
+        (EntryType::Entry, Some(EntryType::Reference)) => "inreference",
+        (EntryType::Book, _) => "book",
+        (EntryType::Anthology, _) => "collection",
+        (EntryType::Reference, _) => "reference",
+        (EntryType::Proceedings, _) => "proceedings",
+        (EntryType::Report, _) => "report",
+        (EntryType::Thesis, _) => "thesis",
+        (EntryType::Patent, _) => "patent",
+        (EntryType::Periodical, _) => "periodical",
+        (EntryType::Manuscript, _) => "unpublished",
+        (EntryType::Web | EntryType::Blog | EntryType::Post, _) => "online",
+        _ => "misc",
+    };
+
+    let mut fields: Vec<(&str, String)> = Vec::new();
+    if let Some(authors) = entry.authors() {
+        fields.push(("author", format_persons(authors)));
+    }
+    if let Some(editors) = entry.editors() {
+        fields.push(("editor", format_persons(editors)));
+    }
+    if let Some(title) = entry.title() {
+        fields.push(("title", title.to_string()));
+    }
+    // The title of the parent names the journal or the book containing
+    // the entry
+    if let Some(parent_title) = parent.and_then(|p| p.title()) {
+        let field = match pubtype {
+            "article" => "journaltitle",
+            "misc" | "online" => "howpublished",
+            _ => "booktitle",
+        };
+        fields.push((field, parent_title.to_string()));
+    }
+    if let Some(date) = entry.map(|e| e.date()) {
+        let mut value = format!("{:04}", date.year);
+        if let Some(month) = date.month {
+            write!(value, "-{:02}", month + 1)?;
+            if let Some(day) = date.day {
+                write!(value, "-{:02}", day + 1)?;
+            }
+        }
+        fields.push(("date", value));
+    }
+    if let Some(publisher) = entry.map(|e| e.publisher()) {
+        if let Some(name) = publisher.name() {
+            fields.push(("publisher", name.to_string()));
+        }
+    }
+    if let Some(location) = entry
+        .map(|e| e.location())
+        .or_else(|| entry.map(|e| e.publisher().and_then(|p| p.location())))
+    {
+        fields.push(("location", location.to_string()));
+    }
+    if let Some(organization) = entry.map(|e| e.organization()) {
+        fields.push(("organization", organization.to_string()));
+    }
+    if let Some(volume) = entry.map(|e| e.volume()) {
+        fields.push(("volume", volume.to_string()));
+    }
+    if let Some(issue) = entry.map(|e| e.issue()) {
+        fields.push(("number", issue.to_string()));
+    }
+    if let Some(edition) = entry.edition() {
+        fields.push(("edition", edition.to_string()));
+    }
+    if let Some(pages) = entry.page_range() {
+        fields.push(("pages", pages.to_string()));
+    }
+    if let Some(doi) = entry.doi() {
+        fields.push(("doi", doi.to_string()));
+    }
+    if let Some(isbn) = entry.map(|e| e.isbn()) {
+        fields.push(("isbn", isbn.to_string()));
+    }
+    if let Some(url) = entry.url() {
+        fields.push(("url", url.value.to_string()));
+    }
+    if let Some(genre) = entry.genre() {
+        fields.push(("type", genre.to_string()));
+    }
+    if let Some(language) = entry.language() {
+        fields.push(("langid", language.to_string()));
+    }
+    if let Some(note) = entry.note() {
+        fields.push(("note", note.to_string()));
+    }
+    if let Some(abstract_text) = entry.abstract_() {
+        fields.push(("abstract", abstract_text.to_string()));
+    }
+
+    writeln!(out, "@{}{{{},", pubtype, entry.key())?;
+    for (name, value) in fields {
+        writeln!(out, "  {} = {{{}}},", name, escape_value(&value))?;
+    }
+    writeln!(out, "}}\n")?;
+    Ok(())
+}
+
+// Format names as `prefix Last, Suffix, Given` joined by `and`. Names of
+// institutions are wrapped in braces, thus, they aren't split up
+fn format_persons(persons: &[Person]) -> String {
+    persons
+        .iter()
+        .map(|p| {
+            if p.is_institutional() {
+                return format!("{{{}}}", p.name);
+            }
+            let mut name = match &p.prefix {
+                Some(prefix) => format!("{} {}", prefix, p.name),
+                None => p.name.clone(),
+            };
+            if let Some(suffix) = &p.suffix {
+                write!(name, ", {}", suffix).unwrap();
+            }
+            if let Some(given_name) = &p.given_name {
+                write!(name, ", {}", given_name).unwrap();
+            }
+            name
+        })
+        .collect::<Vec<String>>()
+        .join(" and ")
+}
+
+// Values are wrapped in braces. Thus, unbalanced braces inside of them
+// would break the entry and are removed
+fn escape_value(value: &str) -> String {
+    if braces_balanced(value) {
+        value.to_string()
+    } else {
+        value.replace(['{', '}'], "")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bibiman::bibisetup::BibiSetup;
+
+    #[test]
+    fn load_yaml() {
+        let content = fs::read_to_string("tests/hayagriva-test.yml").unwrap();
+        let library = hayagriva::io::from_yaml_str(&content).unwrap();
+        let bibtex = yaml_to_biblatex(&content).unwrap();
+        let biblio = biblatex::Bibliography::parse(&bibtex).unwrap();
+
+        assert_eq!(biblio.len(), library.len());
+        assert_eq!(
+            BibiSetup::get_authors("zygos", &biblio),
+            "Prekas, Kogias, Bugnion"
+        );
+        assert_eq!(BibiSetup::get_pubtype("zygos", &biblio), "inproceedings");
+        assert_eq!(BibiSetup::get_year("zygos", &biblio), "2017");
+        assert_eq!(
+            BibiSetup::get_weblink("zygos", &biblio).unwrap(),
+            "10.1145/3132747.3132780"
+        );
+        assert_eq!(
+            BibiSetup::get_title("omarova-libra", &biblio),
+            "There’s a Lot We Still Don’t Know About Libra"
+        );
+    }
+
+    #[test]
+    fn yaml_entry_spans() {
+        let content = "# Comment\nfirst:\n  type: book\n\n\"second\":\n  type: web\n";
+        let spans = entry_spans(content);
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0].0, "first");
+        assert_eq!(&content[spans[0].1.clone()], "first:\n  type: book\n\n");
+        assert_eq!(spans[1].0, "second");
+        assert_eq!(spans[1].1.end, content.len());
+    }
+
+    #[test]
+    fn append_yaml() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("append.yml");
+        let existing = "# My library\nfirst:\n  type: book\n  title: First # note\n";
+        fs::write(&file, existing).unwrap();
+        append_to_yaml_file(
+            &file,
+            "@article{doe2020, author = {Doe, Jane}, title = {A Title}, date = {2020}}",
+        )
+        .unwrap();
+
+        let content = fs::read_to_string(&file).unwrap();
+        // The existing text is kept as it is
+        assert!(content.starts_with(existing));
+        let biblio = biblatex::Bibliography::parse(&yaml_to_biblatex(&content).unwrap()).unwrap();
+        assert_eq!(BibiSetup::get_title("first", &biblio), "First");
+        assert_eq!(BibiSetup::get_title("doe2020", &biblio), "A Title");
+        assert_eq!(BibiSetup::get_authors("doe2020", &biblio), "Doe");
+    }
+}
@@ -0,0 +1,193 @@
+// bibiman - a TUI for managing BibLaTeX databases
+// Copyright (C) 2024  lukeflo
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+/////
+
+//! Formatted references rendered through a CSL style.
+//!
+//! The styles and locales bundled with the hayagriva crate are used, thus,
+//! no network access or installed style files are needed.
+
+use std::fs;
+use std::path::PathBuf;
+
+use biblatex::{Bibliography, Chunk};
+use color_eyre::eyre::{eyre, Result};
+use hayagriva::archive::{locales, ArchivedStyle};
+use hayagriva::citationberg::{IndependentStyle, Locale, Style};
+use hayagriva::{BibliographyDriver, BibliographyRequest, CitationItem, CitationRequest};
+
+use crate::app::expand_home;
+
+/// A CSL style together with the locales needed for rendering
+#[derive(Debug)]
+pub struct CitationFormatter {
+    style: IndependentStyle,
+    locales: Vec<Locale>,
+}
+
+impl CitationFormatter {
+    /// Load a bundled style by its name, e.g. `apa`, `chicago-author-date`
+    /// or `ieee`, or read a `.csl` file
+    pub fn new(style: &str) -> Result<Self> {
+        let style = if style.ends_with(".csl") {
+            let xml = fs::read_to_string(expand_home(&PathBuf::from(style)))?;
+            IndependentStyle::from_xml(&xml)?
+        } else {
+            match ArchivedStyle::by_name(style).map(|s| s.get()) {
+                Some(Style::Independent(style)) => style,
+                Some(Style::Dependent(_)) => {
+                    return Err(eyre!("Dependent CSL styles aren't supported: {}", style))
+                }
+                None => return Err(eyre!("Unknown citation style: {}", style)),
+            }
+        };
+        Ok(Self {
+            style,
+            locales: locales(),
+        })
+    }
+
+    /// Render the entry as plain text reference like it would appear in
+    /// the bibliography of a paper
+    pub fn format(&self, biblio: &Bibliography, citekey: &str) -> Result<String> {
+        let entry = biblio
+            .get(citekey)
+            .ok_or_else(|| eyre!("No entry with citekey {}", citekey))?;
+        let mut entry = entry.clone();
+        // The biblatex crate keeps the TeX non-breaking space `~` as it is
+        for chunks in entry.fields.values_mut() {
+            for chunk in chunks.iter_mut() {
+                if let Chunk::Normal(text) = &mut chunk.v {
+                    *text = text.replace('~', " ");
+                }
+            }
+        }
+        let mut single = Bibliography::new();
+        single.insert(entry);
+        let library = hayagriva::io::from_biblatex(&single).map_err(|errors| {
+            eyre!(
+                "{}",
+                errors
+                    .iter()
+                    .map(|e| e.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            )
+        })?;
+        let entry = library
+            .get(citekey)
+            .ok_or_else(|| eyre!("Entry {} can't be converted", citekey))?;
+
+        let mut driver = BibliographyDriver::new();
+        driver.citation(CitationRequest::from_items(
+            vec![CitationItem::with_entry(entry)],
+            &self.style,
+            &self.locales,
+        ));
+        let rendered = driver.finish(BibliographyRequest::new(&self.style, None, &self.locales));
+
+        let item = rendered
+            .bibliography
+            .and_then(|b| b.items.into_iter().next())
+            .ok_or_else(|| eyre!("The citation style has no bibliography"))?;
+        // Numeric styles like IEEE render the label `[1]` as first field
+        Ok(match item.first_field {
+            Some(label) => format!("{:#} {:#}", label, item.content),
+            None => format!("{:#}", item.content),
+        })
+    }
+}
+
+/// Formatted reference of the selected entry shown in the TUI
+#[derive(Debug, Default)]
+pub struct CitationPreview {
+    // Name or path of the style as set in the config
+    pub style: String,
+    formatter: Option<CitationFormatter>,
+    // Error message if the style couldn't be loaded
+    error: Option<String>,
+    // Citekey and reference of the last rendered entry. Rendering is too
+    // slow to run it for every frame
+    cache: Option<(String, Result<String, String>)>,
+}
+
+impl CitationPreview {
+    pub fn new(style: &str) -> Self {
+        match CitationFormatter::new(style) {
+            Ok(formatter) => Self {
+                style: style.to_string(),
+                formatter: Some(formatter),
+                ..Default::default()
+            },
+            Err(e) => Self {
+                style: style.to_string(),
+                error: Some(e.to_string()),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Formatted reference of the entry, or an error message
+    pub fn reference(&mut self, biblio: &Bibliography, citekey: &str) -> Result<String, String> {
+        if let Some((key, reference)) = &self.cache {
+            if key == citekey {
+                return reference.clone();
+            }
+        }
+        let reference = match (&self.formatter, &self.error) {
+            (Some(formatter), _) => formatter.format(biblio, citekey).map_err(|e| e.to_string()),
+            (None, Some(error)) => Err(error.clone()),
+            (None, None) => Err("No citation style set".to_string()),
+        };
+        self.cache = Some((citekey.to_string(), reference.clone()));
+        reference
+    }
+
+    /// Forget the cached reference, e.g. after the entry was edited
+    pub fn clear_cache(&mut self) {
+        self.cache = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_reference() {
+        let content = fs::read_to_string("tests/biblatex-test.bib").unwrap();
+        let biblio = Bibliography::parse(&content).unwrap();
+
+        let apa = CitationFormatter::new("apa").unwrap();
+        assert_eq!(
+            apa.format(&biblio, "bertram").unwrap(),
+            "Bertram, A., & Wentworth, R. (1996). Gromov invariants for holomorphic maps on Riemann surfaces. J. Amer. Math. Soc., 9(2), 529–571."
+        );
+        let ieee = CitationFormatter::new("ieee").unwrap();
+        assert!(ieee
+            .format(&biblio, "bertram")
+            .unwrap()
+            .starts_with("[1] A. Bertram"));
+        assert!(CitationFormatter::new("chicago-author-date").is_ok());
+        assert!(CitationFormatter::new("no-such-style").is_err());
+
+        let mut preview = CitationPreview::new("no-such-style");
+        assert_eq!(
+            preview.reference(&biblio, "bertram"),
+            Err("Unknown citation style: no-such-style".to_string())
+        );
+    }
+}
@@ -0,0 +1,320 @@
+// bibiman - a TUI for managing BibLaTeX databases
+// Copyright (C) 2024  lukeflo
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+/////
+
+//! Citekeys built from a pattern like `{author_last}{year}{title_first_word}`.
+//!
+//! The values of the placeholders are reduced to letters and digits. If a
+//! citekey is already taken, a letter is appended: `doe2020a`, `doe2020b`…
+//! File names in the library directory are built the same way.
+
+use biblatex::{ChunksExt, DateValue, Entry, PermissiveType};
+use color_eyre::eyre::{eyre, Result};
+use unicode_normalization::UnicodeNormalization;
+
+use super::bibisetup::BibiSetup;
+use super::newentry::invalid_citekey_char;
+use crate::config::Citekey;
+
+pub const PLACEHOLDERS: [&str; 4] = ["author_last", "year", "title_first_word", "entry_type"];
+
+// File names may contain the citekey, too
+const FILE_PLACEHOLDERS: [&str; 5] = [
+    "citekey",
+    "author_last",
+    "year",
+    "title_first_word",
+    "entry_type",
+];
+
+// Words skipped when looking for the first word of the title
+const STOPWORDS: [&str; 24] = [
+    "a", "an", "the", "on", "of", "in", "and", "to", "for", "with", "at", "by", "from", "der",
+    "die", "das", "ein", "eine", "le", "la", "les", "un", "une", "el",
+];
+
+#[derive(Debug, PartialEq, Eq)]
+enum Part<'a> {
+    Text(&'a str),
+    Placeholder(&'a str),
+}
+
+fn parse<'a>(pattern: &'a str, known: &[&str]) -> Result<Vec<Part<'a>>> {
+    let mut parts = Vec::new();
+    let mut rest = pattern;
+    while let Some(open) = rest.find('{') {
+        if open > 0 {
+            parts.push(Part::Text(&rest[..open]));
+        }
+        let close = rest[open..]
+            .find('}')
+            .ok_or_else(|| eyre!("Unclosed placeholder in \"{}\"", pattern))?;
+        let name = &rest[open + 1..open + close];
+        if !known.contains(&name) {
+            return Err(eyre!(
+                "Unknown placeholder {{{}}}, possible are: {}",
+                name,
+                known
+                    .iter()
+                    .map(|p| format!("{{{}}}", p))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ));
+        }
+        parts.push(Part::Placeholder(name));
+        rest = &rest[open + close + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Text(rest));
+    }
+    Ok(parts)
+}
+
+/// Check that the pattern only contains known placeholders
+pub fn check_pattern(pattern: &str) -> Result<()> {
+    parse(pattern, &PLACEHOLDERS).map(|_| ())
+}
+
+/// Check the pattern of file names, which may contain `{citekey}`, too
+pub fn check_file_pattern(pattern: &str) -> Result<()> {
+    parse(pattern, &FILE_PLACEHOLDERS).map(|_| ())
+}
+
+/// Citekey for the entry built from the pattern. Placeholders without a
+/// value are left empty. Isn't checked against existing citekeys
+pub fn format_citekey(entry: &Entry, cfg: &Citekey) -> String {
+    let mut citekey = String::new();
+    for part in parse(&cfg.pattern, &PLACEHOLDERS).unwrap_or_default() {
+        let value = match part {
+            Part::Text(text) => text.to_string(),
+            Part::Placeholder(name) => placeholder_value(entry, name)
+                .chars()
+                .filter(|c| c.is_alphanumeric())
+                .collect(),
+        };
+        citekey.push_str(&value);
+    }
+    if cfg.ascii {
+        citekey = fold_to_ascii(&citekey);
+    }
+    if cfg.lowercase {
+        citekey = citekey.to_lowercase();
+    }
+    citekey
+        .chars()
+        .filter(|c| !invalid_citekey_char(*c))
+        .collect()
+}
+
+/// File name for the entry built from the pattern, without extension.
+/// Path separators are removed, thus, the file stays in the directory
+pub fn format_filename(entry: &Entry, pattern: &str) -> String {
+    let mut name = String::new();
+    for part in parse(pattern, &FILE_PLACEHOLDERS).unwrap_or_default() {
+        match part {
+            Part::Text(text) => name.push_str(text),
+            Part::Placeholder("citekey") => name.push_str(&entry.key),
+            Part::Placeholder(placeholder) => name.extend(
+                placeholder_value(entry, placeholder)
+                    .chars()
+                    .filter(|c| c.is_alphanumeric()),
+            ),
+        }
+    }
+    name.chars()
+        .filter(|c| !matches!(c, '/' | '\\' | '\0'))
+        .collect()
+}
+
+/// Append a letter to the citekey if it's already taken
+pub fn unique_citekey(citekey: &str, taken: impl Fn(&str) -> bool) -> String {
+    if !taken(citekey) {
+        return citekey.to_string();
+    }
+    (0..)
+        .map(|n| format!("{}{}", citekey, suffix(n)))
+        .find(|key| !taken(key))
+        .unwrap()
+}
+
+/// Citekeys of the passed entries which differ from the pattern, mapped to
+/// the generated ones. Entries for which no citekey can be built are
+/// skipped
+pub fn regenerate_citekeys(
+    biblio: &BibiSetup,
+    citekeys: &[String],
+    cfg: &Citekey,
+) -> Vec<(String, String)> {
+    let generated: Vec<(&String, String)> = citekeys
+        .iter()
+        .filter_map(|key| {
+            let entry = biblio.bibliography.get(key)?;
+            Some((key, format_citekey(entry, cfg))).filter(|(_, new)| !new.is_empty())
+        })
+        .collect();
+
+    // Keys of the other entries stay taken. Entries already matching the
+    // pattern keep their key, the others get a free one
+    let mut taken: Vec<String> = biblio
+        .citekeys
+        .iter()
+        .filter(|key| !generated.iter().any(|(old, _)| old == key))
+        .cloned()
+        .collect();
+    taken.extend(
+        generated
+            .iter()
+            .filter(|(old, new)| old == &new)
+            .map(|(old, _)| old.to_string()),
+    );
+    let mut renames = Vec::new();
+    for (old, new) in generated.into_iter().filter(|(old, new)| old != &new) {
+        let new = unique_citekey(&new, |key| taken.iter().any(|t| t == key));
+        taken.push(new.clone());
+        if &new != old {
+            renames.push((old.clone(), new));
+        }
+    }
+    renames
+}
+
+fn placeholder_value(entry: &Entry, name: &str) -> String {
+    match name {
+        "author_last" => entry
+            .author()
+            .ok()
+            .filter(|authors| !authors.is_empty())
+            .or_else(|| {
+                entry
+                    .editors()
+                    .ok()
+                    .and_then(|editors| editors.into_iter().next())
+                    .map(|(editors, _)| editors)
+            })
+            .and_then(|persons| persons.into_iter().next())
+            .map(|person| person.name)
+            .unwrap_or_default(),
+        "year" => match entry.date() {
+            Ok(PermissiveType::Typed(date)) => match date.value {
+                DateValue::At(start)
+                | DateValue::After(start)
+                | DateValue::Before(start)
+                | DateValue::Between(start, _) => start.year.to_string(),
+            },
+            Ok(PermissiveType::Chunks(chunks)) => chunks.format_verbatim(),
+            Err(_) => String::new(),
+        },
+        "title_first_word" => entry
+            .title()
+            .map(|title| title.format_verbatim())
+            .unwrap_or_default()
+            .split(|c: char| !c.is_alphanumeric())
+            .find(|word| !word.is_empty() && !STOPWORDS.contains(&word.to_lowercase().as_str()))
+            .unwrap_or_default()
+            .to_string(),
+        "entry_type" => entry.entry_type.to_string(),
+        _ => String::new(),
+    }
+}
+
+// Letters which aren't split into a base letter and accents are replaced
+// explicitly, all other non-ASCII chars are dropped
+fn fold_to_ascii(text: &str) -> String {
+    let mut folded = String::new();
+    for c in text.nfkd() {
+        match c {
+            'ß' => folded.push_str("ss"),
+            'æ' => folded.push_str("ae"),
+            'Æ' => folded.push_str("AE"),
+            'œ' => folded.push_str("oe"),
+            'Œ' => folded.push_str("OE"),
+            'ø' => folded.push('o'),
+            'Ø' => folded.push('O'),
+            'ł' => folded.push('l'),
+            'Ł' => folded.push('L'),
+            'đ' => folded.push('d'),
+            'Đ' => folded.push('D'),
+            'þ' => folded.push_str("th"),
+            'Þ' => folded.push_str("Th"),
+            c if c.is_ascii() => folded.push(c),
+            _ => {}
+        }
+    }
+    folded
+}
+
+// a, b, …, z, aa, ab, …
+fn suffix(mut n: usize) -> String {
+    let mut suffix = Vec::new();
+    loop {
+        suffix.push((b'a' + (n % 26) as u8) as char);
+        if n < 26 {
+            break;
+        }
+        n = n / 26 - 1;
+    }
+    suffix.into_iter().rev().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use biblatex::Bibliography;
+
+    #[test]
+    fn generate_citekeys() {
+        let bib = Bibliography::parse(
+            "@article{x,\n  author = {Müller, Jörg and Doe, Jane},\n  title = {The Øresund Bridge},\n  date = {2020-05},\n}\n\
+             @book{y,\n  editor = {Łukasz, Anna},\n  title = {On Tests},\n  year = {1999},\n}\n",
+        )
+        .unwrap();
+        let cfg = Citekey::default();
+        assert_eq!(
+            format_citekey(bib.get("x").unwrap(), &cfg),
+            "muller2020oresund"
+        );
+        assert_eq!(
+            format_citekey(bib.get("y").unwrap(), &cfg),
+            "lukasz1999tests"
+        );
+
+        let cfg = Citekey {
+            pattern: String::from("{author_last}:{entry_type}-{year}"),
+            lowercase: false,
+            ascii: false,
+        };
+        assert_eq!(
+            format_citekey(bib.get("x").unwrap(), &cfg),
+            "Müller:article-2020"
+        );
+        assert!(check_pattern("{author_last}{title}").is_err());
+        assert!(check_pattern("{year").is_err());
+        assert!(check_pattern("{citekey}").is_err());
+        assert!(check_file_pattern("{citekey}").is_ok());
+        assert_eq!(
+            format_filename(bib.get("y").unwrap(), "{year}/{citekey} {title_first_word}"),
+            "1999y Tests"
+        );
+
+        let taken = ["doe2020", "doe2020a"];
+        assert_eq!(
+            unique_citekey("doe2020", |k| taken.contains(&k)),
+            "doe2020b"
+        );
+        assert_eq!(suffix(25), "z");
+        assert_eq!(suffix(26), "aa");
+    }
+}
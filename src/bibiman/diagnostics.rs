@@ -0,0 +1,329 @@
+// bibiman - a TUI for managing BibLaTeX databases
+// Copyright (C) 2024  lukeflo
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+/////
+
+//! Problems found while loading the bibfiles.
+//!
+//! Entries the biblatex parser rejects are blanked out and parsing is
+//! retried. Thus, a single malformed entry doesn't prevent browsing all
+//! other entries. Blanking keeps the byte offsets and line numbers of the
+//! remaining text, which are needed to report the position of further
+//! problems.
+
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use biblatex::{Bibliography, ParseError, RawBibliography};
+
+use super::bibedit;
+
+/// Problem found in a bibfile. The affected entry or file is skipped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub file: PathBuf,
+    // Line of the problem, starting at 1. Unknown for unreadable files and
+    // Hayagriva YAML files, which are converted before parsing
+    pub line: Option<usize>,
+    // Citekey of the skipped entry, if it could be determined
+    pub citekey: Option<String>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(file: &Path, message: &str) -> Self {
+        Self {
+            file: file.to_path_buf(),
+            line: None,
+            citekey: None,
+            message: message.to_string(),
+        }
+    }
+
+    /// Position of the problem, e.g. `refs.bib:12`
+    pub fn location(&self) -> String {
+        let file = self.file.file_name().unwrap_or_default().to_string_lossy();
+        match self.line {
+            Some(line) => format!("{}:{}", file, line),
+            None => file.to_string(),
+        }
+    }
+
+    /// Position, message and skipped entry in a single line
+    pub fn summary(&self) -> String {
+        match &self.citekey {
+            Some(citekey) => format!(
+                "{}: {} (skipped {})",
+                self.location(),
+                self.message,
+                citekey
+            ),
+            None => format!("{}: {}", self.location(), self.message),
+        }
+    }
+}
+
+/// Text of a single bibfile as passed to the parser
+#[derive(Debug)]
+pub struct BibSource {
+    pub file: PathBuf,
+    pub content: String,
+    // Converted from Hayagriva YAML, thus, lines don't match the file
+    pub yaml: bool,
+}
+
+/// Parse the joined bibfiles. Every entry the parser rejects is blanked out
+/// and reported, until the remaining text can be parsed
+pub fn parse_sources(
+    sources: &mut [BibSource],
+    diagnostics: &mut Vec<Diagnostic>,
+) -> (String, Bibliography) {
+    // Syntax errors are searched in each entry on its own, thus, unbalanced
+    // braces don't spill into the following entries or files
+    for source in sources.iter_mut() {
+        for (citekey, range, message) in broken_entries(&source.content) {
+            diagnostics.push(Diagnostic {
+                file: source.file.clone(),
+                line: (!source.yaml).then(|| line_of(&source.content, range.start)),
+                citekey,
+                message,
+            });
+            blank(&mut source.content, range);
+        }
+        // Remaining errors outside of entries, e.g. in `@string` blocks
+        while let Err(error) = RawBibliography::parse(&source.content) {
+            if !skip_broken(source, &error, error.span.start, diagnostics) {
+                break;
+            }
+        }
+    }
+
+    // Abbreviations and cross references can only be resolved for all files
+    // together
+    loop {
+        let joined = sources
+            .iter()
+            .map(|s| s.content.as_str())
+            .collect::<Vec<&str>>()
+            .join("\n");
+        let error = match Bibliography::parse(&joined) {
+            Ok(bibliography) => return (joined, bibliography),
+            Err(e) => e,
+        };
+
+        // Find the file the error occurred in
+        let mut offset = 0;
+        let source = sources.iter_mut().find(|s| {
+            if error.span.start <= offset + s.content.len() {
+                true
+            } else {
+                offset += s.content.len() + 1;
+                false
+            }
+        });
+        let progress = match source {
+            Some(source) => skip_broken(source, &error, error.span.start - offset, diagnostics),
+            None => false,
+        };
+        if !progress {
+            return (String::new(), Bibliography::new());
+        }
+    }
+}
+
+// Blank the entry with the error at `pos` and report it. Returns false if
+// there is nothing left to skip
+fn skip_broken(
+    source: &mut BibSource,
+    error: &ParseError,
+    pos: usize,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> bool {
+    let (citekey, mut range) = broken_range(&source.content, pos);
+    // Blank the whole file if the broken part can't be narrowed down
+    if source.content[range.clone()].trim().is_empty() {
+        if source.content.trim().is_empty() {
+            return false;
+        }
+        range = 0..source.content.len();
+    }
+
+    diagnostics.push(Diagnostic {
+        file: source.file.clone(),
+        line: (!source.yaml).then(|| line_of(&source.content, range.start)),
+        citekey,
+        message: capitalize(&error.kind.to_string()),
+    });
+    blank(&mut source.content, range);
+    true
+}
+
+// Citekey, range and syntax error of all entries which can't be parsed
+fn broken_entries(content: &str) -> Vec<(Option<String>, Range<usize>, String)> {
+    let entries = bibedit::entries(content);
+    let mut broken = Vec::new();
+    let mut gap_start = 0;
+    for (citekey, span) in &entries {
+        broken.extend(unclosed_entry(content, gap_start..span.range.start));
+        if let Err(error) = RawBibliography::parse(&content[span.range.clone()]) {
+            broken.push((
+                Some(citekey.clone()),
+                span.range.clone(),
+                capitalize(&error.kind.to_string()),
+            ));
+        }
+        gap_start = span.range.end;
+    }
+    broken.extend(unclosed_entry(content, gap_start..content.len()));
+    broken
+}
+
+// An entry with unbalanced delimiters isn't recognized as entry. It is
+// expected to start with `@` at the beginning of a line between the
+// recognized entries, and reaches until the next recognized one
+fn unclosed_entry(
+    content: &str,
+    gap: Range<usize>,
+) -> Option<(Option<String>, Range<usize>, String)> {
+    let mut pos = gap.start;
+    for line in content[gap.clone()].split_inclusive('\n') {
+        let start = pos + line.len() - line.trim_start().len();
+        pos += line.len();
+        let Some(head) = line.trim_start().strip_prefix('@') else {
+            continue;
+        };
+        let Some((entry_type, rest)) = head.split_once(['{', '(']) else {
+            continue;
+        };
+        let entry_type = entry_type.trim().to_lowercase();
+        if entry_type.is_empty() || ["string", "preamble", "comment"].contains(&entry_type.as_str())
+        {
+            continue;
+        }
+        let citekey = rest
+            .split(',')
+            .next()
+            .map(|key| key.trim().to_string())
+            .filter(|key| !key.is_empty() && !key.contains(char::is_whitespace));
+        return Some((citekey, start..gap.end, "Entry isn't closed".to_string()));
+    }
+    None
+}
+
+// Citekey and range of the entry the error at `pos` belongs to
+fn broken_range(content: &str, pos: usize) -> (Option<String>, Range<usize>) {
+    let entries = bibedit::entries(content);
+    if let Some((citekey, span)) = entries.iter().find(|(_, s)| s.range.contains(&pos)) {
+        return (Some(citekey.clone()), span.range.clone());
+    }
+
+    // An entry with unbalanced delimiters isn't recognized, thus, it starts
+    // at the last `@` before the error outside of the recognized entries. It
+    // reaches until the next recognized entry
+    let pos = pos.min(content.len());
+    let in_entry = |idx: usize| entries.iter().any(|(_, s)| s.range.contains(&idx));
+    if let Some(start) = content[..pos]
+        .rmatch_indices('@')
+        .map(|(idx, _)| idx)
+        .find(|idx| !in_entry(*idx))
+    {
+        let end = entries
+            .iter()
+            .map(|(_, s)| s.range.start)
+            .find(|idx| *idx > start)
+            .unwrap_or(content.len());
+        return (None, start..end);
+    }
+
+    // Otherwise, skip the line with the error
+    let start = content[..pos].rfind('\n').map_or(0, |idx| idx + 1);
+    let end = content[pos..]
+        .find('\n')
+        .map_or(content.len(), |idx| pos + idx);
+    (None, start..end)
+}
+
+/// Replace the text by spaces, keeping line breaks and byte offsets
+pub fn blank(content: &mut String, range: Range<usize>) {
+    let blanked: String = content[range.clone()]
+        .chars()
+        .map(|c| {
+            if c == '\n' {
+                "\n".to_string()
+            } else {
+                " ".repeat(c.len_utf8())
+            }
+        })
+        .collect();
+    content.replace_range(range, &blanked);
+}
+
+fn newlines(text: &str) -> usize {
+    text.matches('\n').count()
+}
+
+fn line_of(content: &str, pos: usize) -> usize {
+    newlines(&content[..pos]) + 1
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skip_broken_entries() {
+        let first = "@article{good,\n  title = {Good},\n}\n\n@article{bad,\n  title = {Bad,\n}\n\n@book{after,\n  title = {Äfter},\n}\n";
+        let second = "@misc{abbrev,\n  title = unknown,\n}\n@misc{fine,\n  title = {Fine},\n}\n";
+        let mut sources = vec![
+            BibSource {
+                file: PathBuf::from("first.bib"),
+                content: first.to_string(),
+                yaml: false,
+            },
+            BibSource {
+                file: PathBuf::from("second.bib"),
+                content: second.to_string(),
+                yaml: false,
+            },
+        ];
+        let mut diagnostics = Vec::new();
+        let (joined, bibliography) = parse_sources(&mut sources, &mut diagnostics);
+
+        let keys: Vec<&str> = bibliography.keys().collect();
+        assert_eq!(keys, vec!["good", "after", "fine"]);
+        assert_eq!(joined.len(), first.len() + second.len() + 1);
+        assert_eq!(
+            joined.lines().count(),
+            (first.to_string() + "\n" + second).lines().count()
+        );
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(
+            diagnostics[0].summary(),
+            "first.bib:5: Entry isn't closed (skipped bad)"
+        );
+        assert_eq!(
+            diagnostics[1].summary(),
+            "second.bib:1: Unknown abbreviation \"unknown\" (skipped abbrev)"
+        );
+    }
+}
@@ -0,0 +1,438 @@
+// bibiman - a TUI for managing BibLaTeX databases
+// Copyright (C) 2024  lukeflo
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+/////
+
+//! Detection of duplicate entries and merging them into one.
+//!
+//! Entries are read directly from the source text of the bibfiles. Thus,
+//! entries with the same citekey are found, too, although only the first
+//! of them can be loaded into the bibliography.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::ops::Range;
+use std::path::PathBuf;
+
+use color_eyre::eyre::{eyre, Result};
+
+use super::bibedit::{self, strip_delimiters};
+use super::bibyaml::is_yaml;
+
+/// Why entries are considered duplicates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DuplicateReason {
+    Citekey,
+    Doi,
+    TitleYear,
+}
+
+impl DuplicateReason {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Citekey => "citekey",
+            Self::Doi => "DOI",
+            Self::TitleYear => "title and year",
+        }
+    }
+}
+
+/// An entry as it is written in the bibfile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceEntry {
+    pub citekey: String,
+    pub entry_type: String,
+    pub file: PathBuf,
+    // Byte range of the entry inside the file
+    pub range: Range<usize>,
+    pub line: usize,
+    // Lowercased field names with their raw values
+    pub fields: Vec<(String, String)>,
+    // Source text of the whole entry
+    pub text: String,
+}
+
+impl SourceEntry {
+    /// Citekey, type and position, e.g. `doe2020 (article, refs.bib:12)`
+    pub fn label(&self) -> String {
+        format!(
+            "{} ({}, {}:{})",
+            self.citekey,
+            self.entry_type,
+            self.file.file_name().unwrap_or_default().to_string_lossy(),
+            self.line
+        )
+    }
+
+    fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn doi(&self) -> Option<String> {
+        normalize_doi(strip_delimiters(self.field("doi")?))
+    }
+
+    // Title reduced to lowercase letters and digits, together with the year
+    fn title_year(&self) -> Option<(String, String)> {
+        let title: String = self
+            .field("title")?
+            .chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect();
+        let year = self
+            .field("year")
+            .or_else(|| self.field("date"))
+            .and_then(|y| strip_delimiters(y).get(..4))?
+            .to_string();
+        (!title.is_empty()).then_some((title, year))
+    }
+}
+
+/// Entries which are considered to be the same
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateGroup {
+    pub reasons: BTreeSet<DuplicateReason>,
+    pub entries: Vec<SourceEntry>,
+}
+
+impl DuplicateGroup {
+    /// Citekeys and reasons, e.g. `doe2020, doe_2020 (DOI, title and year)`
+    pub fn summary(&self) -> String {
+        let mut citekeys: Vec<&str> = Vec::new();
+        for entry in &self.entries {
+            if !citekeys.contains(&entry.citekey.as_str()) {
+                citekeys.push(&entry.citekey);
+            }
+        }
+        format!(
+            "{} ({})",
+            citekeys.join(", "),
+            self.reasons
+                .iter()
+                .map(|r| r.name())
+                .collect::<Vec<&str>>()
+                .join(", ")
+        )
+    }
+}
+
+/// DOI without resolver prefix, lowercased. Thus, the same DOI written in
+/// different ways is recognized
+pub fn normalize_doi(doi: &str) -> Option<String> {
+    let doi = doi.trim().to_lowercase();
+    let doi = doi
+        .trim_start_matches("https://doi.org/")
+        .trim_start_matches("http://dx.doi.org/")
+        .trim_start_matches("doi:");
+    (!doi.is_empty()).then(|| doi.to_string())
+}
+
+/// Read all entries of the bibfiles. YAML files are skipped, since they
+/// can't be edited in place
+pub fn read_entries(files: &[PathBuf]) -> Result<Vec<SourceEntry>> {
+    let mut entries = Vec::new();
+    for file in files.iter().filter(|f| !is_yaml(f)) {
+        let content = fs::read_to_string(file)?;
+        for (citekey, span) in bibedit::entries(&content) {
+            let entry_type = content[span.range.start + 1..span.key_end]
+                .split(['{', '('])
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_lowercase();
+            entries.push(SourceEntry {
+                fields: span
+                    .fields
+                    .iter()
+                    .map(|f| (f.name.to_lowercase(), content[f.value.clone()].to_string()))
+                    .collect(),
+                entry_type,
+                file: file.clone(),
+                line: content[..span.range.start].matches('\n').count() + 1,
+                text: content[span.range.clone()].to_string(),
+                range: span.range,
+                citekey,
+            });
+        }
+    }
+    Ok(entries)
+}
+
+/// Group entries with identical citekey, DOI or normalized title and year.
+/// Entries linked through different reasons end up in the same group
+pub fn find_duplicates(entries: Vec<SourceEntry>) -> Vec<DuplicateGroup> {
+    let mut parent: Vec<usize> = (0..entries.len()).collect();
+    let mut reasons: Vec<BTreeSet<DuplicateReason>> = vec![BTreeSet::new(); entries.len()];
+
+    fn root(parent: &mut [usize], mut idx: usize) -> usize {
+        while parent[idx] != idx {
+            parent[idx] = parent[parent[idx]];
+            idx = parent[idx];
+        }
+        idx
+    }
+
+    let keys: [(DuplicateReason, Vec<Option<String>>); 3] = [
+        (
+            DuplicateReason::Citekey,
+            entries.iter().map(|e| Some(e.citekey.clone())).collect(),
+        ),
+        (
+            DuplicateReason::Doi,
+            entries.iter().map(|e| e.doi()).collect(),
+        ),
+        (
+            DuplicateReason::TitleYear,
+            entries
+                .iter()
+                .map(|e| e.title_year().map(|(t, y)| format!("{} {}", t, y)))
+                .collect(),
+        ),
+    ];
+    for (reason, values) in keys {
+        // First entry with the value
+        let mut first: HashMap<&str, usize> = HashMap::new();
+        for (i, value) in values.iter().enumerate() {
+            let Some(value) = value else {
+                continue;
+            };
+            if let Some(j) = first.get(value.as_str()).copied() {
+                let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                parent[a] = b;
+                let merged: BTreeSet<DuplicateReason> =
+                    reasons[a].union(&reasons[b]).cloned().collect();
+                reasons[b] = merged;
+                reasons[b].insert(reason);
+            } else {
+                first.insert(value, i);
+            }
+        }
+    }
+
+    let mut groups: Vec<(usize, DuplicateGroup)> = Vec::new();
+    for (i, entry) in entries.into_iter().enumerate() {
+        let r = root(&mut parent, i);
+        match groups.iter_mut().find(|(root, _)| *root == r) {
+            Some((_, group)) => group.entries.push(entry),
+            None => groups.push((
+                r,
+                DuplicateGroup {
+                    reasons: reasons[r].clone(),
+                    entries: vec![entry],
+                },
+            )),
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(_, group)| group)
+        .filter(|group| group.entries.len() > 1)
+        .collect()
+}
+
+/// Merge of a group of duplicates into one entry, which is done step by
+/// step: first the entry to keep is chosen, afterwards one value for every
+/// field the entries disagree on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Merge {
+    pub group: DuplicateGroup,
+    // Index of the entry which is kept
+    pub base: usize,
+    // Fields with differing values and the candidates to choose from
+    pub conflicts: Vec<(String, Vec<String>)>,
+    // Values set for the kept entry
+    pub chosen: Vec<(String, String)>,
+}
+
+impl Merge {
+    pub fn new(group: DuplicateGroup) -> Self {
+        Self {
+            group,
+            base: 0,
+            conflicts: Vec::new(),
+            chosen: Vec::new(),
+        }
+    }
+
+    /// Set the entry which is kept and collect the fields to choose a value
+    /// for. A field which only one of the other entries has is taken over
+    /// without asking
+    pub fn set_base(&mut self, base: usize) {
+        self.base = base;
+        self.conflicts.clear();
+        self.chosen.clear();
+
+        let mut entries: Vec<&SourceEntry> = self.group.entries.iter().collect();
+        let base_entry = entries.remove(base);
+        entries.insert(0, base_entry);
+        let mut names: Vec<&str> = Vec::new();
+        for entry in &entries {
+            for (name, _) in &entry.fields {
+                if !names.contains(&name.as_str()) {
+                    names.push(name);
+                }
+            }
+        }
+
+        for name in names {
+            let mut values: Vec<String> = Vec::new();
+            for value in entries.iter().filter_map(|e| e.field(name)) {
+                if !values.iter().any(|v| v.trim() == value.trim()) {
+                    values.push(value.to_string());
+                }
+            }
+            match values.len() {
+                1 if base_entry.field(name).is_none() => {
+                    self.chosen.push((name.to_string(), values.remove(0)))
+                }
+                1 => {}
+                _ => self.conflicts.push((name.to_string(), values)),
+            }
+        }
+    }
+
+    /// Field the next value has to be chosen for, together with the
+    /// candidates
+    pub fn next_conflict(&self) -> Option<&(String, Vec<String>)> {
+        self.conflicts.first()
+    }
+
+    /// Choose the value of the current conflict with the passed index
+    pub fn choose(&mut self, idx: usize) {
+        if self.conflicts.is_empty() {
+            return;
+        }
+        let (name, mut values) = self.conflicts.remove(0);
+        if idx < values.len() {
+            self.chosen.push((name, values.remove(idx)));
+        }
+    }
+
+    /// Write the merged entry in place of the kept one and remove all other
+    /// entries of the group from their files
+    pub fn write(&self) -> Result<String> {
+        let base = &self.group.entries[self.base];
+        let mut merged = base.text.clone();
+        for (name, value) in &self.chosen {
+            // Values are taken over as written, thus, macros aren't wrapped in
+            // braces
+            merged = bibedit::set_raw_field(&merged, &base.citekey, name, value)?;
+        }
+
+        let mut files: Vec<&PathBuf> = self.group.entries.iter().map(|e| &e.file).collect();
+        files.sort();
+        files.dedup();
+        for file in files {
+            let mut content = fs::read_to_string(file)?;
+            let mut edits: Vec<(Range<usize>, &str)> = Vec::new();
+            for (i, entry) in self.group.entries.iter().enumerate() {
+                if &entry.file != file {
+                    continue;
+                }
+                if content.get(entry.range.clone()) != Some(entry.text.as_str()) {
+                    return Err(eyre!(
+                        "{} was changed in the meantime",
+                        file.to_string_lossy()
+                    ));
+                }
+                if i == self.base {
+                    edits.push((entry.range.clone(), &merged));
+                } else {
+                    edits.push((bibedit::removal_range(&content, entry.range.clone()), ""));
+                }
+            }
+            // Start with the last edit, thus, the other ranges stay valid
+            edits.sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+            for (range, text) in edits {
+                content.replace_range(range, text);
+            }
+            fs::write(file, content)?;
+        }
+        Ok(base.citekey.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bibiman::bibisetup::BibiSetup;
+
+    #[test]
+    fn find_and_merge_duplicates() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("first.bib");
+        let second = dir.path().join("second.bib");
+        fs::write(
+            &first,
+            "@article{doe,\n  author = {Doe, Jane},\n  title = {A Title},\n  date = {2020},\n}\n\n@book{single,\n  title = {Single},\n}\n",
+        )
+        .unwrap();
+        fs::write(
+            &second,
+            "@article{doe,\n  author = {Doe, J.},\n  title = {A title},\n  doi = {10.1000/182},\n  month = jan,\n  note = \"Part \" # jan,\n  date = {2020},\n}\n\n@misc{doe_2020,\n  title = {A Title!},\n  year = {2020},\n  doi = {https://doi.org/10.1000/182},\n}\n",
+        )
+        .unwrap();
+        let files = vec![first.clone(), second.clone()];
+
+        // Only the first entry with the same citekey is loaded
+        let diagnostics = BibiSetup::new(&files).diagnostics;
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].summary(),
+            "second.bib:1: Duplicate citekey doe, first defined at first.bib:1 (skipped doe)"
+        );
+
+        let groups = find_duplicates(read_entries(&files).unwrap());
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].entries.len(), 3);
+        assert_eq!(
+            groups[0].summary(),
+            "doe, doe_2020 (citekey, DOI, title and year)"
+        );
+
+        let mut merge = Merge::new(groups[0].clone());
+        merge.set_base(0);
+        // Month, note and year are only set in one of the other entries
+        assert_eq!(
+            merge.chosen,
+            vec![
+                ("month".to_string(), "jan".to_string()),
+                ("note".to_string(), "\"Part \" # jan".to_string()),
+                ("year".to_string(), "{2020}".to_string()),
+            ]
+        );
+        assert_eq!(merge.next_conflict().unwrap().0, "author");
+        merge.choose(0);
+        assert_eq!(merge.next_conflict().unwrap().0, "title");
+        merge.choose(1);
+        assert_eq!(merge.next_conflict().unwrap().0, "doi");
+        merge.choose(0);
+        assert!(merge.next_conflict().is_none());
+        assert_eq!(merge.write().unwrap(), "doe");
+
+        let first_content = fs::read_to_string(&first).unwrap();
+        assert!(first_content
+            .starts_with("@article{doe,\n  author = {Doe, Jane},\n  title = {A title},"));
+        assert!(first_content.contains("doi = {10.1000/182}"));
+        // Macros and concatenations are written unchanged
+        assert!(first_content.contains("month = jan,\n  note = \"Part \" # jan,"));
+        assert!(first_content.contains("@book{single,"));
+        assert!(bibedit::entries(&fs::read_to_string(&second).unwrap()).is_empty());
+    }
+}
@@ -15,12 +15,18 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 /////
 
+use std::collections::BTreeSet;
 use std::ffi::{OsStr, OsString};
+use std::ops::RangeInclusive;
+use std::sync::Arc;
 
-use crate::bibiman::bibisetup::BibiData;
+use crate::bibiman::attach;
+use crate::bibiman::bibisetup::{BibiData, EntrySource};
 use ratatui::widgets::{ScrollbarState, TableState};
+use serde::Deserialize;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum EntryTableColumn {
     Authors,
     Title,
@@ -33,6 +39,8 @@ pub enum EntryTableColumn {
 pub struct EntryTable {
     pub entry_table_items: Vec<EntryTableItem>,
     pub entry_table_at_search_start: Vec<EntryTableItem>,
+    // Entries listed before filtering by keywords
+    pub entry_table_at_filter_start: Vec<EntryTableItem>,
     pub entry_table_selected_column: EntryTableColumn,
     pub entry_table_sorted_by_col: EntryTableColumn,
     pub entry_table_reversed_sort: bool,
@@ -40,28 +48,44 @@ pub struct EntryTable {
     pub entry_scroll_state: ScrollbarState,
     pub entry_info_scroll: u16,
     pub entry_info_scroll_state: ScrollbarState,
+    // Citekeys of marked entries. Citekeys are used instead of indices,
+    // thus, marks survive sorting, searching and filtering
+    pub marked: BTreeSet<String>,
+    // Citekey of the entry at which the visual range was started
+    pub visual_anchor: Option<String>,
 }
 
 impl EntryTable {
-    pub fn new(entry_list: &[BibiData]) -> Self {
+    pub fn new(entry_list: &[BibiData], sort_col: &EntryTableColumn) -> Self {
         let entry_table_items = Self::set_entry_table(entry_list);
+        let col_idx = match sort_col {
+            EntryTableColumn::Authors => 0,
+            EntryTableColumn::Title => 1,
+            EntryTableColumn::Year => 2,
+            EntryTableColumn::Pubtype => 3,
+        };
         let entry_table_state = TableState::default()
             .with_selected(0)
-            .with_selected_column(0)
-            .with_selected_cell(Some((0, 0)));
+            .with_selected_column(col_idx)
+            .with_selected_cell(Some((0, col_idx)));
         let entry_scroll_state = ScrollbarState::new(entry_table_items.len());
         let entry_info_scroll_state = ScrollbarState::default();
-        Self {
+        let mut entry_table = Self {
             entry_table_items,
             entry_table_at_search_start: Vec::new(),
-            entry_table_selected_column: EntryTableColumn::Authors,
-            entry_table_sorted_by_col: EntryTableColumn::Authors,
+            entry_table_at_filter_start: Vec::new(),
+            entry_table_selected_column: sort_col.clone(),
+            entry_table_sorted_by_col: sort_col.clone(),
             entry_table_reversed_sort: false,
             entry_table_state,
             entry_scroll_state,
             entry_info_scroll: 0,
             entry_info_scroll_state,
-        }
+            marked: BTreeSet::new(),
+            visual_anchor: None,
+        };
+        entry_table.sort_entry_table(false);
+        entry_table
     }
 
     pub fn set_entry_table(entry_list: &[BibiData]) -> Vec<EntryTableItem> {
@@ -79,10 +103,13 @@ impl EntryTable {
                 doi_url: e.doi_url.clone(),
                 filepath: e.filepath.clone(),
                 subtitle: e.subtitle.clone(),
+                other_fields: e.other_fields.clone(),
+                fulltext: e.fulltext.clone(),
+                source: e.source.clone(),
             })
             .collect();
 
-        entry_table.sort_by(|a, b| a.authors.to_lowercase().cmp(&b.authors.to_lowercase()));
+        entry_table.sort_by_key(|a| a.authors.to_lowercase());
         entry_table
     }
 
@@ -100,34 +127,130 @@ impl EntryTable {
             match self.entry_table_selected_column {
                 EntryTableColumn::Authors => self
                     .entry_table_items
-                    .sort_by(|a, b| b.authors.to_lowercase().cmp(&a.authors.to_lowercase())),
+                    .sort_by_key(|a| std::cmp::Reverse(a.authors.to_lowercase())),
                 EntryTableColumn::Title => self
                     .entry_table_items
-                    .sort_by(|a, b| b.title.to_lowercase().cmp(&a.title.to_lowercase())),
+                    .sort_by_key(|a| std::cmp::Reverse(a.title.to_lowercase())),
                 EntryTableColumn::Year => self
                     .entry_table_items
-                    .sort_by(|a, b| b.year.to_lowercase().cmp(&a.year.to_lowercase())),
+                    .sort_by_key(|a| std::cmp::Reverse(a.year.to_lowercase())),
                 EntryTableColumn::Pubtype => self
                     .entry_table_items
-                    .sort_by(|a, b| b.pubtype.to_lowercase().cmp(&a.pubtype.to_lowercase())),
+                    .sort_by_key(|a| std::cmp::Reverse(a.pubtype.to_lowercase())),
             }
         } else if !self.entry_table_reversed_sort {
             match self.entry_table_selected_column {
                 EntryTableColumn::Authors => self
                     .entry_table_items
-                    .sort_by(|a, b| a.authors.to_lowercase().cmp(&b.authors.to_lowercase())),
+                    .sort_by_key(|a| a.authors.to_lowercase()),
                 EntryTableColumn::Title => self
                     .entry_table_items
-                    .sort_by(|a, b| a.title.to_lowercase().cmp(&b.title.to_lowercase())),
+                    .sort_by_key(|a| a.title.to_lowercase()),
                 EntryTableColumn::Year => self
                     .entry_table_items
-                    .sort_by(|a, b| a.year.to_lowercase().cmp(&b.year.to_lowercase())),
+                    .sort_by_key(|a| a.year.to_lowercase()),
                 EntryTableColumn::Pubtype => self
                     .entry_table_items
-                    .sort_by(|a, b| a.pubtype.to_lowercase().cmp(&b.pubtype.to_lowercase())),
+                    .sort_by_key(|a| a.pubtype.to_lowercase()),
             }
         }
     }
+
+    /// Mark the selected entry, or unmark it if it is already marked
+    pub fn toggle_mark(&mut self) {
+        let Some(item) = self
+            .entry_table_state
+            .selected()
+            .and_then(|idx| self.entry_table_items.get(idx))
+        else {
+            return;
+        };
+        if !self.marked.remove(&item.citekey) {
+            self.marked.insert(item.citekey.clone());
+        }
+    }
+
+    /// Start a visual range at the selected entry. If a range is already
+    /// active, mark all entries inside of it and stop the visual mode
+    pub fn toggle_visual(&mut self) {
+        if let Some(range) = self.visual_range() {
+            let citekeys: Vec<String> = self.entry_table_items[range]
+                .iter()
+                .map(|e| e.citekey.clone())
+                .collect();
+            self.marked.extend(citekeys);
+            self.visual_anchor = None;
+        } else if let Some(item) = self
+            .entry_table_state
+            .selected()
+            .and_then(|idx| self.entry_table_items.get(idx))
+        {
+            self.visual_anchor = Some(item.citekey.clone());
+        }
+    }
+
+    /// Rows between the start of the visual range and the selected row.
+    /// `None` if the visual mode isn't active
+    pub fn visual_range(&self) -> Option<RangeInclusive<usize>> {
+        let anchor = self.visual_anchor.as_ref()?;
+        let selected = self
+            .entry_table_state
+            .selected()?
+            .min(self.entry_table_items.len().checked_sub(1)?);
+        // If the anchor isn't listed anymore, the range shrinks to the
+        // selected row
+        let start = self
+            .entry_table_items
+            .iter()
+            .position(|e| &e.citekey == anchor)
+            .unwrap_or(selected);
+        Some(start.min(selected)..=start.max(selected))
+    }
+
+    pub fn is_marked(&self, idx: usize) -> bool {
+        self.visual_range().is_some_and(|r| r.contains(&idx))
+            || self
+                .entry_table_items
+                .get(idx)
+                .is_some_and(|e| self.marked.contains(&e.citekey))
+    }
+
+    pub fn has_marks(&self) -> bool {
+        !self.marked.is_empty() || self.visual_anchor.is_some()
+    }
+
+    /// Citekeys of all listed entries which are marked or inside the
+    /// visual range, in the order of the table. Marked entries hidden by
+    /// a search or filter are left out
+    pub fn marked_citekeys(&self) -> Vec<String> {
+        (0..self.entry_table_items.len())
+            .filter(|idx| self.is_marked(*idx))
+            .map(|idx| self.entry_table_items[idx].citekey.clone())
+            .collect()
+    }
+
+    pub fn clear_marks(&mut self) {
+        self.marked.clear();
+        self.visual_anchor = None;
+    }
+
+    /// Select the entry which was selected in the list of `old_citekeys`.
+    /// If it is gone, select the closest entry of the old list which is
+    /// still listed, preferring the following ones
+    pub fn select_nearby(&mut self, old_citekeys: &[String], old_idx: usize) {
+        let old_idx = old_idx.min(old_citekeys.len());
+        let idx = old_citekeys[old_idx..]
+            .iter()
+            .chain(old_citekeys[..old_idx].iter().rev())
+            .find_map(|citekey| {
+                self.entry_table_items
+                    .iter()
+                    .position(|e| &e.citekey == citekey)
+            })
+            .unwrap_or(old_idx.min(self.entry_table_items.len().saturating_sub(1)));
+        self.entry_table_state.select(Some(idx));
+        self.entry_scroll_state = ScrollbarState::new(self.entry_table_items.len()).position(idx);
+    }
 }
 
 // Define contents of each entry table row
@@ -144,6 +267,12 @@ pub struct EntryTableItem {
     pub doi_url: Option<String>,
     pub filepath: Option<OsString>,
     pub subtitle: Option<String>,
+    // All fields of the entry as plain text, used for searching
+    pub other_fields: Vec<(String, String)>,
+    // Text of the connected PDF, if the full-text index is enabled
+    pub fulltext: Option<Arc<str>>,
+    // File the entry was read from
+    pub source: Option<EntrySource>,
 }
 
 impl EntryTableItem {
@@ -205,6 +334,19 @@ impl EntryTableItem {
         self.filepath.as_ref().unwrap()
     }
 
+    /// Files listed in the `file` field
+    pub fn files(&self) -> Vec<String> {
+        self.filepath
+            .as_ref()
+            .map(|value| {
+                attach::split_files(&value.to_string_lossy())
+                    .into_iter()
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
     pub fn subtitle(&self) -> &str {
         self.subtitle.as_ref().unwrap()
     }
@@ -212,7 +354,10 @@ impl EntryTableItem {
 
 #[cfg(test)]
 mod tests {
-    use super::EntryTableItem;
+    use std::path::PathBuf;
+
+    use super::{EntryTable, EntryTableColumn, EntryTableItem};
+    use crate::bibiman::bibisetup::BibiSetup;
 
     #[test]
     fn check_os() {
@@ -239,6 +384,9 @@ mod tests {
             doi_url: None,
             filepath: None,
             subtitle: None,
+            other_fields: Vec::new(),
+            fulltext: None,
+            source: None,
         };
 
         let entry_vec = EntryTableItem::ref_vec(&mut entry);
@@ -255,6 +403,9 @@ mod tests {
             doi_url: None,
             filepath: None,
             subtitle: None,
+            other_fields: Vec::new(),
+            fulltext: None,
+            source: None,
         };
 
         let entry_vec_editors = EntryTableItem::ref_vec(&mut entry_editors);
@@ -268,4 +419,41 @@ mod tests {
             vec!["Miller et al. (ed.)", "A title", "2000", "article"]
         )
     }
+
+    #[test]
+    fn mark_entries() {
+        let biblio = BibiSetup::new(&[PathBuf::from("tests/biblatex-test.bib")]);
+        let mut table = EntryTable::new(&biblio.entry_list, &EntryTableColumn::Authors);
+        let citekey = |table: &EntryTable, idx: usize| table.entry_table_items[idx].citekey.clone();
+
+        let first = citekey(&table, 0);
+        table.toggle_mark();
+        assert_eq!(table.marked_citekeys(), vec![first.clone()]);
+
+        // Visual range from the third to the fifth row
+        table.entry_table_state.select(Some(2));
+        table.toggle_visual();
+        table.entry_table_state.select(Some(4));
+        assert!(table.is_marked(3));
+        table.toggle_visual();
+        assert!(table.visual_anchor.is_none());
+        assert_eq!(
+            table.marked_citekeys(),
+            [0, 2, 3, 4].map(|i| citekey(&table, i)).to_vec()
+        );
+
+        // Marks are kept by citekey, thus, reversing the order keeps them
+        table.sort_entry_table(true);
+        assert_eq!(table.marked_citekeys().len(), 4);
+        let idx = table
+            .entry_table_items
+            .iter()
+            .position(|e| e.citekey == first)
+            .unwrap();
+        table.entry_table_state.select(Some(idx));
+        table.toggle_mark();
+        assert_eq!(table.marked_citekeys().len(), 3);
+        table.clear_marks();
+        assert!(!table.has_marks());
+    }
 }
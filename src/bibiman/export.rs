@@ -0,0 +1,470 @@
+// bibiman - a TUI for managing BibLaTeX databases
+// Copyright (C) 2024  lukeflo
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+/////
+
+//! Export of entries to other bibliography formats.
+
+use biblatex::{Bibliography, ChunksExt, Entry, EntryType, Person};
+use color_eyre::eyre::{eyre, Result};
+use serde_json::{json, Map, Value};
+
+use super::bibedit::split_keywords;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Bibtex,
+    Biblatex,
+    CslJson,
+    Ris,
+    Hayagriva,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 5] = [
+        Self::Biblatex,
+        Self::Bibtex,
+        Self::CslJson,
+        Self::Ris,
+        Self::Hayagriva,
+    ];
+
+    /// Name used on the command line and in the TUI
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Bibtex => "bibtex",
+            Self::Biblatex => "biblatex",
+            Self::CslJson => "csl-json",
+            Self::Ris => "ris",
+            Self::Hayagriva => "hayagriva",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|f| f.name() == name.to_lowercase())
+    }
+
+    /// Common file extension of the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Bibtex | Self::Biblatex => "bib",
+            Self::CslJson => "json",
+            Self::Ris => "ris",
+            Self::Hayagriva => "yml",
+        }
+    }
+}
+
+/// Export the entries with the passed citekeys in the given order
+pub fn export_entries(
+    biblio: &Bibliography,
+    citekeys: &[String],
+    format: ExportFormat,
+) -> Result<String> {
+    let entries: Vec<&Entry> = citekeys.iter().filter_map(|k| biblio.get(k)).collect();
+
+    match format {
+        ExportFormat::Biblatex => Ok(entries
+            .iter()
+            .map(|e| unescape_tilde(&e.to_biblatex_string()))
+            .collect::<Vec<String>>()
+            .join("\n\n")),
+        ExportFormat::Bibtex => Ok(entries
+            .iter()
+            .map(|e| {
+                e.to_bibtex_string()
+                    .map(|s| unescape_tilde(&s))
+                    .map_err(|err| eyre!("Can't convert entry {} to BibTeX: {}", e.key, err))
+            })
+            .collect::<Result<Vec<String>>>()?
+            .join("\n\n")),
+        ExportFormat::CslJson => Ok(serde_json::to_string_pretty(&Value::Array(
+            entries.iter().map(|e| entry_to_csl(e)).collect(),
+        ))?),
+        ExportFormat::Ris => Ok(entries
+            .iter()
+            .map(|e| entry_to_ris(e))
+            .collect::<Vec<String>>()
+            .join("\n")),
+        ExportFormat::Hayagriva => {
+            let mut selection = Bibliography::new();
+            for entry in entries {
+                selection.insert(entry.clone());
+            }
+            let library = hayagriva::io::from_biblatex(&selection).map_err(|errors| {
+                eyre!(
+                    "Can't convert entries to Hayagriva: {}",
+                    errors
+                        .iter()
+                        .map(|e| e.to_string())
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                )
+            })?;
+            Ok(hayagriva::io::to_yaml_str(&library)?)
+        }
+    }
+}
+
+// The biblatex crate keeps non-breaking spaces (`~`) as they are, but
+// escapes them when serializing. The resulting `\~` would be read as accent
+fn unescape_tilde(bibtex: &str) -> String {
+    bibtex.replace("\\~", "~")
+}
+
+// Plain text of a field, if it is set
+fn field(entry: &Entry, name: &str) -> Option<String> {
+    entry
+        .get(name)
+        .map(|value| value.format_verbatim())
+        .filter(|value| !value.is_empty())
+}
+
+// Journal or book containing the entry
+fn container_title(entry: &Entry) -> Option<String> {
+    field(entry, "journaltitle")
+        .or_else(|| field(entry, "journal"))
+        .or_else(|| field(entry, "booktitle"))
+}
+
+fn editors(entry: &Entry) -> Vec<Person> {
+    entry
+        .editors()
+        .unwrap_or_default()
+        .into_iter()
+        .flat_map(|(persons, _)| persons)
+        .collect()
+}
+
+// Year, month and day of the `date` field, or the `year` and `month` fields
+fn date_parts(entry: &Entry) -> Vec<i32> {
+    if let Some(date) = field(entry, "date") {
+        // Only the start of date ranges like `2020-01/2020-03` is used
+        let start = date.split('/').next().unwrap_or_default();
+        return start
+            .split('-')
+            .map_while(|part| part.trim().parse().ok())
+            .collect();
+    }
+    let Some(year) = field(entry, "year").and_then(|y| y.trim().parse().ok()) else {
+        return Vec::new();
+    };
+    let mut parts = vec![year];
+    if let Some(month) = field(entry, "month").and_then(|m| parse_month(&m)) {
+        parts.push(month);
+    }
+    parts
+}
+
+fn parse_month(month: &str) -> Option<i32> {
+    let month = month.trim().to_lowercase();
+    if let Ok(number) = month.parse() {
+        return Some(number);
+    }
+    [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ]
+    .iter()
+    .position(|m| month.starts_with(m))
+    .map(|i| i as i32 + 1)
+}
+
+// First and last page of the `pages` field, e.g. `529--571`
+fn page_range(entry: &Entry) -> Option<(String, Option<String>)> {
+    let pages = field(entry, "pages")?;
+    let mut parts = pages
+        .split(['-', '–'])
+        .map(|p| p.trim())
+        .filter(|p| !p.is_empty());
+    let first = parts.next()?.to_string();
+    Some((first, parts.next_back().map(|p| p.to_string())))
+}
+
+fn csl_type(entry_type: &EntryType) -> &'static str {
+    match entry_type {
+        EntryType::Article => "article-journal",
+        EntryType::Book
+        | EntryType::MvBook
+        | EntryType::Collection
+        | EntryType::MvCollection
+        | EntryType::Reference
+        | EntryType::MvReference
+        | EntryType::Proceedings
+        | EntryType::MvProceedings
+        | EntryType::Manual => "book",
+        EntryType::InBook
+        | EntryType::BookInBook
+        | EntryType::SuppBook
+        | EntryType::InCollection
+        | EntryType::SuppCollection => "chapter",
+        EntryType::InProceedings => "paper-conference",
+        EntryType::InReference => "entry-encyclopedia",
+        EntryType::Report | EntryType::TechReport => "report",
+        EntryType::Thesis | EntryType::PhdThesis | EntryType::MastersThesis => "thesis",
+        EntryType::Online => "webpage",
+        EntryType::Patent => "patent",
+        EntryType::Periodical | EntryType::SuppPeriodical => "periodical",
+        EntryType::Unpublished => "manuscript",
+        EntryType::Dataset => "dataset",
+        EntryType::Software => "software",
+        _ => "document",
+    }
+}
+
+fn csl_names(persons: &[Person]) -> Value {
+    persons
+        .iter()
+        .map(|p| {
+            // Names without given name are mostly institutions
+            if p.given_name.is_empty() && p.prefix.is_empty() {
+                return json!({ "literal": p.name });
+            }
+            let mut name = Map::new();
+            name.insert("family".to_string(), json!(p.name));
+            name.insert("given".to_string(), json!(p.given_name));
+            if !p.prefix.is_empty() {
+                name.insert("non-dropping-particle".to_string(), json!(p.prefix));
+            }
+            if !p.suffix.is_empty() {
+                name.insert("suffix".to_string(), json!(p.suffix));
+            }
+            Value::Object(name)
+        })
+        .collect()
+}
+
+fn entry_to_csl(entry: &Entry) -> Value {
+    let mut csl = Map::new();
+    csl.insert("id".to_string(), json!(entry.key));
+    csl.insert("type".to_string(), json!(csl_type(&entry.entry_type)));
+
+    if let Ok(authors) = entry.author() {
+        csl.insert("author".to_string(), csl_names(&authors));
+    }
+    let editors = editors(entry);
+    if !editors.is_empty() {
+        csl.insert("editor".to_string(), csl_names(&editors));
+    }
+    let date = date_parts(entry);
+    if !date.is_empty() {
+        csl.insert("issued".to_string(), json!({ "date-parts": [date] }));
+    }
+    if let Some(title) = container_title(entry) {
+        csl.insert("container-title".to_string(), json!(title));
+    }
+    if let Some((first, last)) = page_range(entry) {
+        let pages = match last {
+            Some(last) => format!("{}-{}", first, last),
+            None => first,
+        };
+        csl.insert("page".to_string(), json!(pages));
+    }
+    if let Some(keywords) = field(entry, "keywords") {
+        csl.insert(
+            "keyword".to_string(),
+            json!(split_keywords(&keywords).join(", ")),
+        );
+    }
+
+    for (name, key) in [
+        ("title", "title"),
+        ("volume", "volume"),
+        ("number", "issue"),
+        ("edition", "edition"),
+        ("publisher", "publisher"),
+        ("location", "publisher-place"),
+        ("address", "publisher-place"),
+        ("doi", "DOI"),
+        ("isbn", "ISBN"),
+        ("issn", "ISSN"),
+        ("url", "URL"),
+        ("abstract", "abstract"),
+        ("note", "note"),
+    ] {
+        if let Some(value) = field(entry, name) {
+            csl.entry(key.to_string()).or_insert(json!(value));
+        }
+    }
+
+    Value::Object(csl)
+}
+
+fn ris_type(entry_type: &EntryType) -> &'static str {
+    match entry_type {
+        EntryType::Article => "JOUR",
+        EntryType::Book
+        | EntryType::MvBook
+        | EntryType::Collection
+        | EntryType::MvCollection
+        | EntryType::Reference
+        | EntryType::MvReference
+        | EntryType::Manual => "BOOK",
+        EntryType::InBook
+        | EntryType::BookInBook
+        | EntryType::SuppBook
+        | EntryType::InCollection
+        | EntryType::SuppCollection => "CHAP",
+        EntryType::InProceedings => "CPAPER",
+        EntryType::Proceedings | EntryType::MvProceedings => "CONF",
+        EntryType::InReference => "ENCYC",
+        EntryType::Report | EntryType::TechReport => "RPRT",
+        EntryType::Thesis | EntryType::PhdThesis | EntryType::MastersThesis => "THES",
+        EntryType::Online => "ELEC",
+        EntryType::Patent => "PAT",
+        EntryType::Periodical | EntryType::SuppPeriodical => "JFULL",
+        EntryType::Unpublished => "UNPB",
+        EntryType::Dataset => "DATA",
+        EntryType::Software => "COMP",
+        _ => "GEN",
+    }
+}
+
+fn ris_name(person: &Person) -> String {
+    let mut name = if person.prefix.is_empty() {
+        person.name.clone()
+    } else {
+        format!("{} {}", person.prefix, person.name)
+    };
+    if !person.given_name.is_empty() {
+        name = format!("{}, {}", name, person.given_name);
+    }
+    if !person.suffix.is_empty() {
+        name = format!("{}, {}", name, person.suffix);
+    }
+    name
+}
+
+fn entry_to_ris(entry: &Entry) -> String {
+    let mut lines: Vec<(&str, String)> = vec![("TY", ris_type(&entry.entry_type).to_string())];
+
+    for author in entry.author().unwrap_or_default() {
+        lines.push(("AU", ris_name(&author)));
+    }
+    for editor in editors(entry) {
+        lines.push(("ED", ris_name(&editor)));
+    }
+    if let Some(title) = field(entry, "title") {
+        lines.push(("TI", title));
+    }
+    if let Some(title) = container_title(entry) {
+        lines.push(("T2", title));
+    }
+    let date = date_parts(entry);
+    if let Some(year) = date.first() {
+        lines.push(("PY", year.to_string()));
+        lines.push((
+            "DA",
+            date.iter()
+                .map(|p| format!("{:02}", p))
+                .collect::<Vec<String>>()
+                .join("/"),
+        ));
+    }
+    if let Some((first, last)) = page_range(entry) {
+        lines.push(("SP", first));
+        if let Some(last) = last {
+            lines.push(("EP", last));
+        }
+    }
+    for keyword in field(entry, "keywords")
+        .map(|k| split_keywords(&k))
+        .unwrap_or_default()
+    {
+        lines.push(("KW", keyword));
+    }
+
+    for (name, tag) in [
+        ("volume", "VL"),
+        ("number", "IS"),
+        ("edition", "ET"),
+        ("publisher", "PB"),
+        ("location", "CY"),
+        ("address", "CY"),
+        ("isbn", "SN"),
+        ("issn", "SN"),
+        ("doi", "DO"),
+        ("url", "UR"),
+        ("abstract", "AB"),
+        ("note", "N1"),
+    ] {
+        if let Some(value) = field(entry, name) {
+            if !lines.iter().any(|(t, _)| *t == tag) {
+                lines.push((tag, value));
+            }
+        }
+    }
+    lines.push(("ID", entry.key.clone()));
+
+    let mut ris = String::new();
+    for (tag, value) in lines {
+        // RIS values must not span multiple lines
+        let value = value.split_whitespace().collect::<Vec<&str>>().join(" ");
+        ris.push_str(&format!("{}  - {}\n", tag, value));
+    }
+    ris.push_str("ER  - \n");
+    ris
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_formats() {
+        let content = std::fs::read_to_string("tests/biblatex-test.bib").unwrap();
+        let biblio = Bibliography::parse(&content).unwrap();
+        let citekeys = vec!["bertram".to_string(), "aristotle:physics".to_string()];
+        let export = |format| export_entries(&biblio, &citekeys, format).unwrap();
+
+        // BibLaTeX can be parsed again and contains the same entries
+        let reparsed = Bibliography::parse(&export(ExportFormat::Biblatex)).unwrap();
+        assert_eq!(
+            reparsed.keys().collect::<Vec<&str>>(),
+            ["bertram", "aristotle:physics"]
+        );
+        let fields = |biblio: &Bibliography| {
+            biblio
+                .get("bertram")
+                .unwrap()
+                .fields
+                .iter()
+                .map(|(k, v)| (k.clone(), v.format_verbatim()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(fields(&reparsed), fields(&biblio));
+
+        assert!(export(ExportFormat::Bibtex).contains("journal = {J.~Amer. Math. Soc.}"));
+
+        let csl: Value = serde_json::from_str(&export(ExportFormat::CslJson)).unwrap();
+        assert_eq!(csl[0]["type"], "article-journal");
+        assert_eq!(csl[0]["author"][1]["family"], "Wentworth");
+        assert_eq!(csl[0]["issued"]["date-parts"][0][0], 1996);
+        assert_eq!(csl[0]["page"], "529-571");
+        assert_eq!(csl[1]["author"][0]["literal"], "Aristotle");
+        assert_eq!(csl[1]["keyword"], "primary, ancient, philosophy");
+
+        let ris = export(ExportFormat::Ris);
+        assert!(ris.starts_with("TY  - JOUR\nAU  - Bertram, Aaron\nAU  - Wentworth, Richard\n"));
+        assert!(ris.contains("SP  - 529\nEP  - 571\n"));
+        assert!(ris.contains("TY  - BOOK\n"));
+        assert_eq!(ris.matches("ER  - ").count(), 2);
+
+        let library = hayagriva::io::from_yaml_str(&export(ExportFormat::Hayagriva)).unwrap();
+        assert_eq!(library.len(), 2);
+        assert!(library.get("aristotle:physics").is_some());
+    }
+}
@@ -0,0 +1,291 @@
+// bibiman - a TUI for managing BibLaTeX databases
+// Copyright (C) 2024  lukeflo
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+/////
+
+//! Full-text index of the PDF files attached to entries.
+//!
+//! Extracting text from PDFs is slow. Thus, the text is cached on disk
+//! together with the modification time of the file and only extracted
+//! again if the file changed. The TUI keeps the index in memory and updates
+//! it in the background.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::UNIX_EPOCH;
+
+use color_eyre::eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use super::attach;
+use super::bibisetup::BibiData;
+use crate::app::expand_home;
+
+/// Name of the threads extracting text. The panic hook ignores panics of
+/// these threads, they only result in an empty text
+pub const EXTRACT_THREAD: &str = "fulltext-extract";
+
+/// Text of the first PDF attached to each entry, by citekey
+pub type EntryTexts = HashMap<String, Arc<str>>;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct FulltextIndex {
+    files: BTreeMap<PathBuf, IndexedFile>,
+    // Files were added or removed since loading or saving
+    #[serde(skip)]
+    changed: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexedFile {
+    // Seconds since UNIX epoch
+    mtime: u64,
+    // Normalized text, empty if extraction failed
+    text: Arc<str>,
+}
+
+impl FulltextIndex {
+    /// Load the index from the cache file. A missing or broken cache
+    /// results in an empty index
+    pub fn load(cache: &Path) -> Self {
+        fs::read_to_string(cache)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    /// Write the index to the cache file, if it changed since loading
+    pub fn save(&mut self, cache: &Path) -> Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+        if let Some(dir) = cache.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(cache, serde_json::to_string(self)?)?;
+        self.changed = false;
+        Ok(())
+    }
+
+    /// Return the text of the PDF file. It is extracted again if the file
+    /// isn't indexed yet or was modified since. Returns `None` for files
+    /// which don't exist or aren't PDFs
+    pub fn text_for(&mut self, file: &Path) -> Option<Arc<str>> {
+        if !file
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"))
+        {
+            return None;
+        }
+        let mtime = fs::metadata(file)
+            .and_then(|m| m.modified())
+            .ok()?
+            .duration_since(UNIX_EPOCH)
+            .ok()?
+            .as_secs();
+
+        if let Some(indexed) = self.files.get(file) {
+            if indexed.mtime == mtime {
+                return Some(indexed.text.clone());
+            }
+        }
+
+        let text: Arc<str> = extract_text(file).into();
+        self.changed = true;
+        self.files.insert(
+            file.to_path_buf(),
+            IndexedFile {
+                mtime,
+                text: text.clone(),
+            },
+        );
+        Some(text)
+    }
+
+    /// Text of the entries, which are passed with their attached files.
+    /// The text of the first PDF is used if several files are attached.
+    /// Files which don't exist anymore are removed from the index
+    pub fn entry_texts(&mut self, entries: &[(String, Vec<PathBuf>)]) -> EntryTexts {
+        let texts = entries
+            .iter()
+            .filter_map(|(citekey, files)| {
+                let text = files.iter().find_map(|file| self.text_for(file))?;
+                Some((citekey.clone(), text))
+            })
+            .collect();
+        let len = self.files.len();
+        self.files.retain(|file, _| file.is_file());
+        self.changed |= self.files.len() != len;
+        texts
+    }
+}
+
+/// Keeps the index in memory and updates it in a background task, which
+/// passes the texts of the entries to `done`
+#[derive(Clone)]
+pub struct FulltextIndexer {
+    // Loaded from the cache file by the first update
+    index: Arc<Mutex<Option<FulltextIndex>>>,
+    cache: PathBuf,
+    done: Arc<dyn Fn(EntryTexts) + Send + Sync>,
+}
+
+impl fmt::Debug for FulltextIndexer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FulltextIndexer")
+            .field("cache", &self.cache)
+            .finish_non_exhaustive()
+    }
+}
+
+impl FulltextIndexer {
+    pub fn new(cache: PathBuf, done: impl Fn(EntryTexts) + Send + Sync + 'static) -> Self {
+        Self {
+            index: Arc::default(),
+            cache,
+            done: Arc::new(done),
+        }
+    }
+
+    /// Index the PDFs of the entries. Only files which were modified since
+    /// they were indexed are extracted again. Must be called inside the
+    /// tokio runtime
+    pub fn update(&self, entries: &[BibiData]) {
+        let entries = entry_files(entries);
+        let indexer = self.clone();
+        tokio::task::spawn_blocking(move || {
+            // Updates run one after another, thus, the texts are passed on
+            // in the order the updates were started
+            let mut index = indexer.index.lock().unwrap_or_else(PoisonError::into_inner);
+            let index = index.get_or_insert_with(|| FulltextIndex::load(&indexer.cache));
+            let texts = index.entry_texts(&entries);
+            // The index is only a cache, thus, failing to write it is not fatal
+            let _ = index.save(&indexer.cache);
+            (indexer.done)(texts);
+        });
+    }
+}
+
+/// Attach the text of the connected PDF files to the entries. Uses and
+/// updates the cache file
+pub fn index_entries(entries: &mut [BibiData], cache: &Path) {
+    let mut index = FulltextIndex::load(cache);
+    let mut texts = index.entry_texts(&entry_files(entries));
+    for entry in entries.iter_mut() {
+        entry.fulltext = texts.remove(&entry.citekey);
+    }
+    let _ = index.save(cache);
+}
+
+// Citekeys and attached files of the entries
+fn entry_files(entries: &[BibiData]) -> Vec<(String, Vec<PathBuf>)> {
+    entries
+        .iter()
+        .filter_map(|entry| {
+            let files = attach::split_files(&entry.filepath.as_ref()?.to_string_lossy())
+                .into_iter()
+                .map(|file| expand_home(&PathBuf::from(file)))
+                .collect();
+            Some((entry.citekey.clone(), files))
+        })
+        .collect()
+}
+
+/// Default path of the cache file: `$XDG_CACHE_HOME/bibiman/fulltext.json`
+pub fn default_cache_path() -> Option<PathBuf> {
+    dirs::cache_dir().map(|d| d.join("bibiman").join("fulltext.json"))
+}
+
+/// Lowercase the text and collapse all whitespace, including line breaks,
+/// into single spaces. Search phrases are normalized the same way
+pub fn normalize_text(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Part of the text around the first match of the phrase
+pub fn snippet(text: &str, phrase: &str, context: usize) -> Option<String> {
+    let pos = text.find(phrase)?;
+    let start = text[..pos]
+        .char_indices()
+        .rev()
+        .nth(context.saturating_sub(1))
+        .map(|(i, _)| i)
+        .unwrap_or(0);
+    let end = text[pos + phrase.len()..]
+        .char_indices()
+        .nth(context)
+        .map(|(i, _)| pos + phrase.len() + i)
+        .unwrap_or(text.len());
+    let prefix = if start > 0 { "…" } else { "" };
+    let suffix = if end < text.len() { "…" } else { "" };
+    Some(format!("{}{}{}", prefix, &text[start..end], suffix))
+}
+
+fn extract_text(file: &Path) -> String {
+    // The PDF parser panics on some malformed files, thus, it runs in a
+    // thread of its own. Such files are indexed with empty text, thus, they
+    // aren't parsed again until changed
+    let file = file.to_path_buf();
+    let text = thread::Builder::new()
+        .name(EXTRACT_THREAD.to_string())
+        .spawn(move || pdf_extract::extract_text(file))
+        .map(|handle| handle.join());
+
+    match text {
+        Ok(Ok(Ok(text))) => normalize_text(&text),
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_pdf() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = dir.path().join("fulltext.json");
+        let pdf = Path::new("tests/aristotle_physics.pdf");
+
+        let mut index = FulltextIndex::default();
+        let text = index.text_for(pdf).unwrap();
+        assert!(text.contains("acquainted with its primary conditions or first principles"));
+        assert!(index
+            .text_for(Path::new("tests/biblatex-test.bib"))
+            .is_none());
+
+        // The cached text is used as long as the file isn't modified
+        index.save(&cache).unwrap();
+        let mut index = FulltextIndex::load(&cache);
+        assert_eq!(index.files.len(), 1);
+        assert_eq!(index.text_for(pdf).unwrap(), text);
+        // The unchanged index isn't written again
+        fs::remove_file(&cache).unwrap();
+        index.save(&cache).unwrap();
+        assert!(!cache.exists());
+
+        assert_eq!(
+            snippet(&text, "first principles", 10).unwrap(),
+            "…itions or first principles, and have…"
+        );
+    }
+}
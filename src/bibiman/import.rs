@@ -0,0 +1,277 @@
+// bibiman - a TUI for managing BibLaTeX databases
+// Copyright (C) 2024  lukeflo
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+/////
+
+//! Import of entries from BibTeX/BibLaTeX or RIS text.
+//!
+//! RIS records are converted to BibLaTeX first. Entries which already exist
+//! in the opened bibfiles, or several times in the text, are skipped.
+
+use color_eyre::eyre::{eyre, Result};
+
+use super::bibedit;
+use super::bibisetup::BibiSetup;
+use super::citekey::unique_citekey;
+use super::lookup::{citekey, first_year, BibEntry};
+use super::newentry::NewEntry;
+use crate::config::Citekey;
+
+/// Entries read from the text
+#[derive(Debug, Default)]
+pub struct Import {
+    // New entries with unique citekeys
+    pub entries: Vec<NewEntry>,
+    // Entries which already exist, e.g. `doe2020 (same DOI as miller2020)`
+    pub duplicates: Vec<String>,
+}
+
+/// Whether the text contains BibTeX entries or RIS records instead of an
+/// identifier
+pub fn is_import(text: &str) -> bool {
+    is_ris(text) || !bibedit::entries(text).is_empty()
+}
+
+/// Read all entries of the text. The suggested citekeys are built like the
+/// ones of fetched entries
+pub fn read_import(text: &str, biblio: &BibiSetup, cfg: &Citekey) -> Result<Import> {
+    let sources: Vec<String> = if is_ris(text) {
+        ris_records(text)
+            .iter()
+            .map(|r| ris_to_biblatex(r))
+            .collect()
+    } else {
+        bibedit::entries(text)
+            .into_iter()
+            .map(|(_, span)| text[span.range].to_string())
+            .collect()
+    };
+    if sources.is_empty() {
+        return Err(eyre!("No BibTeX entry or RIS record found"));
+    }
+
+    let mut import = Import::default();
+    for source in sources {
+        let mut entry = NewEntry::new(&source, biblio, cfg)?;
+        if let Some((existing, reason)) = entry.duplicate_of(biblio) {
+            import.duplicates.push(format!(
+                "{} (same {} as {})",
+                entry.citekey,
+                reason.name(),
+                existing
+            ));
+            continue;
+        }
+        if let Some(other) = import.entries.iter().find(|e| e.same_as(&entry)) {
+            import
+                .duplicates
+                .push(format!("{} (same as {})", entry.citekey, other.citekey));
+            continue;
+        }
+        // Citekeys must be unique among the imported entries, too
+        entry.citekey = unique_citekey(&entry.citekey, |key| {
+            import.entries.iter().any(|e| e.citekey == key)
+        });
+        import.entries.push(entry);
+    }
+    Ok(import)
+}
+
+// RIS records start with a `TY` tag
+fn is_ris(text: &str) -> bool {
+    text.lines()
+        .find(|line| !line.trim().is_empty())
+        .and_then(ris_line)
+        .is_some_and(|(tag, _)| tag == "TY")
+}
+
+// Tag and value of lines like `TY  - JOUR`
+fn ris_line(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim_end();
+    let tag = line.get(..2)?;
+    let rest = line.get(2..)?;
+    let value = rest.strip_prefix("  -")?;
+    (tag.chars()
+        .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+        && tag.starts_with(|c: char| c.is_ascii_uppercase()))
+    .then_some((tag, value.trim()))
+}
+
+// Tags and values of each record. Lines without a tag continue the value
+// of the previous one
+fn ris_records(text: &str) -> Vec<Vec<(String, String)>> {
+    let mut records = Vec::new();
+    let mut record: Vec<(String, String)> = Vec::new();
+    for line in text.lines() {
+        match ris_line(line) {
+            Some(("ER", _)) => records.push(std::mem::take(&mut record)),
+            Some((tag, value)) => record.push((tag.to_string(), value.to_string())),
+            None if !line.trim().is_empty() => {
+                if let Some((_, value)) = record.last_mut() {
+                    value.push(' ');
+                    value.push_str(line.trim());
+                }
+            }
+            None => {}
+        }
+    }
+    if !record.is_empty() {
+        records.push(record);
+    }
+    records
+}
+
+fn ris_to_biblatex(record: &[(String, String)]) -> String {
+    let values = |tags: &[&str]| -> Vec<String> {
+        record
+            .iter()
+            .filter(|(tag, value)| tags.contains(&tag.as_str()) && !value.is_empty())
+            .map(|(_, value)| value.clone())
+            .collect()
+    };
+    // Value of the first of the tags which is set
+    let first = |tags: &[&str]| {
+        tags.iter()
+            .find_map(|tag| values(&[tag]).into_iter().next())
+            .unwrap_or_default()
+    };
+
+    let entry_type = ris_entry_type(&first(&["TY"]));
+    let authors = values(&["AU", "A1"]);
+    let date = first(&["DA", "PY", "Y1"]);
+    let year = first_year(&date);
+    // Dates are written like `2020/05/17/`
+    let date = date
+        .split('/')
+        .filter(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+        .collect::<Vec<&str>>()
+        .join("-");
+    let pages = match (first(&["SP"]), first(&["EP"])) {
+        (start, end) if end.is_empty() => start,
+        (start, end) => format!("{}--{}", start, end),
+    };
+    let container = if entry_type == "article" {
+        "journaltitle"
+    } else {
+        "booktitle"
+    };
+    let number = if entry_type == "book" { "isbn" } else { "issn" };
+    let id = first(&["ID"]);
+
+    let mut fields = vec![
+        ("author", authors.join(" and ")),
+        ("editor", values(&["ED", "A2"]).join(" and ")),
+        ("title", first(&["TI", "T1"])),
+        (container, first(&["T2", "JO", "JF", "JA"])),
+        (if date.contains('-') { "date" } else { "year" }, date),
+        ("volume", first(&["VL"])),
+        ("number", first(&["IS"])),
+        ("pages", pages),
+        ("edition", first(&["ET"])),
+        ("publisher", first(&["PB"])),
+        ("location", first(&["CY"])),
+        (number, first(&["SN"])),
+        ("keywords", values(&["KW"]).join(", ")),
+        ("abstract", first(&["AB", "N2"])),
+        ("note", first(&["N1"])),
+    ];
+    for (_, value) in fields.iter_mut() {
+        *value = escape(value);
+    }
+    // Verbatim fields must not be escaped
+    fields.push(("doi", first(&["DO"])));
+    fields.push(("url", first(&["UR"])));
+
+    BibEntry {
+        entry_type,
+        citekey: if id.is_empty() {
+            citekey(&authors, &year)
+        } else {
+            id
+        },
+        fields,
+    }
+    .to_string()
+}
+
+// Reverse of the RIS types used by the export
+fn ris_entry_type(ris_type: &str) -> &'static str {
+    match ris_type {
+        "JOUR" | "EJOUR" | "MGZN" | "NEWS" => "article",
+        "BOOK" | "EBOOK" => "book",
+        "CHAP" | "ECHAP" => "incollection",
+        "CPAPER" => "inproceedings",
+        "CONF" => "proceedings",
+        "ENCYC" | "DICT" => "inreference",
+        "RPRT" => "report",
+        "THES" => "thesis",
+        "ELEC" | "WEB" | "BLOG" => "online",
+        "PAT" => "patent",
+        "JFULL" => "periodical",
+        "UNPB" | "MANSCPT" => "unpublished",
+        "DATA" => "dataset",
+        "COMP" => "software",
+        _ => "misc",
+    }
+}
+
+// Characters with a special meaning in LaTeX
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\textbackslash{}")
+        .replace(['{', '}'], "")
+        .replace('&', "\\&")
+        .replace('%', "\\%")
+        .replace('#', "\\#")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn import_entries() {
+        let biblio = BibiSetup::new(&[PathBuf::from("tests/import-test.bib")]);
+        let cfg = Citekey::default();
+
+        let ris = "TY  - JOUR\nAU  - Doe, Jane\nAU  - Smith, John\nTI  - Costs & Benefits\n  of Things\nT2  - Journal\nPY  - 2020\nDA  - 2020/05/17/\nSP  - 10\nEP  - 20\nDO  - 10.1000/a_b\nER  - \n\nTY  - BOOK\nTI  - Old Things\nPY  - 2019\nER  - \n";
+        assert!(is_import(ris));
+        let import = read_import(ris, &biblio, &cfg).unwrap();
+        assert_eq!(import.entries.len(), 1);
+        assert_eq!(
+            import.entries[0].with_citekey(),
+            "@article{doe2020costs,\n  author = {Doe, Jane and Smith, John},\n  title = {Costs \\& Benefits of Things},\n  journaltitle = {Journal},\n  date = {2020-05-17},\n  pages = {10--20},\n  doi = {10.1000/a_b},\n}"
+        );
+        assert_eq!(
+            import.duplicates,
+            vec!["2019old (same title and year as existing)"]
+        );
+
+        // Entries which are the same are imported once, citekeys are unique
+        let bibtex = "@book{a,\n  author = {Doe, Jane},\n  title = {Things},\n  year = {2020},\n}\n\
+                      @book{b,\n  author = {Doe, Jane},\n  title = {Things},\n  year = {2020},\n}\n\
+                      @book{c,\n  author = {Doe, Jane},\n  title = {Things II},\n  year = {2020},\n}\n";
+        assert!(is_import(bibtex));
+        assert!(!is_import("10.1000/182"));
+        let import = read_import(bibtex, &biblio, &cfg).unwrap();
+        let citekeys: Vec<&str> = import.entries.iter().map(|e| e.citekey.as_str()).collect();
+        assert_eq!(citekeys, ["doe2020things", "doe2020thingsa"]);
+        assert_eq!(
+            import.duplicates,
+            vec!["doe2020things (same as doe2020things)"]
+        );
+    }
+}
@@ -16,6 +16,7 @@
 /////
 
 use ratatui::widgets::{ListState, ScrollbarState};
+use std::fmt;
 
 #[derive(Debug)]
 pub struct TagList {
@@ -23,7 +24,22 @@ pub struct TagList {
     pub tag_list_at_search_start: Vec<String>,
     pub tag_list_state: ListState,
     pub tag_scroll_state: ScrollbarState,
-    pub selected_keywords: Vec<String>,
+    // Keywords the entry list is filtered by, in the order they were added
+    pub keyword_filter: Vec<KeywordFilter>,
+}
+
+/// How a keyword is connected to the filters added before
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterOp {
+    And,
+    Or,
+    Not,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeywordFilter {
+    pub op: FilterOp,
+    pub keyword: String,
 }
 
 impl TagList {
@@ -36,7 +52,85 @@ impl TagList {
             tag_list_at_search_start: Vec::new(),
             tag_list_state,
             tag_scroll_state,
-            selected_keywords: Vec::new(),
+            keyword_filter: Vec::new(),
+        }
+    }
+}
+
+impl KeywordFilter {
+    pub fn new(op: FilterOp, keyword: &str) -> Self {
+        Self {
+            op,
+            keyword: keyword.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for KeywordFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.op {
+            FilterOp::And => write!(f, "AND {}", self.keyword),
+            FilterOp::Or => write!(f, "OR {}", self.keyword),
+            FilterOp::Not => write!(f, "AND NOT {}", self.keyword),
         }
     }
 }
+
+/// Check if the comma separated keywords of an entry match the filter
+/// chain. The filters are applied from left to right, e.g. `a OR b AND NOT c`
+/// is evaluated as `(a OR b) AND NOT c`. Keywords are compared as whole
+/// tokens, thus, "learning" doesn't match "deep learning".
+pub fn matches_keyword_filter(filters: &[KeywordFilter], keywords: &str) -> bool {
+    let has = |keyword: &str| keywords.split(',').any(|k| k.trim() == keyword);
+    let mut filters = filters.iter();
+    let Some(first) = filters.next() else {
+        return true;
+    };
+    let start = match first.op {
+        FilterOp::And | FilterOp::Or => has(&first.keyword),
+        FilterOp::Not => !has(&first.keyword),
+    };
+    filters.fold(start, |acc, filter| match filter.op {
+        FilterOp::And => acc && has(&filter.keyword),
+        FilterOp::Or => acc || has(&filter.keyword),
+        FilterOp::Not => acc && !has(&filter.keyword),
+    })
+}
+
+/// Format the filter chain for the footer, e.g. `a OR b AND NOT c`
+pub fn format_keyword_filter(filters: &[KeywordFilter]) -> String {
+    filters
+        .iter()
+        .enumerate()
+        .map(|(i, filter)| match (i, filter.op) {
+            (0, FilterOp::Not) => format!("NOT {}", filter.keyword),
+            (0, _) => filter.keyword.clone(),
+            _ => filter.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keyword_filter_chain() {
+        let filters = vec![
+            KeywordFilter::new(FilterOp::And, "learning"),
+            KeywordFilter::new(FilterOp::Or, "statistics"),
+            KeywordFilter::new(FilterOp::Not, "draft"),
+        ];
+
+        assert!(!matches_keyword_filter(&filters[..1], "deep learning, ai"));
+        assert!(matches_keyword_filter(&filters, "learning, ai"));
+        assert!(matches_keyword_filter(&filters, "ai,statistics"));
+        assert!(!matches_keyword_filter(&filters, "statistics, draft"));
+        assert!(matches_keyword_filter(&[], ""));
+        assert_eq!(
+            format_keyword_filter(&filters),
+            "learning OR statistics AND NOT draft"
+        );
+    }
+}
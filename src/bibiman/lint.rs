@@ -0,0 +1,220 @@
+// bibiman - a TUI for managing BibLaTeX databases
+// Copyright (C) 2024  lukeflo
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+/////
+
+//! Checks of entries which could be parsed, but are incomplete or point
+//! to something that doesn't exist.
+
+use biblatex::{Entry, PermissiveType, RetrievalError};
+use serde::Deserialize;
+
+use super::attach;
+use super::bibisetup::{BibiSetup, EntrySource};
+use crate::config::Lint;
+
+/// A single check, can be disabled in the config file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LintRule {
+    // Fields BibLaTeX requires for the entry type are missing
+    MissingField,
+    // A date field can't be parsed as date
+    MalformedDate,
+    // `crossref` or `xref` point to citekeys which don't exist
+    DanglingCrossref,
+    // The `file` field points to a file which doesn't exist
+    MissingFile,
+}
+
+impl LintRule {
+    pub const ALL: [LintRule; 4] = [
+        Self::MissingField,
+        Self::MalformedDate,
+        Self::DanglingCrossref,
+        Self::MissingFile,
+    ];
+
+    /// Name used in the config file and the output
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::MissingField => "missing-field",
+            Self::MalformedDate => "malformed-date",
+            Self::DanglingCrossref => "dangling-crossref",
+            Self::MissingFile => "missing-file",
+        }
+    }
+
+    fn check(&self, entry: &Entry, biblio: &BibiSetup, settings: &Lint) -> Vec<String> {
+        match self {
+            Self::MissingField => {
+                let mut missing: Vec<&str> = entry.verify().missing;
+                let entry_type = entry.entry_type.to_string().to_lowercase();
+                if let Some(fields) = settings.required_fields.get(&entry_type) {
+                    missing.extend(
+                        fields
+                            .iter()
+                            .filter(|field| entry.get(&field.to_lowercase()).is_none())
+                            .map(|field| field.as_str()),
+                    );
+                }
+                missing.sort_unstable();
+                missing.dedup();
+                if missing.is_empty() {
+                    vec![]
+                } else {
+                    vec![format!(
+                        "Missing required field(s) for type {}: {}",
+                        entry.entry_type,
+                        missing.join(", ")
+                    )]
+                }
+            }
+            Self::MalformedDate => [
+                ("date", entry.date()),
+                ("urldate", entry.url_date()),
+                ("origdate", entry.orig_date()),
+                ("eventdate", entry.event_date()),
+            ]
+            .into_iter()
+            .filter_map(|(field, date)| match date {
+                Err(RetrievalError::TypeError(e)) => Some(format!("Malformed {}: {}", field, e)),
+                // Values which aren't dates are kept as plain text
+                Ok(PermissiveType::Chunks(_)) => Some(format!(
+                    "Malformed {}: {}",
+                    field,
+                    entry
+                        .get_as::<String>(field)
+                        .or_else(|_| entry.get_as::<String>("year"))
+                        .unwrap_or_default()
+                )),
+                _ => None,
+            })
+            .collect(),
+            Self::DanglingCrossref => ["crossref", "xref"]
+                .into_iter()
+                .filter_map(|field| Some((field, entry.get_as::<String>(field).ok()?)))
+                .flat_map(|(field, keys)| {
+                    keys.split(',')
+                        .map(|key| key.trim().to_string())
+                        .filter(|key| !key.is_empty() && biblio.bibliography.get(key).is_none())
+                        .map(|key| format!("{} points to unknown entry {}", field, key))
+                        .collect::<Vec<String>>()
+                })
+                .collect(),
+            Self::MissingFile => entry
+                .file()
+                .map(|value| {
+                    attach::split_files(&value)
+                        .into_iter()
+                        .filter(|file| !attach::file_exists(file))
+                        .map(|file| format!("File doesn't exist: {}", file))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Problem found by one of the rules
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintIssue {
+    pub rule: LintRule,
+    pub citekey: String,
+    pub source: Option<EntrySource>,
+    pub message: String,
+}
+
+impl LintIssue {
+    /// Position, citekey, message and rule in a single line
+    pub fn summary(&self) -> String {
+        match &self.source {
+            Some(source) => format!(
+                "{}: {}: {} [{}]",
+                source.location(),
+                self.citekey,
+                self.message,
+                self.rule.name()
+            ),
+            None => format!("{}: {} [{}]", self.citekey, self.message, self.rule.name()),
+        }
+    }
+}
+
+/// Run the rules enabled in the config on all entries. The issues are
+/// ordered by the position of the entries in the files
+pub fn lint(biblio: &BibiSetup, settings: &Lint) -> Vec<LintIssue> {
+    let rules = settings.rules();
+    let mut issues: Vec<LintIssue> = Vec::new();
+    for data in &biblio.entry_list {
+        let Some(entry) = biblio.bibliography.get(&data.citekey) else {
+            continue;
+        };
+        for rule in &rules {
+            issues.extend(
+                rule.check(entry, biblio, settings)
+                    .into_iter()
+                    .map(|message| LintIssue {
+                        rule: *rule,
+                        citekey: data.citekey.clone(),
+                        source: data.source.clone(),
+                        message,
+                    }),
+            );
+        }
+    }
+    issues.sort_by(|a, b| (&a.source, a.rule).cmp(&(&b.source, b.rule)));
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn lint_entries() {
+        let biblio = BibiSetup::new(&[PathBuf::from("tests/lint-test.bib")]);
+
+        let issues: Vec<(String, LintRule)> = lint(&biblio, &Lint::default())
+            .into_iter()
+            .map(|i| (i.citekey, i.rule))
+            .collect();
+        assert_eq!(
+            issues,
+            vec![
+                ("incomplete".to_string(), LintRule::MissingField),
+                ("incomplete".to_string(), LintRule::MalformedDate),
+                ("child".to_string(), LintRule::DanglingCrossref),
+                ("child".to_string(), LintRule::MissingFile),
+                ("literal".to_string(), LintRule::MalformedDate),
+            ]
+        );
+        let settings = Lint {
+            disabled_rules: vec![
+                LintRule::MalformedDate,
+                LintRule::DanglingCrossref,
+                LintRule::MissingFile,
+            ],
+            required_fields: [("article".to_string(), vec!["doi".to_string()])].into(),
+        };
+        let issues = lint(&biblio, &settings);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(
+            issues[0].summary(),
+            "lint-test.bib:1: incomplete: Missing required field(s) for type article: author, doi, journaltitle [missing-field]"
+        );
+    }
+}
@@ -0,0 +1,841 @@
+// bibiman - a TUI for managing BibLaTeX databases
+// Copyright (C) 2024  lukeflo
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+/////
+
+//! Fetch new entries by DOI, ISBN, arXiv ID or PubMed ID.
+//!
+//! Every kind of identifier is handled by a [`Resolver`], which recognizes
+//! the identifier in the input of the user and fetches the entry from a web
+//! service. The base URLs of the services are set in the `[lookup]` section
+//! of the config.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use futures::future::BoxFuture;
+use quick_xml::events::Event as XmlEvent;
+use quick_xml::Reader;
+use reqwest::header::ACCEPT;
+use reqwest::StatusCode;
+use serde_json::Value;
+use tokio::task::JoinHandle;
+
+use crate::config::Lookup;
+
+/// Why fetching an entry failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LookupError {
+    // The service doesn't know the identifier
+    NotFound,
+    // The service couldn't be reached or answered with an error
+    Network(String),
+    // The answer of the service couldn't be read
+    Malformed(String),
+    // No answer within the configured time
+    TimedOut,
+}
+
+impl fmt::Display for LookupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "not found"),
+            Self::Network(e) => write!(f, "network failure: {}", e),
+            Self::Malformed(e) => write!(f, "unexpected answer: {}", e),
+            Self::TimedOut => write!(f, "timed out"),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, LookupError>;
+
+/// Fetched entry or the reason why fetching failed
+pub type LookupResult = Result<String>;
+
+// Frames of the spinner shown while a lookup runs
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// Lookup running as a background task
+#[derive(Debug)]
+pub struct PendingLookup {
+    // Kind of identifier and the identifier itself, used in messages
+    pub name: &'static str,
+    pub id: String,
+    started: Instant,
+    task: JoinHandle<()>,
+}
+
+impl PendingLookup {
+    /// Run the lookup as a task, which calls `progress` regularly while
+    /// waiting and passes the result to `done`. Must be called inside the
+    /// tokio runtime
+    pub fn spawn(
+        resolver: &dyn Resolver,
+        id: String,
+        timeout: Duration,
+        progress: impl Fn() + Send + 'static,
+        done: impl FnOnce(LookupResult) + Send + 'static,
+    ) -> Self {
+        let lookup = tokio::time::timeout(timeout, resolver.resolve(id.clone()));
+        let task = tokio::spawn(async move {
+            tokio::pin!(lookup);
+            let mut interval = tokio::time::interval(Duration::from_millis(100));
+            let result = loop {
+                tokio::select! {
+                    result = &mut lookup => break result.unwrap_or(Err(LookupError::TimedOut)),
+                    _ = interval.tick() => progress(),
+                }
+            };
+            done(result);
+        });
+        Self {
+            name: resolver.name(),
+            id,
+            started: Instant::now(),
+            task,
+        }
+    }
+
+    /// Stop the lookup, `done` isn't called afterwards
+    pub fn cancel(self) {
+        self.task.abort();
+    }
+
+    /// Current frame of the spinner
+    pub fn spinner(&self) -> char {
+        SPINNER[(self.started.elapsed().as_millis() / 100) as usize % SPINNER.len()]
+    }
+}
+
+/// Recognizes one kind of identifier and fetches the entry for it
+pub trait Resolver: Send + Sync {
+    /// Kind of identifier, used in messages
+    fn name(&self) -> &'static str;
+
+    /// Normalized identifier if the input is one handled by this resolver
+    fn detect(&self, input: &str) -> Option<String>;
+
+    /// Whether the input is meant as this kind of identifier, but isn't a
+    /// valid one, e.g. because of a wrong check digit
+    fn is_malformed(&self, input: &str) -> bool;
+
+    /// Fetch the entry for a detected identifier as BibLaTeX
+    fn resolve(&self, id: String) -> BoxFuture<'static, Result<String>>;
+}
+
+/// All resolvers, in the order they are tried
+pub fn resolvers(settings: &Lookup) -> Vec<Box<dyn Resolver>> {
+    vec![
+        Box::new(DoiResolver {
+            base_url: settings.doi_url.clone(),
+        }),
+        Box::new(ArxivResolver {
+            base_url: settings.arxiv_url.clone(),
+        }),
+        Box::new(IsbnResolver {
+            base_url: settings.isbn_url.clone(),
+        }),
+        Box::new(PubmedResolver {
+            base_url: settings.pubmed_url.clone(),
+        }),
+    ]
+}
+
+/// First resolver which recognizes the input and the normalized identifier
+pub fn detect<'a>(
+    resolvers: &'a [Box<dyn Resolver>],
+    input: &str,
+) -> Option<(&'a dyn Resolver, String)> {
+    resolvers
+        .iter()
+        .find_map(|r| r.detect(input).map(|id| (r.as_ref(), id)))
+}
+
+/// Resolver whose kind of identifier the input resembles, if it isn't
+/// detected by any resolver
+pub fn malformed<'a>(resolvers: &'a [Box<dyn Resolver>], input: &str) -> Option<&'a dyn Resolver> {
+    resolvers
+        .iter()
+        .find(|r| r.is_malformed(input))
+        .map(|r| r.as_ref())
+}
+
+pub struct DoiResolver {
+    base_url: String,
+}
+
+impl Resolver for DoiResolver {
+    fn name(&self) -> &'static str {
+        "DOI"
+    }
+
+    fn detect(&self, input: &str) -> Option<String> {
+        let doi = strip_prefixes(input, DOI_PREFIXES);
+        let (prefix, suffix) = doi.split_once('/')?;
+        let registrant = prefix.strip_prefix("10.")?;
+        (!registrant.is_empty()
+            && registrant.chars().all(|c| c.is_ascii_digit() || c == '.')
+            && !suffix.is_empty()
+            && !suffix.contains(char::is_whitespace))
+        .then(|| doi.to_string())
+    }
+
+    fn is_malformed(&self, input: &str) -> bool {
+        has_prefix(input, DOI_PREFIXES) || input.trim().starts_with("10.")
+    }
+
+    fn resolve(&self, id: String) -> BoxFuture<'static, Result<String>> {
+        let url = format!("{}/{}", self.base_url.trim_end_matches('/'), id);
+        Box::pin(async move {
+            // doi.org returns the entry as BibTeX through content negotiation
+            let entry = fetch(&url, "application/x-bibtex").await?;
+            if entry.trim_start().starts_with('@') {
+                Ok(entry)
+            } else {
+                Err(LookupError::Malformed(String::from("no BibTeX entry")))
+            }
+        })
+    }
+}
+
+/// Books via the Open Library API
+pub struct IsbnResolver {
+    base_url: String,
+}
+
+impl Resolver for IsbnResolver {
+    fn name(&self) -> &'static str {
+        "ISBN"
+    }
+
+    fn detect(&self, input: &str) -> Option<String> {
+        let isbn: String = strip_prefixes(input, &["isbn:"])
+            .chars()
+            .filter(|c| *c != '-' && *c != ' ')
+            .collect();
+        let digits: Vec<u32> = isbn
+            .chars()
+            .enumerate()
+            .map(|(idx, c)| match c {
+                'X' | 'x' if idx == 9 && isbn.len() == 10 => Some(10),
+                c => c.to_digit(10),
+            })
+            .collect::<Option<Vec<u32>>>()?;
+        let valid = match digits.len() {
+            10 => {
+                digits
+                    .iter()
+                    .zip((1..=10).rev())
+                    .map(|(d, w)| d * w)
+                    .sum::<u32>()
+                    % 11
+                    == 0
+            }
+            13 => {
+                digits
+                    .iter()
+                    .zip([1, 3].into_iter().cycle())
+                    .map(|(d, w)| d * w)
+                    .sum::<u32>()
+                    % 10
+                    == 0
+            }
+            _ => false,
+        };
+        valid.then(|| isbn.to_uppercase())
+    }
+
+    fn is_malformed(&self, input: &str) -> bool {
+        // Numbers with the length of an ISBN and a wrong check digit
+        let isbn: String = strip_prefixes(input, &["isbn:"])
+            .chars()
+            .filter(|c| *c != '-' && *c != ' ')
+            .collect();
+        has_prefix(input, &["isbn:"])
+            || [10, 13].contains(&isbn.len())
+                && isbn
+                    .chars()
+                    .all(|c| c.is_ascii_digit() || c.eq_ignore_ascii_case(&'x'))
+    }
+
+    fn resolve(&self, id: String) -> BoxFuture<'static, Result<String>> {
+        let url = format!(
+            "{}/api/books?bibkeys=ISBN:{}&format=json&jscmd=data",
+            self.base_url.trim_end_matches('/'),
+            id
+        );
+        Box::pin(async move {
+            let response = parse_json(&fetch(&url, "application/json").await?)?;
+            let book = response
+                .get(format!("ISBN:{}", id))
+                .ok_or(LookupError::NotFound)?;
+            let names = |key: &str| -> Vec<String> {
+                book[key]
+                    .as_array()
+                    .map(|list| {
+                        list.iter()
+                            .filter_map(|v| v["name"].as_str().map(String::from))
+                            .collect()
+                    })
+                    .unwrap_or_default()
+            };
+            let authors: Vec<String> = names("authors").iter().map(|n| invert_name(n)).collect();
+            let year = first_year(book["publish_date"].as_str().unwrap_or_default());
+
+            Ok(BibEntry {
+                entry_type: "book",
+                citekey: citekey(&authors, &year),
+                fields: vec![
+                    ("author", authors.join(" and ")),
+                    ("title", text(&book["title"])),
+                    ("subtitle", text(&book["subtitle"])),
+                    ("publisher", names("publishers").join(" and ")),
+                    ("location", names("publish_places").join(" and ")),
+                    ("year", year),
+                    ("pagetotal", text(&book["number_of_pages"])),
+                    ("isbn", id),
+                ],
+            }
+            .to_string())
+        })
+    }
+}
+
+/// Preprints via the arXiv API
+pub struct ArxivResolver {
+    base_url: String,
+}
+
+impl Resolver for ArxivResolver {
+    fn name(&self) -> &'static str {
+        "arXiv ID"
+    }
+
+    fn detect(&self, input: &str) -> Option<String> {
+        let id = strip_prefixes(input, ARXIV_PREFIXES);
+        let id = id.strip_suffix(".pdf").unwrap_or(id);
+        // Version suffix like `v2` is optional
+        let base = match id.rsplit_once('v') {
+            Some((base, version))
+                if !version.is_empty() && version.chars().all(|c| c.is_ascii_digit()) =>
+            {
+                base
+            }
+            _ => id,
+        };
+        let new_style = base.split_once('.').is_some_and(|(month, number)| {
+            month.len() == 4
+                && (4..=5).contains(&number.len())
+                && month
+                    .chars()
+                    .chain(number.chars())
+                    .all(|c| c.is_ascii_digit())
+        });
+        // Old identifiers like `hep-th/9901001` or `math.GT/0309136`
+        let old_style = base.split_once('/').is_some_and(|(archive, number)| {
+            !archive.is_empty()
+                && archive
+                    .chars()
+                    .all(|c| c.is_ascii_alphabetic() || c == '-' || c == '.')
+                && number.len() == 7
+                && number.chars().all(|c| c.is_ascii_digit())
+        });
+        (new_style || old_style).then(|| id.to_string())
+    }
+
+    fn is_malformed(&self, input: &str) -> bool {
+        has_prefix(input, ARXIV_PREFIXES)
+    }
+
+    fn resolve(&self, id: String) -> BoxFuture<'static, Result<String>> {
+        let url = format!(
+            "{}/api/query?id_list={}",
+            self.base_url.trim_end_matches('/'),
+            id
+        );
+        Box::pin(async move {
+            let feed = fetch(&url, "application/atom+xml").await?;
+            let preprint = parse_arxiv_feed(&feed)?;
+            let date = preprint.published.get(..10).unwrap_or_default().to_string();
+            let year = first_year(&date);
+
+            Ok(BibEntry {
+                entry_type: "online",
+                citekey: citekey(&preprint.authors, &year),
+                fields: vec![
+                    ("author", preprint.authors.join(" and ")),
+                    ("title", preprint.title),
+                    ("date", date),
+                    ("eprint", id.clone()),
+                    ("eprinttype", String::from("arxiv")),
+                    ("eprintclass", preprint.category),
+                    ("doi", preprint.doi),
+                    ("url", format!("https://arxiv.org/abs/{}", id)),
+                    ("abstract", preprint.summary),
+                ],
+            }
+            .to_string())
+        })
+    }
+}
+
+/// Articles via the esummary service of the NCBI E-utilities
+pub struct PubmedResolver {
+    base_url: String,
+}
+
+impl Resolver for PubmedResolver {
+    fn name(&self) -> &'static str {
+        "PubMed ID"
+    }
+
+    fn detect(&self, input: &str) -> Option<String> {
+        let id = strip_prefixes(input, PUBMED_PREFIXES).trim_end_matches('/');
+        // Longer numbers are ISBNs
+        ((1..=8).contains(&id.len()) && id.chars().all(|c| c.is_ascii_digit()))
+            .then(|| id.to_string())
+    }
+
+    fn is_malformed(&self, input: &str) -> bool {
+        has_prefix(input, PUBMED_PREFIXES)
+    }
+
+    fn resolve(&self, id: String) -> BoxFuture<'static, Result<String>> {
+        let url = format!(
+            "{}/entrez/eutils/esummary.fcgi?db=pubmed&retmode=json&id={}",
+            self.base_url.trim_end_matches('/'),
+            id
+        );
+        Box::pin(async move {
+            let response = parse_json(&fetch(&url, "application/json").await?)?;
+            let article = &response["result"][&id];
+            if !article.is_object() || article.get("error").is_some() {
+                return Err(LookupError::NotFound);
+            }
+            // Names are listed as `Doe JA`
+            let authors: Vec<String> = article["authors"]
+                .as_array()
+                .map(|list| {
+                    list.iter()
+                        .filter(|a| a["authtype"].as_str().is_none_or(|t| t == "Author"))
+                        .filter_map(|a| a["name"].as_str())
+                        .map(|name| match name.rsplit_once(' ') {
+                            Some((last, initials)) => {
+                                let initials: Vec<String> =
+                                    initials.chars().map(|c| format!("{}.", c)).collect();
+                                format!("{}, {}", last, initials.join(" "))
+                            }
+                            None => name.to_string(),
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            let year = first_year(article["pubdate"].as_str().unwrap_or_default());
+            let doi = article["articleids"]
+                .as_array()
+                .and_then(|ids| ids.iter().find(|i| i["idtype"] == "doi"))
+                .map(|i| text(&i["value"]))
+                .unwrap_or_default();
+            let journal = match text(&article["fulljournalname"]) {
+                name if name.is_empty() => text(&article["source"]),
+                name => name,
+            };
+
+            Ok(BibEntry {
+                entry_type: "article",
+                citekey: citekey(&authors, &year),
+                fields: vec![
+                    ("author", authors.join(" and ")),
+                    (
+                        "title",
+                        text(&article["title"]).trim_end_matches('.').to_string(),
+                    ),
+                    ("journaltitle", journal),
+                    ("year", year),
+                    ("volume", text(&article["volume"])),
+                    ("number", text(&article["issue"])),
+                    ("pages", text(&article["pages"]).replace('-', "--")),
+                    ("doi", doi),
+                    ("eprint", id.clone()),
+                    ("eprinttype", String::from("pubmed")),
+                ],
+            }
+            .to_string())
+        })
+    }
+}
+
+// Fields of an arXiv preprint
+#[derive(Debug, Default)]
+struct Preprint {
+    title: String,
+    authors: Vec<String>,
+    published: String,
+    summary: String,
+    category: String,
+    doi: String,
+}
+
+// Read the first entry of an Atom feed returned by the arXiv API
+fn parse_arxiv_feed(feed: &str) -> Result<Preprint> {
+    let malformed = || LookupError::Malformed(String::from("invalid Atom feed"));
+    let mut reader = Reader::from_str(feed);
+    let mut preprint = Preprint::default();
+    let mut path: Vec<String> = Vec::new();
+    let mut in_entry = false;
+    loop {
+        match reader.read_event().map_err(|_| malformed())? {
+            XmlEvent::Start(tag) => {
+                let name = String::from_utf8_lossy(tag.local_name().as_ref()).to_string();
+                in_entry |= name == "entry";
+                path.push(name);
+            }
+            XmlEvent::Empty(tag)
+                if in_entry && tag.local_name().as_ref() == b"primary_category" =>
+            {
+                if let Ok(Some(term)) = tag.try_get_attribute("term") {
+                    preprint.category = term.unescape_value().map_err(|_| malformed())?.to_string();
+                }
+            }
+            XmlEvent::Text(text) if in_entry => {
+                let text = text.unescape().map_err(|_| malformed())?;
+                let field = match path
+                    .iter()
+                    .rev()
+                    .take(2)
+                    .map(|s| s.as_str())
+                    .collect::<Vec<_>>()[..]
+                {
+                    ["title", "entry"] => &mut preprint.title,
+                    ["summary", "entry"] => &mut preprint.summary,
+                    ["published", "entry"] => &mut preprint.published,
+                    ["doi", "entry"] => &mut preprint.doi,
+                    ["name", "author"] => {
+                        preprint.authors.push(String::new());
+                        preprint.authors.last_mut().ok_or_else(malformed)?
+                    }
+                    _ => continue,
+                };
+                field.push_str(&text);
+            }
+            XmlEvent::End(tag) => {
+                path.pop();
+                if tag.local_name().as_ref() == b"entry" {
+                    break;
+                }
+            }
+            XmlEvent::Eof => break,
+            _ => {}
+        }
+    }
+
+    // An unknown ID returns an entry titled "Error" without authors
+    if preprint.title.is_empty() || preprint.authors.is_empty() {
+        return Err(LookupError::NotFound);
+    }
+    // Long values are wrapped in the feed
+    let clean = |s: &str| s.split_whitespace().collect::<Vec<&str>>().join(" ");
+    preprint.title = clean(&preprint.title);
+    preprint.summary = clean(&preprint.summary);
+    preprint.authors = preprint
+        .authors
+        .iter()
+        .map(|a| invert_name(&clean(a)))
+        .collect();
+    Ok(preprint)
+}
+
+/// Entry built from the fields returned by a web service. Empty fields are
+/// left out
+pub struct BibEntry {
+    pub entry_type: &'static str,
+    pub citekey: String,
+    pub fields: Vec<(&'static str, String)>,
+}
+
+impl std::fmt::Display for BibEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "@{}{{{}", self.entry_type, self.citekey)?;
+        for (field, value) in &self.fields {
+            if !value.trim().is_empty() {
+                write!(f, ",\n  {} = {{{}}}", field, value.trim())?;
+            }
+        }
+        write!(f, ",\n}}")
+    }
+}
+
+async fn fetch(url: &str, accept: &str) -> Result<String> {
+    let client = reqwest::Client::builder()
+        .user_agent(concat!(
+            env!("CARGO_PKG_NAME"),
+            "/",
+            env!("CARGO_PKG_VERSION")
+        ))
+        .build()
+        .map_err(network_error)?;
+    let response = client
+        .get(url)
+        .header(ACCEPT, accept)
+        .send()
+        .await
+        .map_err(network_error)?;
+    match response.status() {
+        StatusCode::NOT_FOUND | StatusCode::GONE => Err(LookupError::NotFound),
+        status if !status.is_success() => Err(LookupError::Network(format!(
+            "server answered with {}",
+            status
+        ))),
+        _ => response.text().await.map_err(network_error),
+    }
+}
+
+// The innermost cause is the most specific one, e.g. "Connection refused"
+fn network_error(e: reqwest::Error) -> LookupError {
+    let mut cause: &dyn std::error::Error = &e;
+    while let Some(source) = cause.source() {
+        cause = source;
+    }
+    LookupError::Network(cause.to_string())
+}
+
+fn parse_json(text: &str) -> Result<Value> {
+    serde_json::from_str(text).map_err(|e| LookupError::Malformed(e.to_string()))
+}
+
+const DOI_PREFIXES: &[&str] = &[
+    "https://doi.org/",
+    "http://doi.org/",
+    "https://dx.doi.org/",
+    "doi:",
+];
+const ARXIV_PREFIXES: &[&str] = &[
+    "https://arxiv.org/abs/",
+    "http://arxiv.org/abs/",
+    "arxiv.org/abs/",
+    "arxiv:",
+];
+const PUBMED_PREFIXES: &[&str] = &["https://pubmed.ncbi.nlm.nih.gov/", "pmid:", "pubmed:"];
+
+fn has_prefix(input: &str, prefixes: &[&str]) -> bool {
+    strip_prefixes(input, prefixes).len() != input.trim().len()
+}
+
+// Remove one of the prefixes, ignoring case
+fn strip_prefixes<'a>(input: &'a str, prefixes: &[&str]) -> &'a str {
+    let input = input.trim();
+    prefixes
+        .iter()
+        .find_map(|prefix| {
+            input
+                .get(..prefix.len())
+                .filter(|start| start.eq_ignore_ascii_case(prefix))
+                .map(|_| input[prefix.len()..].trim_start())
+        })
+        .unwrap_or(input)
+}
+
+/// `Jane Doe` to `Doe, Jane`
+pub fn invert_name(name: &str) -> String {
+    match name.trim().rsplit_once(' ') {
+        Some((first, last)) if !name.contains(',') => format!("{}, {}", last, first),
+        _ => name.trim().to_string(),
+    }
+}
+
+pub fn first_year(date: &str) -> String {
+    date.split(|c: char| !c.is_ascii_digit())
+        .find(|part| part.len() == 4)
+        .unwrap_or_default()
+        .to_string()
+}
+
+// Strings and numbers of a JSON response as text
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.trim().to_string(),
+        Value::Number(n) => n.to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Citekey like the ones doi.org generates, e.g. `Doe_2020`
+pub fn citekey(authors: &[String], year: &str) -> String {
+    let last_name: String = authors
+        .first()
+        .and_then(|a| a.split(',').next())
+        .unwrap_or("Anonymous")
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect();
+    if year.is_empty() {
+        last_name
+    } else {
+        format!("{}_{}", last_name, year)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    // Answer every request with the response whose path prefix matches
+    fn mock_server(responses: Vec<(&'static str, &'static str)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = [0; 4096];
+                let len = stream.read(&mut request).unwrap();
+                let request = String::from_utf8_lossy(&request[..len]);
+                let path = request.split_whitespace().nth(1).unwrap_or_default();
+                let body = responses
+                    .iter()
+                    .find(|(prefix, _)| path.starts_with(prefix))
+                    .map_or("{}", |(_, body)| body);
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn detect_identifiers() {
+        let resolvers = resolvers(&Lookup::default());
+        let detected =
+            |input: &str| detect(&resolvers, input).map(|(r, id)| (r.name().to_string(), id));
+        let pair = |name: &str, id: &str| Some((name.to_string(), id.to_string()));
+
+        assert_eq!(
+            detected("https://doi.org/10.1109/5.771073"),
+            pair("DOI", "10.1109/5.771073")
+        );
+        assert_eq!(detected("978-3-16-148410-0"), pair("ISBN", "9783161484100"));
+        assert_eq!(detected("0-8044-2957-x"), pair("ISBN", "080442957X"));
+        assert_eq!(
+            detected("arXiv:2101.00001v2"),
+            pair("arXiv ID", "2101.00001v2")
+        );
+        assert_eq!(
+            detected("hep-th/9901001"),
+            pair("arXiv ID", "hep-th/9901001")
+        );
+        assert_eq!(detected("PMID: 31452104"), pair("PubMed ID", "31452104"));
+        // Wrong check digit
+        assert_eq!(detected("978-3-16-148410-1"), None);
+        assert_eq!(detected("no identifier"), None);
+
+        let malformed = |input: &str| malformed(&resolvers, input).map(|r| r.name());
+        assert_eq!(malformed("978-3-16-148410-1"), Some("ISBN"));
+        assert_eq!(malformed("doi:10.1109"), Some("DOI"));
+        assert_eq!(malformed("arXiv:21.1"), Some("arXiv ID"));
+        assert_eq!(malformed("no identifier"), None);
+    }
+
+    #[tokio::test]
+    async fn resolve_identifiers() {
+        let url = mock_server(vec![
+            (
+                "/api/books",
+                r#"{"ISBN:9783161484100": {"title": "A Book", "authors": [{"name": "Jane Doe"}], "publishers": [{"name": "Press"}], "publish_date": "March 2001", "number_of_pages": 321}}"#,
+            ),
+            (
+                "/api/query",
+                r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom" xmlns:arxiv="http://arxiv.org/schemas/atom">
+  <title>ArXiv Query</title>
+  <entry>
+    <id>http://arxiv.org/abs/2101.00001v1</id>
+    <published>2021-01-01T10:00:00Z</published>
+    <title>A Preprint
+      about Things</title>
+    <summary>An abstract.</summary>
+    <author><name>John Smith</name></author>
+    <author><name>Jane Doe</name></author>
+    <arxiv:primary_category term="cs.LG" scheme="http://arxiv.org/schemas/atom"/>
+  </entry>
+</feed>"#,
+            ),
+            (
+                "/entrez/eutils/esummary.fcgi",
+                r#"{"result": {"uids": ["123"], "123": {"title": "An article.", "authors": [{"name": "Doe JA", "authtype": "Author"}], "fulljournalname": "Journal", "pubdate": "2019 Aug 27", "volume": "5", "issue": "2", "pages": "10-20", "articleids": [{"idtype": "doi", "value": "10.1000/xyz"}]}}}"#,
+            ),
+        ]);
+        let settings = Lookup {
+            doi_url: url.clone(),
+            isbn_url: url.clone(),
+            arxiv_url: url.clone(),
+            pubmed_url: url,
+            ..Lookup::default()
+        };
+        let resolvers = resolvers(&settings);
+        let resolve = |input: &str| {
+            let (resolver, id) = detect(&resolvers, input).unwrap();
+            resolver.resolve(id)
+        };
+
+        assert_eq!(
+            resolve("9783161484100").await.unwrap(),
+            "@book{Doe_2001,\n  author = {Doe, Jane},\n  title = {A Book},\n  publisher = {Press},\n  year = {2001},\n  pagetotal = {321},\n  isbn = {9783161484100},\n}"
+        );
+        assert_eq!(
+            resolve("arxiv:2101.00001").await.unwrap(),
+            "@online{Smith_2021,\n  author = {Smith, John and Doe, Jane},\n  title = {A Preprint about Things},\n  date = {2021-01-01},\n  eprint = {2101.00001},\n  eprinttype = {arxiv},\n  eprintclass = {cs.LG},\n  url = {https://arxiv.org/abs/2101.00001},\n  abstract = {An abstract.},\n}"
+        );
+        assert_eq!(
+            resolve("pmid:123").await.unwrap(),
+            "@article{Doe_2019,\n  author = {Doe, J. A.},\n  title = {An article},\n  journaltitle = {Journal},\n  year = {2019},\n  volume = {5},\n  number = {2},\n  pages = {10--20},\n  doi = {10.1000/xyz},\n  eprint = {123},\n  eprinttype = {pubmed},\n}"
+        );
+        // The mock server returns an empty object for unknown books
+        assert_eq!(resolve("080442957X").await, Err(LookupError::NotFound));
+        assert!(matches!(
+            resolve("10.1000/unknown").await,
+            Err(LookupError::Malformed(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn time_out_lookup() {
+        // Connections are accepted, but never answered
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let _streams: Vec<_> = listener.incoming().collect();
+        });
+        let resolvers = resolvers(&Lookup {
+            doi_url: url,
+            ..Lookup::default()
+        });
+        let (resolver, id) = detect(&resolvers, "10.1000/182").unwrap();
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let _lookup = PendingLookup::spawn(
+            resolver,
+            id,
+            Duration::from_millis(300),
+            || {},
+            move |result| sender.send(result).unwrap(),
+        );
+        assert_eq!(receiver.recv().await, Some(Err(LookupError::TimedOut)));
+    }
+}
@@ -0,0 +1,195 @@
+// bibiman - a TUI for managing BibLaTeX databases
+// Copyright (C) 2024  lukeflo
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+/////
+
+//! New entries which are reviewed before they are written to a bibfile.
+
+use biblatex::{Bibliography, ChunksExt};
+use color_eyre::eyre::{eyre, Result};
+
+use super::bibedit;
+use super::bibisetup::BibiSetup;
+use super::citekey::{format_citekey, unique_citekey};
+use super::duplicates::{normalize_doi, DuplicateReason};
+use crate::config::Citekey;
+
+/// Fetched entry waiting for the citekey and bibfile to be confirmed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewEntry {
+    // Source text of the entry as fetched
+    pub content: String,
+    // Citekey the entry is written with
+    pub citekey: String,
+    // Citekey in the fetched source text
+    source_key: String,
+}
+
+impl NewEntry {
+    /// Read the first entry of a BibTeX or BibLaTeX text. The suggested
+    /// citekey is built from the pattern, or taken from the text if the
+    /// pattern gives an empty one, and doesn't exist yet
+    pub fn new(content: &str, biblio: &BibiSetup, cfg: &Citekey) -> Result<Self> {
+        let content = content.trim().to_string();
+        let (source_key, _) = bibedit::entries(&content)
+            .into_iter()
+            .next()
+            .ok_or_else(|| eyre!("No entry found in the fetched text"))?;
+        let mut citekey = Bibliography::parse(&content)
+            .ok()
+            .and_then(|bib| bib.get(&source_key).map(|entry| format_citekey(entry, cfg)))
+            .unwrap_or_default();
+        if citekey.is_empty() {
+            citekey = source_key
+                .chars()
+                .filter(|c| !invalid_citekey_char(*c))
+                .collect();
+        }
+        if citekey.is_empty() {
+            citekey = String::from("entry");
+        }
+        let citekey = unique_citekey(&citekey, |key| biblio.citekeys.iter().any(|k| k == key));
+        Ok(Self {
+            content,
+            citekey,
+            source_key,
+        })
+    }
+
+    /// Source text of the entry with the chosen citekey
+    pub fn with_citekey(&self) -> String {
+        let renames = [(self.source_key.clone(), self.citekey.clone())];
+        bibedit::rename_citekeys(&self.content, &renames).0
+    }
+
+    /// Problems shown while reviewing the entry. Only a problem with the
+    /// citekey prevents writing it
+    pub fn warnings(&self, biblio: &BibiSetup) -> Vec<String> {
+        let mut warnings: Vec<String> =
+            citekey_problem(&self.citekey, biblio).into_iter().collect();
+        if let Some((existing, reason)) = self.duplicate_of(biblio) {
+            warnings.push(format!("Entry {} has the same {}", existing, reason.name()));
+        }
+        warnings
+    }
+
+    /// Opened entry with the same DOI, or the same title and year
+    pub fn duplicate_of(&self, biblio: &BibiSetup) -> Option<(String, DuplicateReason)> {
+        let (doi, title_year) = self.identity();
+        if let Some(doi) = doi {
+            let existing = biblio
+                .bibliography
+                .iter()
+                .find(|e| e.doi().ok().and_then(|d| normalize_doi(&d)).as_ref() == Some(&doi));
+            if let Some(existing) = existing {
+                return Some((existing.key.clone(), DuplicateReason::Doi));
+            }
+        }
+        let (title, year) = title_year?;
+        biblio
+            .bibliography
+            .iter()
+            .find(|e| {
+                e.title()
+                    .is_ok_and(|t| title_key(&t.format_verbatim()) == title)
+                    && BibiSetup::get_year(&e.key, &biblio.bibliography) == year
+            })
+            .map(|e| (e.key.clone(), DuplicateReason::TitleYear))
+    }
+
+    /// Whether both entries have the same DOI, or the same title and year
+    pub fn same_as(&self, other: &NewEntry) -> bool {
+        let (doi, title_year) = self.identity();
+        let (other_doi, other_title_year) = other.identity();
+        doi.is_some() && doi == other_doi || title_year.is_some() && title_year == other_title_year
+    }
+
+    // Normalized DOI and title together with the year
+    fn identity(&self) -> (Option<String>, Option<(String, String)>) {
+        let field = |name| bibedit::get_field(&self.content, &self.source_key, name);
+        let doi = field("doi").and_then(|doi| normalize_doi(&doi));
+        let title_year = field("title").map(|t| title_key(&t)).and_then(|title| {
+            let year = field("year")
+                .or_else(|| field("date"))?
+                .get(..4)?
+                .to_string();
+            (!title.is_empty()).then_some((title, year))
+        });
+        (doi, title_year)
+    }
+}
+
+// Title reduced to lowercase letters and digits
+fn title_key(title: &str) -> String {
+    title
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Reason why the citekey can't be used for a new entry
+pub fn citekey_problem(citekey: &str, biblio: &BibiSetup) -> Option<String> {
+    if citekey.is_empty() {
+        Some(String::from("Citekey is empty"))
+    } else if let Some(c) = citekey.chars().find(|c| invalid_citekey_char(*c)) {
+        Some(format!("Citekey contains invalid character '{}'", c))
+    } else if biblio.citekeys.iter().any(|key| key == citekey) {
+        Some(format!("Citekey {} already exists", citekey))
+    } else {
+        None
+    }
+}
+
+/// Characters which BibLaTeX doesn't allow in citekeys
+pub fn invalid_citekey_char(c: char) -> bool {
+    c.is_whitespace() || "\"#'(),={}%\\~".contains(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn review_new_entry() {
+        let biblio = BibiSetup::new(&[PathBuf::from("tests/newentry-test.bib")]);
+
+        let fetched = " @article{Doe_2020,\n  author = {Doe, John},\n  title = {New},\n  date = {2020},\n  doi = {10.1000/abc}\n}\n";
+        let mut entry = NewEntry::new(fetched, &biblio, &Citekey::default()).unwrap();
+        assert_eq!(entry.citekey, "doe2020new");
+        assert_eq!(
+            entry.warnings(&biblio),
+            vec!["Entry existing has the same DOI"]
+        );
+
+        entry.citekey = String::from("existing");
+        assert_eq!(
+            entry.warnings(&biblio)[0],
+            "Citekey existing already exists"
+        );
+        entry.citekey = String::from("doe 2020");
+        assert_eq!(
+            citekey_problem(&entry.citekey, &biblio),
+            Some(String::from("Citekey contains invalid character ' '"))
+        );
+
+        entry.citekey = String::from("doe2020");
+        assert_eq!(
+            entry.with_citekey(),
+            "@article{doe2020,\n  author = {Doe, John},\n  title = {New},\n  date = {2020},\n  doi = {10.1000/abc}\n}"
+        );
+    }
+}
@@ -0,0 +1,423 @@
+// bibiman - a TUI for managing BibLaTeX databases
+// Copyright (C) 2024  lukeflo
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+/////
+
+//! Parser for search queries like `author:miller year:>2015 -type:book learning`.
+//!
+//! Terms with a field prefix, quoted phrases and negated terms are matched
+//! exactly against the entries. All remaining bare terms are joined and
+//! passed to the fuzzy matcher.
+
+use super::entries::EntryTableItem;
+use super::fulltext::normalize_text;
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Query {
+    // Terms which must (or must not) match exactly
+    pub terms: Vec<Term>,
+    // Bare terms for fuzzy matching, joined by whitespace
+    pub fuzzy: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Term {
+    pub negated: bool,
+    pub field: Field,
+    // Lowercased value to match
+    pub value: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Field {
+    Author,
+    Title,
+    // Inclusive range of years
+    Year(i32, i32),
+    Pubtype,
+    Keyword,
+    Citekey,
+    Abstract,
+    Doi,
+    File,
+    // Path of the bibfile the entry was read from
+    Source,
+    // Text of the connected PDF
+    Fulltext,
+    // Every field of the entry
+    Any,
+    // Any other BibLaTeX field, e.g. `journaltitle` or `publisher`
+    Other(String),
+}
+
+impl Query {
+    pub fn parse(query: &str) -> Self {
+        let mut parsed = Query::default();
+        let mut fuzzy: Vec<String> = Vec::new();
+
+        for token in tokenize(query) {
+            let (negated, token) = match token.strip_prefix('-') {
+                Some(rest) if !rest.is_empty() => (true, rest),
+                _ => (false, token.as_str()),
+            };
+
+            let (prefix, value) = match token.split_once(':') {
+                // Other prefixes, e.g. `https` of a pasted URL, are part of
+                // the value
+                Some((prefix, value)) if is_field_name(&prefix.to_lowercase()) => {
+                    (Some(prefix.to_lowercase()), value)
+                }
+                _ => (None, token),
+            };
+            let quoted = value.starts_with('"');
+            let value = normalize_text(value.trim_matches('"'));
+
+            // Incomplete terms like `author:` are ignored while typing
+            if value.is_empty() {
+                continue;
+            }
+
+            let field = match prefix.as_deref() {
+                None if !negated && !quoted => {
+                    fuzzy.push(value);
+                    continue;
+                }
+                None => Field::Any,
+                Some("author" | "au") => Field::Author,
+                Some("title" | "ti") => Field::Title,
+                Some("year" | "y") => match parse_years(&value) {
+                    Some((from, to)) => Field::Year(from, to),
+                    None => continue,
+                },
+                Some("type" | "pubtype") => Field::Pubtype,
+                Some("keyword" | "keywords" | "kw") => Field::Keyword,
+                Some("key" | "citekey") => Field::Citekey,
+                Some("abstract" | "abs") => Field::Abstract,
+                Some("doi" | "url") => Field::Doi,
+                Some("file") => Field::File,
+                Some("source" | "bibfile") => Field::Source,
+                Some("fulltext" | "ft" | "pdf") => Field::Fulltext,
+                Some("any") => Field::Any,
+                Some(other) => Field::Other(other.to_string()),
+            };
+            parsed.terms.push(Term {
+                negated,
+                field,
+                value,
+            });
+        }
+
+        parsed.fuzzy = fuzzy.join(" ");
+        parsed
+    }
+
+    /// Phrases which have to be found in the text of connected PDFs
+    pub fn fulltext_phrases(&self) -> Vec<String> {
+        self.terms
+            .iter()
+            .filter(|t| t.field == Field::Fulltext && !t.negated)
+            .map(|t| t.value.clone())
+            .collect()
+    }
+
+    /// Check if the entry matches all exact terms of the query. The fuzzy
+    /// part isn't checked here
+    pub fn matches(&self, entry: &EntryTableItem) -> bool {
+        self.terms.iter().all(|t| t.matches(entry) != t.negated)
+    }
+}
+
+impl Term {
+    fn matches(&self, entry: &EntryTableItem) -> bool {
+        // The value is normalized when parsing, thus, the text is too
+        let contains = |text: &str| normalize_text(text).contains(&self.value);
+        match &self.field {
+            Field::Author => contains(&entry.authors),
+            Field::Title => {
+                contains(&entry.title) || entry.subtitle.as_deref().is_some_and(contains)
+            }
+            Field::Year(from, to) => entry
+                .year
+                .parse::<i32>()
+                .is_ok_and(|year| (*from..=*to).contains(&year)),
+            Field::Pubtype => normalize_text(&entry.pubtype) == self.value,
+            Field::Keyword => entry
+                .keywords
+                .split(',')
+                .any(|k| normalize_text(k) == self.value),
+            Field::Citekey => contains(&entry.citekey),
+            Field::Abstract => contains(&entry.abstract_text),
+            Field::Doi => entry.doi_url.as_deref().is_some_and(contains),
+            Field::File => entry
+                .filepath
+                .as_ref()
+                .is_some_and(|f| contains(&f.to_string_lossy())),
+            Field::Source => entry
+                .source
+                .as_ref()
+                .is_some_and(|s| contains(&s.file.to_string_lossy())),
+            Field::Fulltext => entry
+                .fulltext
+                .as_deref()
+                .is_some_and(|t| t.contains(&self.value)),
+            Field::Any => {
+                [
+                    &entry.authors,
+                    &entry.title,
+                    &entry.year,
+                    &entry.pubtype,
+                    &entry.keywords,
+                    &entry.citekey,
+                    &entry.abstract_text,
+                ]
+                .iter()
+                .any(|text| contains(text))
+                    || entry.other_fields.iter().any(|(_, value)| contains(value))
+            }
+            Field::Other(name) => entry
+                .other_fields
+                .iter()
+                .any(|(field, value)| field == name && contains(value)),
+        }
+    }
+}
+
+// Aliases of the fields which have their own search terms
+const ALIASES: [&str; 23] = [
+    "author", "au", "title", "ti", "year", "y", "type", "pubtype", "keyword", "keywords", "kw",
+    "key", "citekey", "abstract", "abs", "doi", "url", "file", "source", "bibfile", "fulltext",
+    "ft", "pdf",
+];
+
+// Other BibLaTeX fields which can be searched
+const OTHER_FIELDS: [&str; 63] = [
+    "addendum",
+    "address",
+    "afterword",
+    "annotation",
+    "annotator",
+    "bookauthor",
+    "booksubtitle",
+    "booktitle",
+    "booktitleaddon",
+    "chapter",
+    "commentator",
+    "crossref",
+    "date",
+    "edition",
+    "editor",
+    "editora",
+    "editorb",
+    "editorc",
+    "eid",
+    "eprint",
+    "eprintclass",
+    "eprinttype",
+    "eventdate",
+    "eventtitle",
+    "foreword",
+    "holder",
+    "howpublished",
+    "institution",
+    "introduction",
+    "isbn",
+    "issn",
+    "issue",
+    "issuetitle",
+    "journal",
+    "journalsubtitle",
+    "journaltitle",
+    "langid",
+    "language",
+    "library",
+    "location",
+    "maintitle",
+    "month",
+    "note",
+    "number",
+    "organization",
+    "origdate",
+    "origlanguage",
+    "origlocation",
+    "origpublisher",
+    "origtitle",
+    "pages",
+    "pagetotal",
+    "part",
+    "publisher",
+    "pubstate",
+    "school",
+    "series",
+    "shorttitle",
+    "subtitle",
+    "translator",
+    "urldate",
+    "venue",
+    "volume",
+];
+
+// Whether the prefix of a `prefix:value` term names a field
+fn is_field_name(prefix: &str) -> bool {
+    prefix == "any" || ALIASES.contains(&prefix) || OTHER_FIELDS.contains(&prefix)
+}
+
+// Split the query at whitespace, but keep quoted phrases together
+fn tokenize(query: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+
+    for c in query.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                current.push(c);
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+// Parse year specifications like `2015`, `>2015`, `<=2015` or `2010..2015`
+fn parse_years(value: &str) -> Option<(i32, i32)> {
+    if let Some((from, to)) = value.split_once("..") {
+        let from = if from.is_empty() {
+            i32::MIN
+        } else {
+            from.parse().ok()?
+        };
+        let to = if to.is_empty() {
+            i32::MAX
+        } else {
+            to.parse().ok()?
+        };
+        return Some((from, to));
+    }
+    if let Some(year) = value.strip_prefix(">=") {
+        Some((year.parse().ok()?, i32::MAX))
+    } else if let Some(year) = value.strip_prefix("<=") {
+        Some((i32::MIN, year.parse().ok()?))
+    } else if let Some(year) = value.strip_prefix('>') {
+        Some((year.parse::<i32>().ok()?.saturating_add(1), i32::MAX))
+    } else if let Some(year) = value.strip_prefix('<') {
+        Some((i32::MIN, year.parse::<i32>().ok()?.saturating_sub(1)))
+    } else {
+        let year = value.parse().ok()?;
+        Some((year, year))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::bibiman::bibisetup::EntrySource;
+
+    fn test_entry() -> EntryTableItem {
+        EntryTableItem {
+            authors: "Miller, Schmitz".to_string(),
+            short_author: "".to_string(),
+            title: "Deep Learning".to_string(),
+            year: "2018".to_string(),
+            pubtype: "article".to_string(),
+            keywords: "machine learning, statistics".to_string(),
+            citekey: "miller_2018".to_string(),
+            abstract_text: "An abstract about neural\n  networks".to_string(),
+            doi_url: None,
+            filepath: None,
+            subtitle: None,
+            other_fields: vec![("journaltitle".to_string(), "Nature".to_string())],
+            fulltext: None,
+            source: Some(EntrySource {
+                file: PathBuf::from("/home/user/thesis.bib"),
+                span: 0..100,
+                line: 1,
+            }),
+        }
+    }
+
+    #[test]
+    fn parse_query() {
+        let query = Query::parse(r#"author:miller -type:book "deep learning" fuzzy year:>2015"#);
+
+        assert_eq!(query.fuzzy, "fuzzy");
+        assert_eq!(
+            query.terms,
+            vec![
+                Term {
+                    negated: false,
+                    field: Field::Author,
+                    value: "miller".to_string()
+                },
+                Term {
+                    negated: true,
+                    field: Field::Pubtype,
+                    value: "book".to_string()
+                },
+                Term {
+                    negated: false,
+                    field: Field::Any,
+                    value: "deep learning".to_string()
+                },
+                Term {
+                    negated: false,
+                    field: Field::Year(2016, i32::MAX),
+                    value: ">2015".to_string()
+                },
+            ]
+        );
+        // Incomplete terms are skipped
+        assert_eq!(Query::parse("author: year:>"), Query::default());
+        // Unknown prefixes, e.g. of URLs, don't make a field term
+        let query = Query::parse("https://doi.org/10.1000/182 -foo:bar");
+        assert_eq!(query.fuzzy, "https://doi.org/10.1000/182");
+        assert_eq!(
+            query.terms,
+            vec![Term {
+                negated: true,
+                field: Field::Any,
+                value: "foo:bar".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn match_query() {
+        let entry = test_entry();
+        let matches = |q: &str| Query::parse(q).matches(&entry);
+
+        assert!(matches("author:miller year:2010..2020 type:article"));
+        assert!(matches(r#"title:"deep learning" kw:statistics"#));
+        assert!(matches("abstract:neural journaltitle:nature"));
+        // Line breaks and double spaces in the field don't matter
+        assert!(matches(r#"abstract:"neural networks""#));
+        assert!(matches("-kw:learning"));
+        assert!(!matches("year:<2018"));
+        assert!(!matches("-author:schmitz"));
+        assert!(!matches("publisher:oxford"));
+        assert!(matches("source:thesis.bib"));
+        assert!(!matches("source:other.bib"));
+    }
+}
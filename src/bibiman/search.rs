@@ -16,17 +16,24 @@
 /////
 
 use super::entries::EntryTableItem;
+use super::keywords::{matches_keyword_filter, KeywordFilter};
+use super::query::Query;
 use nucleo_matcher::{
     pattern::{CaseMatching, Normalization, Pattern},
     Config, Matcher,
 };
-use std::{collections::HashMap, ffi::OsStr, fs, path::PathBuf};
+use std::collections::HashMap;
 
 #[derive(Debug, Default)]
 pub struct BibiSearch {
     pub search_string: String, // Search string show in footer, used for search
     pub inner_search: bool,    // True, if we trigger a search for already filtered list
     pub filtered_tag_list: Vec<String>,
+    pub fulltext_phrases: Vec<String>, // Phrases of the last full-text search
+    // Confirmed searches of the entry table, reapplied after a reload
+    pub applied_searches: Vec<String>,
+    // Number of applied searches made before filtering by keywords
+    pub filter_start: usize,
 }
 
 impl BibiSearch {
@@ -48,6 +55,17 @@ impl BibiSearch {
         search_pattern: &str,
         orig_list: Vec<EntryTableItem>,
     ) -> Vec<EntryTableItem> {
+        // Field-scoped, quoted and negated terms are matched exactly first,
+        // the remaining bare terms are used for fuzzy matching
+        let query = Query::parse(search_pattern);
+        let orig_list = orig_list.into_iter().filter(|e| query.matches(e));
+        if query.fuzzy.is_empty() {
+            let mut filtered_list: Vec<EntryTableItem> = orig_list.collect();
+            filtered_list.sort();
+            return filtered_list;
+        }
+        let search_pattern = query.fuzzy.as_str();
+
         // Create a hashmap to connect stingified entry with entry vec
         let mut entry_string_hm: HashMap<String, EntryTableItem> = HashMap::new();
 
@@ -92,32 +110,16 @@ impl BibiSearch {
         filtered_matches
     }
 
-    pub fn filter_entries_by_tag(
-        keyword: &str,
-        orig_list: &Vec<EntryTableItem>,
+    pub fn filter_entries_by_tags(
+        filters: &[KeywordFilter],
+        orig_list: &[EntryTableItem],
     ) -> Vec<EntryTableItem> {
-        let mut filtered_list: Vec<EntryTableItem> = Vec::new();
-
-        // Loop over the whole given entry table
-        // Check if the selected keyword is present in the current entry
-        // If present, push the entry to the filtered list
-        for e in orig_list {
-            if e.keywords.contains(keyword) {
-                filtered_list.push(e.to_owned());
-            }
-        }
-
-        filtered_list
-    }
-}
-
-pub fn search_pattern_in_file<'a>(pattern: &str, file: &'a PathBuf) -> Option<&'a OsStr> {
-    let content = fs::read_to_string(file).unwrap();
-
-    if content.contains(pattern) {
-        Some(file.as_os_str())
-    } else {
-        None
+        // Keep all entries whose keywords match the whole filter chain
+        orig_list
+            .iter()
+            .filter(|e| matches_keyword_filter(filters, &e.keywords))
+            .cloned()
+            .collect()
     }
 }
 
@@ -139,6 +141,9 @@ mod tests {
             doi_url: Some("https://www.bibiman.org".to_string()),
             filepath: Some("/home/file/path.pdf".to_string().into()),
             subtitle: None,
+            other_fields: Vec::new(),
+            fulltext: None,
+            source: None,
         };
 
         let joined_vec = BibiSearch::convert_to_string(&bibvec);
@@ -19,10 +19,14 @@ use color_eyre::eyre::Result;
 use color_eyre::owo_colors::OwoColorize;
 use lexopt::prelude::*;
 use std::env;
+use std::ffi::OsString;
 use std::path::PathBuf;
 use walkdir::WalkDir;
 
-use crate::tui::colors::AppColors;
+use crate::app::expand_home;
+use crate::bibiman::bibyaml;
+use crate::bibiman::export::ExportFormat;
+use crate::config::BibiConfig;
 
 // struct for CLIArgs
 #[derive(Debug, Default, Clone)]
@@ -31,9 +35,44 @@ pub struct CLIArgs {
     pub versionarg: bool,
     pub pos_args: Vec<PathBuf>,
     pub files: Vec<PathBuf>,
-    // INFO: AppColors struct later should be moved to config/app struct
-    // when config file is implemented
-    pub colors: AppColors,
+    pub config_path: Option<PathBuf>,
+    pub light_terminal: bool,
+    pub subcommand: Option<SubCmd>,
+    pub format: OutputFormat,
+    // Search pattern selecting the exported entries
+    pub query: Option<String>,
+    // Bibfile imported entries are appended to
+    pub target: Option<PathBuf>,
+}
+
+/// Subcommands which print to stdout instead of starting the TUI
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubCmd {
+    // List all entries
+    List,
+    // List entries matching the search pattern
+    Search(String),
+    // Show all fields of the entry with the given citekey
+    Show(String),
+    // List all keywords
+    Keywords,
+    // Print entries in another bibliography format
+    Export(ExportFormat),
+    // List problems found while loading the bibfiles
+    Check,
+    // List entries violating the lint rules
+    Lint,
+    // Append the BibTeX entries or RIS records of the file
+    Import(PathBuf),
+}
+
+/// Output format of the subcommands
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Plain,
+    Tsv,
+    Json,
 }
 
 impl CLIArgs {
@@ -45,12 +84,69 @@ impl CLIArgs {
             match arg {
                 Short('h') | Long("help") => args.helparg = true,
                 Short('v') | Long("version") => args.versionarg = true,
-                Long("light-terminal") => {
-                    args.colors.light_colors();
-                    args.colors.toggle_color_scheme()
+                Short('c') | Long("config-file") => args.config_path = Some(parser.value()?.into()),
+                Long("light-terminal") => args.light_terminal = true,
+                Short('f') | Long("format") => {
+                    args.format = match parser.value()?.string()?.as_str() {
+                        "plain" => OutputFormat::Plain,
+                        "tsv" => OutputFormat::Tsv,
+                        "json" => OutputFormat::Json,
+                        f => {
+                            return Err(format!(
+                                "Unknown output format: {}. Use plain, tsv or json",
+                                f
+                            )
+                            .into())
+                        }
+                    }
                 }
+                Short('q') | Long("query") => args.query = Some(parser.value()?.string()?),
+                Short('t') | Long("target") => args.target = Some(parser.value()?.into()),
                 // Value(pos_arg) => parse_files(&mut args, pos_arg),
-                Value(pos_arg) => args.pos_args.push(pos_arg.into()),
+                Value(pos_arg) => {
+                    // A subcommand is only recognized as very first positional arg
+                    let subcommand = if args.subcommand.is_none() && args.pos_args.is_empty() {
+                        match pos_arg.to_str() {
+                            Some("list") => Some(SubCmd::List),
+                            Some("search") => Some(SubCmd::Search(
+                                subcmd_value(&mut parser, "search")?.string()?,
+                            )),
+                            Some("show") => {
+                                Some(SubCmd::Show(subcmd_value(&mut parser, "show")?.string()?))
+                            }
+                            Some("keywords") => Some(SubCmd::Keywords),
+                            Some("check") => Some(SubCmd::Check),
+                            Some("lint") => Some(SubCmd::Lint),
+                            Some("import") => {
+                                Some(SubCmd::Import(subcmd_value(&mut parser, "import")?.into()))
+                            }
+                            Some("export") => {
+                                let name = subcmd_value(&mut parser, "export")?.string()?;
+                                match ExportFormat::from_name(&name) {
+                                    Some(format) => Some(SubCmd::Export(format)),
+                                    None => {
+                                        let names: Vec<&str> =
+                                            ExportFormat::ALL.iter().map(|f| f.name()).collect();
+                                        return Err(format!(
+                                            "Unknown export format: {}. Use one of: {}",
+                                            name,
+                                            names.join(", ")
+                                        )
+                                        .into());
+                                    }
+                                }
+                            }
+                            _ => None,
+                        }
+                    } else {
+                        None
+                    };
+                    if subcommand.is_some() {
+                        args.subcommand = subcommand
+                    } else {
+                        args.pos_args.push(pos_arg.into())
+                    }
+                }
                 _ => return Err(arg.unexpected()),
             }
         }
@@ -59,44 +155,67 @@ impl CLIArgs {
 
         Ok(args)
     }
+
+    /// Use the bibfiles set in the config file if no file or dir
+    /// was passed as positional argument
+    pub fn merge_config(&mut self, cfg: &BibiConfig) {
+        if self.pos_args.is_empty() && !cfg.general.bibfiles.is_empty() {
+            self.pos_args = cfg.general.bibfiles.iter().map(expand_home).collect();
+            self.files = parse_files(self.pos_args.clone());
+        }
+    }
 }
 
 /// This function maps a vector containing paths to another vector containing paths.
 /// But it will walk all entries of the first vec which are directories
-/// and put only valid file paths with `.bib` ending to the resulting vec.
+/// and put only valid file paths with `.bib` or `.yml` ending to the resulting vec.
 fn parse_files(args: Vec<PathBuf>) -> Vec<PathBuf> {
+    if let Some(i) = args.iter().find(|i| !i.is_file() && !i.is_dir()) {
+        println!(
+            "{}\n{}",
+            "The positional argument is neither a valid file, nor a directory:"
+                .red()
+                .bold(),
+            i.as_os_str().to_string_lossy().bright_red().italic()
+        );
+        println!();
+        println!("{}", help_func());
+        std::process::exit(1)
+    }
+    collect_bibfiles(&args)
+}
+
+/// Files passed directly and bibfiles found in the passed directories.
+/// Paths which don't exist are skipped
+pub fn collect_bibfiles(args: &[PathBuf]) -> Vec<PathBuf> {
     let mut files: Vec<PathBuf> = Vec::new();
-    // If pos arg is file, just push it to path vec
     for i in args {
+        // If pos arg is file, just push it to path vec
         if i.is_file() {
-            files.push(i);
+            files.push(i.clone());
         // If pos arg is dir, walk dir and collect bibfiles
         } else if i.is_dir() {
-            for file in WalkDir::new(i) {
-                let f = file.unwrap().into_path();
+            for file in WalkDir::new(i).into_iter().filter_map(|f| f.ok()) {
+                let f = file.into_path();
                 if f.is_file()
                     && f.extension().is_some()
-                    && f.extension().unwrap_or_default() == "bib"
+                    && (f.extension().unwrap_or_default() == "bib" || bibyaml::is_yaml(&f))
                 {
                     files.push(f)
                 }
             }
-        } else {
-            println!(
-                "{}\n{}",
-                "The positional argument is neither a valid file, nor a directory:"
-                    .red()
-                    .bold(),
-                i.as_os_str().to_string_lossy().bright_red().italic()
-            );
-            println!();
-            println!("{}", help_func());
-            std::process::exit(1)
         }
     }
     files
 }
 
+// Argument of the subcommand, e.g. the pattern of `search`
+fn subcmd_value(parser: &mut lexopt::Parser, subcmd: &str) -> Result<OsString, lexopt::Error> {
+    parser
+        .value()
+        .map_err(|_| format!("Missing argument of subcommand {}", subcmd).into())
+}
+
 pub fn help_func() -> String {
     let help = format!(
         "\
@@ -104,16 +223,44 @@ pub fn help_func() -> String {
 
 USAGE:
     bibiman [FLAGS] [files/dirs]
+    bibiman [FLAGS] <SUBCOMMAND> [files/dirs]
 
 POSITIONAL ARGS:
-    <file>    Path to .bib file
-    <dir>     Path to directory containing .bib files
+    <file>    Path to .bib or Hayagriva .yml file
+    <dir>     Path to directory containing .bib or .yml files
 
     Both can be passed multiple times
 
+    If no positional arg is passed, the bibfiles set in the
+    config file are used
+
+SUBCOMMANDS:
+    list                List all entries
+    search <pattern>    List entries matching the search pattern
+    show <citekey>      Show all fields of the entry with the citekey
+    keywords            List all keywords
+    export <format>     Print entries as bibtex, biblatex, csl-json,
+                        ris or hayagriva
+    check               List malformed entries and unreadable files,
+                        exit with 1 if there are any
+    lint                List entries with missing fields, malformed
+                        dates, dangling crossrefs or missing files,
+                        exit with 1 if there are any
+    import <file>       Append the BibTeX entries or RIS records of
+                        the file to the first or the target bibfile,
+                        skipping entries which already exist
+
+    Subcommands print to stdout instead of starting the TUI
+
 FLAGS:
     -h, --help          Show this help and exit
     -v, --version       Show the version and exit
+    -c, --config-file   Path to config file. Default is
+                        $XDG_CONFIG_HOME/bibiman/bibiman.toml
+    -f, --format        Output format of subcommands: plain (default),
+                        tsv or json
+    -q, --query         Only export entries matching the search pattern
+    -t, --target        Bibfile the imported entries are appended to
     --light-terminal    Enable color mode for light terminal background",
         env!("CARGO_PKG_NAME"),
         env!("CARGO_PKG_VERSION"),
@@ -0,0 +1,397 @@
+// bibiman - a TUI for managing BibLaTeX databases
+// Copyright (C) 2024  lukeflo
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+/////
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use serde::Deserialize;
+
+use crate::bibiman::citekey;
+use crate::bibiman::entries::EntryTableColumn;
+use crate::bibiman::lint::LintRule;
+use crate::cliargs::CLIArgs;
+use crate::tui::colors::AppColors;
+use crate::tui::keymap::Keymap;
+
+/// Main config struct. Holds all values which can be set through the
+/// config file, merged with the values passed via CLI.
+#[derive(Debug, Clone, Default)]
+pub struct BibiConfig {
+    pub general: General,
+    pub colors: AppColors,
+    pub keymap: Keymap,
+    pub lint: Lint,
+    pub lookup: Lookup,
+    pub citekey: Citekey,
+    pub library: Library,
+}
+
+/// Values of the `[general]` section
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct General {
+    // Default bibfiles/dirs, used if no positional arg is passed
+    pub bibfiles: Vec<PathBuf>,
+    // Command to open PDF and other files
+    pub pdf_opener: String,
+    // Command to open DOIs and URLs
+    pub url_opener: String,
+    // Column by which the entry table is sorted on startup
+    pub sort_column: EntryTableColumn,
+    // Index the text of connected PDFs for full-text search
+    pub fulltext_index: bool,
+    // Name of a bundled CSL style or path to a `.csl` file
+    pub citation_style: String,
+    // Reload the bibfiles when they change on disk
+    pub watch_files: bool,
+}
+
+impl Default for General {
+    fn default() -> Self {
+        Self {
+            bibfiles: Vec::new(),
+            pdf_opener: default_opener(),
+            url_opener: default_opener(),
+            sort_column: EntryTableColumn::Authors,
+            fulltext_index: false,
+            citation_style: String::from("apa"),
+            watch_files: true,
+        }
+    }
+}
+
+/// Values of the `[lint]` section
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Lint {
+    // Rules which aren't checked
+    pub disabled_rules: Vec<LintRule>,
+    // Entry types mapped to fields which are required in addition to the
+    // ones BibLaTeX requires, e.g. `article = ["doi"]`
+    pub required_fields: BTreeMap<String, Vec<String>>,
+}
+
+impl Lint {
+    /// All rules which aren't disabled
+    pub fn rules(&self) -> Vec<LintRule> {
+        LintRule::ALL
+            .into_iter()
+            .filter(|rule| !self.disabled_rules.contains(rule))
+            .collect()
+    }
+}
+
+/// Values of the `[lookup]` section. Base URLs of the services used to
+/// fetch new entries, e.g. to use a mirror or a local test server
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Lookup {
+    pub doi_url: String,
+    pub isbn_url: String,
+    pub arxiv_url: String,
+    pub pubmed_url: String,
+    // Seconds after which a lookup is given up
+    pub timeout: u64,
+}
+
+impl Default for Lookup {
+    fn default() -> Self {
+        Self {
+            doi_url: String::from("https://doi.org"),
+            isbn_url: String::from("https://openlibrary.org"),
+            arxiv_url: String::from("https://export.arxiv.org"),
+            pubmed_url: String::from("https://eutils.ncbi.nlm.nih.gov"),
+            timeout: 20,
+        }
+    }
+}
+
+/// Values of the `[citekey]` section. Citekeys of new entries and
+/// regenerated citekeys are built from the pattern
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Citekey {
+    // Placeholders in braces are replaced by values of the entry
+    pub pattern: String,
+    pub lowercase: bool,
+    // Replace accented and other non-ASCII letters, e.g. `ü` by `u`
+    pub ascii: bool,
+}
+
+impl Default for Citekey {
+    fn default() -> Self {
+        Self {
+            pattern: String::from("{author_last}{year}{title_first_word}"),
+            lowercase: true,
+            ascii: true,
+        }
+    }
+}
+
+/// Values of the `[library]` section. If a directory is set, attached files
+/// are copied or moved there and renamed after the pattern
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Library {
+    pub dir: Option<PathBuf>,
+    // Move the files instead of copying them
+    pub move_files: bool,
+    // Name of the files without extension, placeholders like the citekey
+    // pattern plus `{citekey}`
+    pub pattern: String,
+}
+
+impl Default for Library {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            move_files: false,
+            pattern: String::from("{citekey}"),
+        }
+    }
+}
+
+// Layout of the config file itself. Colors are optional there, because
+// they override the default or light scheme only if set explicitly
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    general: General,
+    colors: ColorsFile,
+    // Action names mapped to lists of key sequences
+    keybindings: BTreeMap<String, Vec<String>>,
+    lint: Lint,
+    lookup: Lookup,
+    citekey: Citekey,
+    library: Library,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ColorsFile {
+    light_terminal: bool,
+    main_text_color: Option<u8>,
+    highlight_text_color: Option<u8>,
+    entry_color: Option<u8>,
+    keyword_color: Option<u8>,
+    info_color: Option<u8>,
+    confirm_color: Option<u8>,
+    warn_color: Option<u8>,
+    bar_bg_color: Option<u8>,
+    popup_bg_color: Option<u8>,
+    selected_row_bg_color: Option<u8>,
+}
+
+impl BibiConfig {
+    /// Load the config file and merge it with the parsed CLI arguments.
+    ///
+    /// The file passed with `-c`/`--config-file` must exist. Otherwise, the
+    /// default path `$XDG_CONFIG_HOME/bibiman/bibiman.toml` is tried and
+    /// the default values are used if it doesn't exist.
+    pub fn new(args: &CLIArgs) -> Result<Self> {
+        let file = match &args.config_path {
+            Some(path) => Self::read_file(path)?,
+            None => match default_config_path() {
+                Some(path) if path.is_file() => Self::read_file(&path)?,
+                _ => ConfigFile::default(),
+            },
+        };
+
+        Self::from_file(file, args.light_terminal)
+    }
+
+    /// Parse config values from a TOML string
+    pub fn parse(content: &str, light_terminal: bool) -> Result<Self> {
+        let file: ConfigFile = toml::from_str(content)?;
+        Self::from_file(file, light_terminal)
+    }
+
+    fn read_file(path: &PathBuf) -> Result<ConfigFile> {
+        if !path.is_file() {
+            return Err(eyre!(
+                "Config file {} doesn't exist",
+                path.to_string_lossy()
+            ));
+        }
+        let content = fs::read_to_string(path)
+            .wrap_err_with(|| format!("Couldn't read config file {}", path.to_string_lossy()))?;
+        toml::from_str(&content)
+            .wrap_err_with(|| format!("Couldn't parse config file {}", path.to_string_lossy()))
+    }
+
+    fn from_file(file: ConfigFile, light_terminal: bool) -> Result<Self> {
+        let mut colors = AppColors::default();
+        if light_terminal || file.colors.light_terminal {
+            colors.light_colors();
+            colors.toggle_color_scheme();
+        }
+
+        // Custom colors override the values of the chosen scheme
+        let c = file.colors;
+        if let Some(index) = c.main_text_color {
+            colors.main_text_color(index)
+        }
+        if let Some(index) = c.highlight_text_color {
+            colors.highlight_text_color(index)
+        }
+        if let Some(index) = c.entry_color {
+            colors.entry_color(index)
+        }
+        if let Some(index) = c.keyword_color {
+            colors.keyword_color(index)
+        }
+        if let Some(index) = c.info_color {
+            colors.info_color(index)
+        }
+        if let Some(index) = c.confirm_color {
+            colors.confirm_color(index)
+        }
+        if let Some(index) = c.warn_color {
+            colors.warn_color(index)
+        }
+        if let Some(index) = c.bar_bg_color {
+            colors.bar_bg_color(index)
+        }
+        if let Some(index) = c.popup_bg_color {
+            colors.popup_bg_color(index)
+        }
+        if let Some(index) = c.selected_row_bg_color {
+            colors.selected_row_bg_color(index)
+        }
+
+        let keymap = Keymap::new(&file.keybindings).wrap_err("Invalid keybindings in config")?;
+        citekey::check_pattern(&file.citekey.pattern)
+            .wrap_err("Invalid citekey pattern in config")?;
+        citekey::check_file_pattern(&file.library.pattern)
+            .wrap_err("Invalid file name pattern in config")?;
+
+        Ok(Self {
+            general: file.general,
+            colors,
+            keymap,
+            lint: file.lint,
+            lookup: file.lookup,
+            citekey: file.citekey,
+            library: file.library,
+        })
+    }
+}
+
+/// Default path of the config file: `$XDG_CONFIG_HOME/bibiman/bibiman.toml`
+pub fn default_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join("bibiman").join("bibiman.toml"))
+}
+
+// Use the standard opener of the OS if nothing else is set
+fn default_opener() -> String {
+    match std::env::consts::OS {
+        "linux" => String::from("xdg-open"),
+        "macos" => String::from("open"),
+        "windows" => String::from("start"),
+        _ => panic!("Couldn't detect OS for setting correct opener"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tui::colors::AppColorScheme;
+
+    #[test]
+    fn parse_config() {
+        let content = r#"
+[general]
+bibfiles = ["~/bibliography.bib"]
+pdf_opener = "zathura --fork"
+sort_column = "year"
+citation_style = "ieee"
+watch_files = false
+
+[colors]
+light_terminal = true
+entry_color = 160
+
+[lint]
+disabled_rules = ["missing-file"]
+required_fields = { article = ["doi"] }
+
+[lookup]
+isbn_url = "http://localhost:8080"
+timeout = 5
+
+[citekey]
+pattern = "{author_last}_{year}"
+lowercase = false
+
+[library]
+dir = "~/papers"
+"#;
+        let cfg = BibiConfig::parse(content, false).unwrap();
+
+        assert_eq!(
+            cfg.general.bibfiles,
+            vec![PathBuf::from("~/bibliography.bib")]
+        );
+        assert_eq!(cfg.general.pdf_opener, "zathura --fork");
+        assert_eq!(cfg.general.url_opener, default_opener());
+        assert_eq!(cfg.general.sort_column, EntryTableColumn::Year);
+        assert_eq!(cfg.general.citation_style, "ieee");
+        assert!(!cfg.general.watch_files);
+        // Light scheme is used, but the custom entry color overrides it
+        assert!(matches!(cfg.colors.color_scheme, AppColorScheme::Light));
+        assert_eq!(cfg.colors.entry_color, 160);
+        assert_eq!(cfg.colors.keyword_color, 58);
+        assert!(!cfg.lint.rules().contains(&LintRule::MissingFile));
+        assert_eq!(cfg.lint.required_fields["article"], vec!["doi"]);
+        assert_eq!(cfg.lookup.isbn_url, "http://localhost:8080");
+        assert_eq!(cfg.lookup.doi_url, "https://doi.org");
+        assert_eq!(cfg.lookup.timeout, 5);
+        assert_eq!(cfg.citekey.pattern, "{author_last}_{year}");
+        assert!(!cfg.citekey.lowercase && cfg.citekey.ascii);
+        assert!(BibiConfig::parse("[citekey]\npattern = \"{author}\"", false).is_err());
+        assert_eq!(cfg.library.dir, Some(PathBuf::from("~/papers")));
+        assert_eq!(cfg.library.pattern, "{citekey}");
+        assert!(!cfg.library.move_files);
+    }
+
+    #[test]
+    fn custom_keybindings() {
+        use crate::tui::keymap::{KeyAction, KeyChord, KeyLookup};
+
+        let content = r#"
+[keybindings]
+next_row = ["ctrl-n", "down"]
+top = ["g g"]
+"#;
+        let cfg = BibiConfig::parse(content, false).unwrap();
+        let ctrl_n = KeyChord::parse("ctrl-n").unwrap();
+
+        assert_eq!(
+            cfg.keymap.lookup(&[ctrl_n]),
+            KeyLookup::Action(KeyAction::NextRow)
+        );
+        assert!(BibiConfig::parse("[keybindings]\nnext_row = [\"ctrl-\"]", false).is_err());
+    }
+
+    #[test]
+    fn reject_unknown_keys() {
+        assert!(BibiConfig::parse("[colors]\nentry_colour = 3", false).is_err());
+        assert!(BibiConfig::parse("[lint]\ndisabled_rules = [\"no-such-rule\"]", false).is_err());
+    }
+}
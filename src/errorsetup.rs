@@ -15,10 +15,11 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 /////
 
+use crate::bibiman::fulltext;
 use color_eyre::config::HookBuilder;
 use color_eyre::eyre::Result;
 use crossterm::cursor;
-use crossterm::event::DisableMouseCapture;
+use crossterm::event::{DisableBracketedPaste, DisableMouseCapture};
 use crossterm::terminal::LeaveAlternateScreen;
 use std::io::stdout;
 
@@ -27,19 +28,19 @@ pub fn init_error_hooks() -> Result<()> {
     let (panic, error) = HookBuilder::default().into_hooks();
     let panic = panic.into_panic_hook();
     let error = error.into_eyre_hook();
-    color_eyre::eyre::set_hook(Box::new(move |e| {
-        let _ = crossterm::execute!(
-            stdout(),
-            DisableMouseCapture,
-            LeaveAlternateScreen,
-            cursor::Show
-        );
-        let _ = crossterm::terminal::disable_raw_mode();
-        error(e)
-    }))?;
+    // The eyre hook runs whenever an error is created, even if it is handled
+    // and shown in a popup. Thus, the terminal isn't restored here. Errors
+    // returned from the main loop drop the TUI first, which restores it
+    color_eyre::eyre::set_hook(Box::new(error))?;
     std::panic::set_hook(Box::new(move |info| {
+        // The PDF parser panics on some malformed files. Such panics only
+        // end the extracting thread, the TUI keeps running
+        if std::thread::current().name() == Some(fulltext::EXTRACT_THREAD) {
+            return;
+        }
         let _ = crossterm::execute!(
             stdout(),
+            DisableBracketedPaste,
             DisableMouseCapture,
             LeaveAlternateScreen,
             cursor::Show
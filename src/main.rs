@@ -18,18 +18,30 @@
 use app::App;
 use cliargs::CLIArgs;
 use color_eyre::eyre::Result;
+use color_eyre::owo_colors::OwoColorize;
+use config::BibiConfig;
 use errorsetup::init_error_hooks;
 
 pub mod app;
 pub mod bibiman;
 pub mod cliargs;
+pub mod config;
 pub mod errorsetup;
+pub mod subcommands;
 pub mod tui;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     // Parse CLI arguments
-    let parsed_args = CLIArgs::parse_args().unwrap();
+    let mut parsed_args = match CLIArgs::parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            // Wrong arguments are the user's fault, thus, no backtrace
+            eprintln!("{}\n", e.to_string().red().bold());
+            eprintln!("{}", cliargs::help_func());
+            std::process::exit(1);
+        }
+    };
 
     // Print help if -h/--help flag is passed and exit
     if parsed_args.helparg {
@@ -45,9 +57,19 @@ async fn main() -> Result<()> {
 
     init_error_hooks()?;
 
+    // Load config file and merge it with the CLI arguments
+    let cfg = BibiConfig::new(&parsed_args)?;
+    parsed_args.merge_config(&cfg);
+
+    // Run subcommand without starting the TUI, if one was passed
+    if let Some(cmd) = &parsed_args.subcommand {
+        let exit_code = subcommands::run_subcommand(cmd, &parsed_args, &cfg);
+        std::process::exit(exit_code);
+    }
+
     // Create an application.
-    let mut app = App::new(&parsed_args)?;
+    let mut app = App::new(&parsed_args, &cfg)?;
 
-    app.run(&parsed_args).await?;
+    app.run(&mut parsed_args, &cfg).await?;
     Ok(())
 }
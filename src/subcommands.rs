@@ -0,0 +1,442 @@
+// bibiman - a TUI for managing BibLaTeX databases
+// Copyright (C) 2024  lukeflo
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+/////
+
+use color_eyre::eyre::eyre;
+use color_eyre::owo_colors::OwoColorize;
+use serde_json::{json, Value};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use crate::bibiman::bibedit;
+use crate::bibiman::bibisetup::BibiSetup;
+use crate::bibiman::diagnostics::Diagnostic;
+use crate::bibiman::entries::{EntryTable, EntryTableItem};
+use crate::bibiman::export;
+use crate::bibiman::import;
+use crate::bibiman::lint::{self, LintIssue};
+use crate::bibiman::search::BibiSearch;
+use crate::bibiman::Bibiman;
+use crate::cliargs::{CLIArgs, OutputFormat, SubCmd};
+use crate::config::BibiConfig;
+
+/// Run the subcommand passed via CLI and print its output to stdout.
+///
+/// Returns the exit code: `0` on success, `1` if nothing was found or
+/// `check`/`lint` found problems.
+pub fn run_subcommand(cmd: &SubCmd, args: &CLIArgs, cfg: &BibiConfig) -> i32 {
+    if let SubCmd::Import(file) = cmd {
+        return run_import(file, args, cfg);
+    }
+    let mut biblio = BibiSetup::new(&args.files);
+    if let SubCmd::Check = cmd {
+        let problems = &biblio.diagnostics;
+        if !problems.is_empty() {
+            let _ = writeln!(
+                std::io::stdout(),
+                "{}",
+                format_diagnostics(problems, &args.format)
+            );
+        }
+        return if problems.is_empty() { 0 } else { 1 };
+    }
+    // Skipped entries shouldn't go unnoticed, but must not mix with the
+    // output
+    for problem in &biblio.diagnostics {
+        eprintln!(
+            "{} {}",
+            "Skipped:".yellow().bold(),
+            format_diagnostics(std::slice::from_ref(problem), &OutputFormat::Plain)
+        );
+    }
+    if cfg.general.fulltext_index {
+        biblio.index_fulltext();
+    }
+    let entries = EntryTable::new(&biblio.entry_list, &cfg.general.sort_column).entry_table_items;
+
+    let (output, found) = match cmd {
+        SubCmd::List => (format_entries(&entries, &args.format), true),
+        SubCmd::Search(pattern) => {
+            let matches = BibiSearch::search_entry_list(pattern, entries);
+            (format_entries(&matches, &args.format), !matches.is_empty())
+        }
+        SubCmd::Show(citekey) => match entries.iter().find(|e| &e.citekey == citekey) {
+            Some(entry) => (format_entry_details(entry, &args.format), true),
+            None => {
+                eprintln!(
+                    "{} {}",
+                    "No entry found with citekey:".red().bold(),
+                    citekey.bright_red().italic()
+                );
+                return 1;
+            }
+        },
+        SubCmd::Keywords => (format_keywords(&biblio.keyword_list, &args.format), true),
+        SubCmd::Check | SubCmd::Import(_) => unreachable!(),
+        SubCmd::Lint => {
+            let issues = lint::lint(&biblio, &cfg.lint);
+            // Succeeds if nothing was found
+            (format_lint_issues(&issues, &args.format), issues.is_empty())
+        }
+        SubCmd::Export(format) => {
+            let entries = match &args.query {
+                Some(pattern) => BibiSearch::search_entry_list(pattern, entries),
+                None => entries,
+            };
+            let citekeys: Vec<String> = entries.into_iter().map(|e| e.citekey).collect();
+            match export::export_entries(&biblio.bibliography, &citekeys, *format) {
+                Ok(output) => (output, !citekeys.is_empty()),
+                Err(e) => {
+                    eprintln!("{} {}", "Export failed:".red().bold(), e);
+                    return 1;
+                }
+            }
+        }
+    };
+
+    // Write directly to stdout to ignore broken pipes, e.g. if the
+    // output is piped to `head`
+    if !output.is_empty() {
+        let _ = writeln!(std::io::stdout(), "{}", output);
+    }
+
+    if found {
+        0
+    } else {
+        1
+    }
+}
+
+// Append the entries of the file to the target bibfile. The citekeys of the
+// imported entries are printed, existing entries are reported as skipped
+fn run_import(file: &Path, args: &CLIArgs, cfg: &BibiConfig) -> i32 {
+    let Some(target) = args.target.as_ref().or(args.files.first()) else {
+        eprintln!("{}", "No bibfile to import the entries to".red().bold());
+        return 1;
+    };
+    // The target is checked for duplicates, too
+    let mut files = args.files.clone();
+    if target.is_file() && !files.contains(target) {
+        files.push(target.clone());
+    }
+    let biblio = BibiSetup::new(&files);
+    let import = fs::read_to_string(file)
+        .map_err(|e| eyre!("Can't read {}: {}", file.display(), e))
+        .and_then(|text| import::read_import(&text, &biblio, &cfg.citekey));
+    let import = match import {
+        Ok(import) => import,
+        Err(e) => {
+            eprintln!("{} {}", "Import failed:".red().bold(), e);
+            return 1;
+        }
+    };
+    for duplicate in &import.duplicates {
+        eprintln!("{} {}", "Skipped:".yellow().bold(), duplicate);
+    }
+    if import.entries.is_empty() {
+        return 1;
+    }
+    let content = import
+        .entries
+        .iter()
+        .map(|e| Bibiman::format_bibtex_entry(&e.with_citekey(), ""))
+        .collect::<Vec<String>>()
+        .join("\n\n");
+    if let Err(e) = bibedit::append_entries(target, &content) {
+        eprintln!("{} {}", "Import failed:".red().bold(), e);
+        return 1;
+    }
+    for entry in &import.entries {
+        let _ = writeln!(std::io::stdout(), "{}", entry.citekey);
+    }
+    0
+}
+
+// Tabs and newlines inside values would break the TSV columns
+fn tsv_escape(value: &str) -> String {
+    value
+        .split(['\t', '\n', '\r'])
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+fn entry_to_json(entry: &EntryTableItem) -> Value {
+    json!({
+        "citekey": entry.citekey,
+        "authors": entry.authors,
+        "title": entry.title,
+        "subtitle": entry.subtitle,
+        "year": entry.year,
+        "pubtype": entry.pubtype,
+        "keywords": split_keywords(&entry.keywords),
+        "doi_url": entry.doi_url,
+        "file": entry.filepath.as_ref().map(|f| f.to_string_lossy()),
+        "source": entry.source.as_ref().map(|s| s.file.to_string_lossy()),
+        "line": entry.source.as_ref().map(|s| s.line),
+    })
+}
+
+fn split_keywords(keywords: &str) -> Vec<&str> {
+    keywords
+        .split(',')
+        .map(|k| k.trim())
+        .filter(|k| !k.is_empty())
+        .collect()
+}
+
+/// Format a list of entries. Plain output prints one line per entry like
+/// `citekey: Authors (Year) Title`, TSV output has the columns citekey,
+/// authors, year, title, pubtype and keywords.
+pub fn format_entries(entries: &[EntryTableItem], format: &OutputFormat) -> String {
+    match format {
+        OutputFormat::Plain => entries
+            .iter()
+            .map(|e| format!("{}: {} ({}) {}", e.citekey, e.authors, e.year, e.title))
+            .collect::<Vec<String>>()
+            .join("\n"),
+        OutputFormat::Tsv => entries
+            .iter()
+            .map(|e| {
+                [
+                    &e.citekey,
+                    &e.authors,
+                    &e.year,
+                    &e.title,
+                    &e.pubtype,
+                    &e.keywords,
+                ]
+                .map(|v| tsv_escape(v))
+                .join("\t")
+            })
+            .collect::<Vec<String>>()
+            .join("\n"),
+        OutputFormat::Json => Value::Array(entries.iter().map(entry_to_json).collect()).to_string(),
+    }
+}
+
+/// Format all fields of a single entry
+pub fn format_entry_details(entry: &EntryTableItem, format: &OutputFormat) -> String {
+    if let OutputFormat::Json = format {
+        let mut value = entry_to_json(entry);
+        value["abstract"] = Value::from(entry.abstract_text.clone());
+        return value.to_string();
+    }
+
+    let mut fields = vec![
+        ("citekey", entry.citekey.clone()),
+        ("authors", entry.authors.clone()),
+        ("title", entry.title.clone()),
+    ];
+    if let Some(subtitle) = &entry.subtitle {
+        fields.push(("subtitle", subtitle.clone()));
+    }
+    fields.push(("year", entry.year.clone()));
+    fields.push(("pubtype", entry.pubtype.clone()));
+    fields.push(("keywords", entry.keywords.clone()));
+    if let Some(doi_url) = &entry.doi_url {
+        fields.push(("doi_url", doi_url.clone()));
+    }
+    if let Some(file) = &entry.filepath {
+        fields.push(("file", file.to_string_lossy().to_string()));
+    }
+    if let Some(source) = &entry.source {
+        fields.push((
+            "source",
+            format!("{}:{}", source.file.to_string_lossy(), source.line),
+        ));
+    }
+    fields.push(("abstract", entry.abstract_text.clone()));
+
+    fields
+        .into_iter()
+        .map(|(name, value)| match format {
+            OutputFormat::Tsv => format!("{}\t{}", name, tsv_escape(&value)),
+            _ => format!("{}: {}", name, value),
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Format the list of keywords, one per line or as JSON array
+pub fn format_keywords(keywords: &[String], format: &OutputFormat) -> String {
+    match format {
+        OutputFormat::Json => json!(keywords).to_string(),
+        _ => keywords.join("\n"),
+    }
+}
+
+/// Format the problems found in the bibfiles. Plain output prints one line
+/// per problem like `path/refs.bib:12: Message (skipped citekey)`, TSV output
+/// has the columns file, line, citekey and message.
+pub fn format_diagnostics(problems: &[Diagnostic], format: &OutputFormat) -> String {
+    match format {
+        OutputFormat::Plain => problems
+            .iter()
+            .map(|d| {
+                let mut line = d.file.to_string_lossy().to_string();
+                if let Some(nr) = d.line {
+                    line.push_str(&format!(":{}", nr));
+                }
+                line.push_str(&format!(": {}", d.message));
+                if let Some(citekey) = &d.citekey {
+                    line.push_str(&format!(" (skipped {})", citekey));
+                }
+                line
+            })
+            .collect::<Vec<String>>()
+            .join("\n"),
+        OutputFormat::Tsv => problems
+            .iter()
+            .map(|d| {
+                [
+                    d.file.to_string_lossy().to_string(),
+                    d.line.map(|l| l.to_string()).unwrap_or_default(),
+                    d.citekey.clone().unwrap_or_default(),
+                    tsv_escape(&d.message),
+                ]
+                .join("\t")
+            })
+            .collect::<Vec<String>>()
+            .join("\n"),
+        OutputFormat::Json => Value::Array(
+            problems
+                .iter()
+                .map(|d| {
+                    json!({
+                        "file": d.file.to_string_lossy(),
+                        "line": d.line,
+                        "citekey": d.citekey,
+                        "message": d.message,
+                    })
+                })
+                .collect(),
+        )
+        .to_string(),
+    }
+}
+
+/// Format the issues found by the lint rules. Plain output prints one line
+/// per issue like `path/refs.bib:12: citekey: Message [rule]`, TSV output has
+/// the columns file, line, citekey, rule and message.
+pub fn format_lint_issues(issues: &[LintIssue], format: &OutputFormat) -> String {
+    match format {
+        OutputFormat::Plain => issues
+            .iter()
+            .map(|i| match &i.source {
+                Some(source) => format!(
+                    "{}:{}: {}: {} [{}]",
+                    source.file.to_string_lossy(),
+                    source.line,
+                    i.citekey,
+                    i.message,
+                    i.rule.name()
+                ),
+                None => i.summary(),
+            })
+            .collect::<Vec<String>>()
+            .join("\n"),
+        OutputFormat::Tsv => issues
+            .iter()
+            .map(|i| {
+                [
+                    i.source
+                        .as_ref()
+                        .map(|s| s.file.to_string_lossy().to_string())
+                        .unwrap_or_default(),
+                    i.source
+                        .as_ref()
+                        .map(|s| s.line.to_string())
+                        .unwrap_or_default(),
+                    i.citekey.clone(),
+                    i.rule.name().to_string(),
+                    tsv_escape(&i.message),
+                ]
+                .join("\t")
+            })
+            .collect::<Vec<String>>()
+            .join("\n"),
+        OutputFormat::Json => Value::Array(
+            issues
+                .iter()
+                .map(|i| {
+                    json!({
+                        "file": i.source.as_ref().map(|s| s.file.to_string_lossy()),
+                        "line": i.source.as_ref().map(|s| s.line),
+                        "citekey": i.citekey,
+                        "rule": i.rule.name(),
+                        "message": i.message,
+                    })
+                })
+                .collect(),
+        )
+        .to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_entry() -> EntryTableItem {
+        EntryTableItem {
+            authors: "Miller, Schmitz".to_string(),
+            short_author: "".to_string(),
+            title: "A title\twith tab".to_string(),
+            year: "2000".to_string(),
+            pubtype: "article".to_string(),
+            keywords: "key1, key2".to_string(),
+            citekey: "miller_2000".to_string(),
+            abstract_text: "An abstract".to_string(),
+            doi_url: Some("10.1000/182".to_string()),
+            filepath: None,
+            subtitle: None,
+            other_fields: Vec::new(),
+            fulltext: None,
+            source: None,
+        }
+    }
+
+    #[test]
+    fn format_entry_list() {
+        let entries = vec![test_entry()];
+
+        assert_eq!(
+            format_entries(&entries, &OutputFormat::Plain),
+            "miller_2000: Miller, Schmitz (2000) A title\twith tab"
+        );
+        assert_eq!(
+            format_entries(&entries, &OutputFormat::Tsv),
+            "miller_2000\tMiller, Schmitz\t2000\tA title with tab\tarticle\tkey1, key2"
+        );
+
+        let json: Value =
+            serde_json::from_str(&format_entries(&entries, &OutputFormat::Json)).unwrap();
+        assert_eq!(json[0]["citekey"], "miller_2000");
+        assert_eq!(json[0]["keywords"], json!(["key1", "key2"]));
+        assert_eq!(json[0]["file"], Value::Null);
+    }
+
+    #[test]
+    fn format_details() {
+        let details = format_entry_details(&test_entry(), &OutputFormat::Plain);
+
+        assert!(details.starts_with("citekey: miller_2000\nauthors: Miller, Schmitz\n"));
+        assert!(details.contains("doi_url: 10.1000/182\n"));
+        assert!(details.ends_with("abstract: An abstract"));
+    }
+}
@@ -17,14 +17,19 @@
 
 pub mod colors;
 pub mod commands;
+pub mod keymap;
 pub mod popup;
 pub mod ui;
+pub mod watcher;
 
-use crate::{cliargs::CLIArgs, App};
+use crate::bibiman::fulltext::EntryTexts;
+use crate::bibiman::lookup::LookupResult;
+use crate::{cliargs::CLIArgs, config::BibiConfig, App};
 use crossterm::{
     cursor,
     event::{
-        DisableMouseCapture, EnableMouseCapture, Event as CrosstermEvent, KeyEvent, MouseEvent,
+        DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture,
+        Event as CrosstermEvent, KeyEvent, MouseEvent,
     },
     terminal::{EnterAlternateScreen, LeaveAlternateScreen},
 };
@@ -34,6 +39,9 @@ use futures::{FutureExt, StreamExt};
 use ratatui::backend::CrosstermBackend;
 use std::io::{stdout, Stdout};
 use std::panic;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
 use std::{
     ops::{Deref, DerefMut},
     time::Duration,
@@ -42,7 +50,7 @@ use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
 
 // Terminal events.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub enum Event {
     /// Terminal tick.
     Tick,
@@ -52,6 +60,14 @@ pub enum Event {
     Mouse(MouseEvent),
     /// Terminal resize.
     Resize(u16, u16),
+    /// Bibfiles changed on disk. Holds the current list of bibfiles.
+    FilesChanged(Vec<PathBuf>),
+    /// Lookup of a new entry finished or timed out.
+    LookupFinished(LookupResult),
+    /// Text pasted into the terminal.
+    Paste(String),
+    /// Full-text index updated. Holds the text of the entries by citekey.
+    FulltextIndexed(EntryTexts),
 }
 
 #[derive(Debug)]
@@ -65,6 +81,10 @@ pub struct Tui {
     /// Event handler thread.
     handler: tokio::task::JoinHandle<()>,
     cancellation_token: CancellationToken,
+    /// Files and directories polled for changes.
+    watched: Vec<PathBuf>,
+    /// Set whenever bibiman loaded the files itself.
+    files_loaded: Arc<AtomicBool>,
 }
 
 impl Tui {
@@ -80,13 +100,36 @@ impl Tui {
             evt_receiver,
             handler,
             cancellation_token,
+            watched: Vec::new(),
+            files_loaded: Arc::default(),
         })
     }
 
+    /// Send an event if the bibfiles in `paths` change. Changes made before
+    /// `loaded` is set aren't reported. Must be called before the TUI is
+    /// entered
+    pub fn watch(&mut self, paths: Vec<PathBuf>, loaded: Arc<AtomicBool>) {
+        self.watched = paths;
+        self.files_loaded = loaded;
+    }
+
+    /// Sender for events from background tasks
+    pub fn sender(&self) -> mpsc::UnboundedSender<Event> {
+        self.evt_sender.clone()
+    }
+
     pub fn start(&mut self) {
         let tick_rate = Duration::from_millis(1000);
         self.cancel();
         self.cancellation_token = CancellationToken::new();
+        if !self.watched.is_empty() {
+            watcher::spawn(
+                self.watched.clone(),
+                self.evt_sender.clone(),
+                self.cancellation_token.clone(),
+                self.files_loaded.clone(),
+            );
+        }
         let event_loop = Self::event_loop(
             self.evt_sender.clone(),
             self.cancellation_token.clone(),
@@ -133,7 +176,8 @@ impl Tui {
                         },
                         CrosstermEvent::FocusGained => {
                         },
-                        CrosstermEvent::Paste(_) => {
+                        CrosstermEvent::Paste(text) => {
+                            sender.send(Event::Paste(text)).unwrap();
                         },
                     }
                 }
@@ -151,9 +195,7 @@ impl Tui {
         // if self.mouse {
         crossterm::execute!(stdout(), EnableMouseCapture)?;
         // }
-        // if self.paste {
-        //     crossterm::execute!(stdout(), EnableBracketedPaste)?;
-        // }
+        crossterm::execute!(stdout(), EnableBracketedPaste)?;
         // Self::init_error_hooks()?;
         self.start();
         Ok(())
@@ -179,9 +221,7 @@ impl Tui {
         self.cancellation_token.cancel();
         if crossterm::terminal::is_raw_mode_enabled()? {
             self.terminal.flush()?;
-            // if self.paste {
-            //     crossterm::execute!(stdout(), DisableBracketedPaste)?;
-            // }
+            crossterm::execute!(stdout(), DisableBracketedPaste)?;
             // if self.mouse {
             crossterm::execute!(stdout(), DisableMouseCapture)?;
             // }
@@ -195,11 +235,11 @@ impl Tui {
     //
     // [`Draw`]: ratatui::Terminal::draw
     // [`rendering`]: crate::ui::render
-    pub fn draw(&mut self, app: &mut App, args: &CLIArgs) -> Result<()> {
+    pub fn draw(&mut self, app: &mut App, args: &CLIArgs, cfg: &BibiConfig) -> Result<()> {
         // self.terminal.draw(|frame| ui::render(app, frame))?;
         self.terminal
             // .draw(|frame| frame.render_widget(app, frame.area()))?;
-            .draw(|frame| ui::render_ui(app, args, frame))?;
+            .draw(|frame| ui::render_ui(app, args, cfg, frame))?;
         Ok(())
     }
 
@@ -15,11 +15,12 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 /////
 
-use ratatui::crossterm::event::{
-    Event, KeyCode, KeyEvent, KeyModifiers, MouseEvent, MouseEventKind,
-};
+use ratatui::crossterm::event::{Event, KeyCode, KeyEvent, MouseEvent, MouseEventKind};
 use tui_input::Input;
 
+use crate::bibiman::keywords::FilterOp;
+use crate::tui::popup::KeywordScope;
+
 // // Possible ressources to open
 // #[derive(Debug, PartialEq, Eq)]
 // pub enum OpenRessource {
@@ -29,6 +30,10 @@ use tui_input::Input;
 // }
 
 /// Application command.
+///
+/// Key events are translated into commands through the [`Keymap`].
+///
+/// [`Keymap`]: super::keymap::Keymap
 #[derive(Debug, PartialEq, Eq)]
 pub enum CmdAction {
     // Toggle area
@@ -59,110 +64,55 @@ pub enum CmdAction {
     SortList,
     // Yank selected item
     YankItem,
+    // Yank formatted reference of selected item
+    YankReference,
+    // Mark/unmark selected entry
+    ToggleMark,
+    // Start/finish marking a range of entries
+    ToggleVisual,
+    // Delete marked/selected entries from their bibfiles
+    DeleteEntries,
+    // Move/copy marked/selected entries to another bibfile
+    MoveEntries,
+    CopyEntries,
+    // Show groups of duplicate entries
+    ShowDuplicates,
+    // Show entries violating the lint rules
+    ShowLint,
+    // Show citekeys which differ from the pattern and rename the entries
+    RegenerateCitekeys,
     // Edit file
     EditFile,
+    // Edit single field inside the TUI
+    EditField,
+    // Set or extend the file field of the selected entry
+    AttachFile,
+    // Manage keywords of entries
+    AddKeyword(KeywordScope),
+    RemoveKeyword(KeywordScope),
+    RenameKeyword,
+    // Add selected keyword to filter chain
+    FilterTags(FilterOp),
+    // Remove last keyword of filter chain
+    PopTagFilter,
     // Open linked ressource
     Open,
+    // Export listed entries
+    Export,
     // Input command.
     Input(InputCmdAction),
     // Hexdump command.
     Exit,
     // Show keybindings
     ShowHelp,
+    // Show problems found in the bibfiles
+    ShowDiagnostics,
     // Add new entry
     AddEntry,
     // Do nothing.
     Nothing,
 }
 
-impl From<KeyEvent> for CmdAction {
-    fn from(key_event: KeyEvent) -> Self {
-        match key_event.code {
-            // Go to first/last entry of selected list/table
-            KeyCode::Char('g') | KeyCode::Home => Self::Top,
-            KeyCode::Char('G') | KeyCode::End => Self::Bottom,
-            // Scroll columns of EntryTable
-            KeyCode::Right | KeyCode::Char('l') => Self::SelectNextCol,
-            KeyCode::Left | KeyCode::Char('h') => Self::SelectPrevCol,
-            // Scroll table/list vertically by 1
-            KeyCode::Down | KeyCode::Char('j') => {
-                if key_event.modifiers == KeyModifiers::ALT {
-                    Self::ScrollInfoDown
-                } else {
-                    Self::SelectNextRow(1)
-                }
-            }
-            KeyCode::Up | KeyCode::Char('k') => {
-                if key_event.modifiers == KeyModifiers::ALT {
-                    Self::ScrollInfoUp
-                } else {
-                    Self::SelectPrevRow(1)
-                }
-            }
-            // Scroll table/list vertically by 5
-            KeyCode::Char('d') => {
-                if key_event.modifiers == KeyModifiers::CONTROL {
-                    Self::SelectNextRow(5)
-                } else {
-                    Self::Nothing
-                }
-            }
-            KeyCode::Char('u') => {
-                if key_event.modifiers == KeyModifiers::CONTROL {
-                    Self::SelectPrevRow(5)
-                } else {
-                    Self::Nothing
-                    // Self::Open(OpenRessource::WebLink)
-                }
-            }
-            // Scroll info/preview area
-            KeyCode::PageDown => Self::ScrollInfoDown,
-            KeyCode::PageUp => Self::ScrollInfoUp,
-            // Exit App
-            KeyCode::Char('q') => Self::Exit,
-            // Add new entry
-            KeyCode::Char('a') => Self::AddEntry,
-            KeyCode::Char('c') | KeyCode::Char('C') => {
-                if key_event.modifiers == KeyModifiers::CONTROL {
-                    Self::Exit
-                } else {
-                    Self::Nothing
-                }
-            }
-            // Switch selected area
-            KeyCode::Tab => Self::ToggleArea,
-            KeyCode::BackTab => Self::ToggleArea,
-            // Enter search mode
-            KeyCode::Char('/') => Self::Input(InputCmdAction::Enter),
-            KeyCode::Char('f') => {
-                if key_event.modifiers == KeyModifiers::CONTROL {
-                    Self::Input(InputCmdAction::Enter)
-                } else {
-                    Self::Nothing
-                }
-            }
-            // KeyCode::Backspace => Self::Input(InputCommand::Resume(Event::Key(key_event))),
-            // Confirm selection
-            KeyCode::Enter => Self::Confirm,
-            // Reset lists/tables
-            KeyCode::Esc => Self::Reset,
-            // Open linked ressource
-            KeyCode::Char('o') => Self::Open,
-            // KeyCode::Char('u') => Self::Open(OpenRessource::WebLink),
-            // Edit currently selected entry
-            KeyCode::Char('e') => Self::EditFile,
-            // Yank selected item/value
-            KeyCode::Char('y') => Self::YankItem,
-            // Sort entry table by selected col
-            KeyCode::Char('s') => Self::SortList,
-            // Show help popup
-            KeyCode::Char('?') => Self::ShowHelp,
-            // Else do nothing
-            _ => Self::Nothing,
-        }
-    }
-}
-
 impl From<MouseEvent> for CmdAction {
     fn from(mouse_event: MouseEvent) -> Self {
         match mouse_event.kind {
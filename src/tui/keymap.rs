@@ -0,0 +1,713 @@
+// bibiman - a TUI for managing BibLaTeX databases
+// Copyright (C) 2024  lukeflo
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+/////
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use color_eyre::eyre::{eyre, Result};
+use itertools::Itertools;
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use super::commands::{CmdAction, InputCmdAction};
+use super::popup::KeywordScope;
+use crate::bibiman::keywords::FilterOp;
+
+/// A single key press including its modifiers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyChord {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        // Shift is already part of the char itself ('G' vs 'g'), and
+        // BackTab is always sent with shift. Thus, ignore it for those keys
+        let modifiers = match code {
+            KeyCode::Char(_) | KeyCode::BackTab => modifiers.difference(KeyModifiers::SHIFT),
+            _ => modifiers,
+        };
+        Self { code, modifiers }
+    }
+
+    /// Parse a chord like `j`, `G`, `ctrl-d`, `alt-down` or `pagedown`
+    pub fn parse(chord: &str) -> Result<Self> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = chord;
+        while let Some((modifier, key)) = rest.split_once('-') {
+            if key.is_empty() {
+                break;
+            }
+            match modifier.to_lowercase().as_str() {
+                "ctrl" => modifiers |= KeyModifiers::CONTROL,
+                "alt" => modifiers |= KeyModifiers::ALT,
+                "shift" => modifiers |= KeyModifiers::SHIFT,
+                _ => return Err(eyre!("Unknown modifier in key chord \"{}\"", chord)),
+            }
+            rest = key;
+        }
+
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match rest.to_lowercase().as_str() {
+                "enter" | "return" => KeyCode::Enter,
+                "esc" | "escape" => KeyCode::Esc,
+                "tab" => KeyCode::Tab,
+                "backtab" => KeyCode::BackTab,
+                "backspace" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "insert" => KeyCode::Insert,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "space" => KeyCode::Char(' '),
+                "minus" => KeyCode::Char('-'),
+                key if key.starts_with('f') && key[1..].parse::<u8>().is_ok() => {
+                    KeyCode::F(key[1..].parse().unwrap())
+                }
+                _ => return Err(eyre!("Unknown key in key chord \"{}\"", chord)),
+            },
+        };
+
+        // Terminals send shifted letters as uppercase chars, other shifted
+        // chars can't be told apart from their unshifted ones
+        let code = match code {
+            KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => {
+                if !c.is_alphabetic() {
+                    return Err(eyre!(
+                        "Shift only works with letters in key chord \"{}\", use the shifted char instead",
+                        chord
+                    ));
+                }
+                KeyCode::Char(c.to_uppercase().next().unwrap_or(c))
+            }
+            code => code,
+        };
+
+        Ok(Self::new(code, modifiers))
+    }
+
+    /// Parse a space separated sequence of chords, e.g. `g g` or `ctrl-x k`
+    pub fn parse_sequence(sequence: &str) -> Result<Vec<Self>> {
+        let chords: Vec<Self> = sequence
+            .split_whitespace()
+            .map(Self::parse)
+            .collect::<Result<_>>()?;
+        if chords.is_empty() {
+            return Err(eyre!("Empty key sequence"));
+        }
+        Ok(chords)
+    }
+}
+
+impl From<KeyEvent> for KeyChord {
+    fn from(key_event: KeyEvent) -> Self {
+        Self::new(key_event.code, key_event.modifiers)
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "Ctrl+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "Alt+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "Shift+")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "SPACE"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::Enter => write!(f, "ENTER"),
+            KeyCode::Esc => write!(f, "ESC"),
+            KeyCode::Tab => write!(f, "TAB"),
+            KeyCode::BackTab => write!(f, "Shift+TAB"),
+            KeyCode::Backspace => write!(f, "BACKSPACE"),
+            KeyCode::Delete => write!(f, "DEL"),
+            KeyCode::Up => write!(f, "↑"),
+            KeyCode::Down => write!(f, "↓"),
+            KeyCode::Left => write!(f, "←"),
+            KeyCode::Right => write!(f, "→"),
+            KeyCode::F(n) => write!(f, "F{}", n),
+            code => write!(f, "{:?}", code),
+        }
+    }
+}
+
+/// Actions which can be bound to keys. The names returned by `name()`
+/// are used as keys of the `[keybindings]` section of the config file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyAction {
+    ToggleArea,
+    Search,
+    AddEntry,
+    ShowHelp,
+    Diagnostics,
+    Quit,
+    Confirm,
+    Reset,
+    NextRow,
+    PrevRow,
+    NextRowFast,
+    PrevRowFast,
+    NextColumn,
+    PrevColumn,
+    Top,
+    Bottom,
+    ScrollInfoDown,
+    ScrollInfoUp,
+    Sort,
+    Yank,
+    YankReference,
+    ToggleMark,
+    ToggleVisual,
+    Delete,
+    Move,
+    Copy,
+    Duplicates,
+    Lint,
+    RegenerateCitekeys,
+    Edit,
+    EditField,
+    AttachFile,
+    Open,
+    Export,
+    AddKeyword,
+    AddKeywordFiltered,
+    RemoveKeyword,
+    RemoveKeywordFiltered,
+    RenameKeyword,
+    FilterAnd,
+    FilterOr,
+    FilterNot,
+    FilterPop,
+}
+
+impl KeyAction {
+    /// All actions in the order they appear in the help popup
+    pub const ALL: [KeyAction; 43] = [
+        Self::ToggleArea,
+        Self::Search,
+        Self::AddEntry,
+        Self::ShowHelp,
+        Self::Diagnostics,
+        Self::Quit,
+        Self::Confirm,
+        Self::Reset,
+        Self::NextRow,
+        Self::PrevRow,
+        Self::NextRowFast,
+        Self::PrevRowFast,
+        Self::NextColumn,
+        Self::PrevColumn,
+        Self::Top,
+        Self::Bottom,
+        Self::ScrollInfoDown,
+        Self::ScrollInfoUp,
+        Self::Sort,
+        Self::Yank,
+        Self::YankReference,
+        Self::ToggleMark,
+        Self::ToggleVisual,
+        Self::Delete,
+        Self::Move,
+        Self::Copy,
+        Self::Duplicates,
+        Self::Lint,
+        Self::RegenerateCitekeys,
+        Self::Edit,
+        Self::EditField,
+        Self::AttachFile,
+        Self::Open,
+        Self::Export,
+        Self::AddKeyword,
+        Self::AddKeywordFiltered,
+        Self::RemoveKeyword,
+        Self::RemoveKeywordFiltered,
+        Self::RenameKeyword,
+        Self::FilterAnd,
+        Self::FilterOr,
+        Self::FilterNot,
+        Self::FilterPop,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::ToggleArea => "toggle_area",
+            Self::Search => "search",
+            Self::AddEntry => "add_entry",
+            Self::ShowHelp => "show_help",
+            Self::Diagnostics => "diagnostics",
+            Self::Quit => "quit",
+            Self::Confirm => "confirm",
+            Self::Reset => "reset",
+            Self::NextRow => "next_row",
+            Self::PrevRow => "prev_row",
+            Self::NextRowFast => "next_row_fast",
+            Self::PrevRowFast => "prev_row_fast",
+            Self::NextColumn => "next_column",
+            Self::PrevColumn => "prev_column",
+            Self::Top => "top",
+            Self::Bottom => "bottom",
+            Self::ScrollInfoDown => "scroll_info_down",
+            Self::ScrollInfoUp => "scroll_info_up",
+            Self::Sort => "sort",
+            Self::Yank => "yank",
+            Self::YankReference => "yank_reference",
+            Self::ToggleMark => "toggle_mark",
+            Self::ToggleVisual => "toggle_visual",
+            Self::Delete => "delete",
+            Self::Move => "move",
+            Self::Copy => "copy",
+            Self::Duplicates => "duplicates",
+            Self::Lint => "lint",
+            Self::RegenerateCitekeys => "regenerate_citekeys",
+            Self::Edit => "edit",
+            Self::EditField => "edit_field",
+            Self::AttachFile => "attach_file",
+            Self::Open => "open",
+            Self::Export => "export",
+            Self::AddKeyword => "add_keyword",
+            Self::AddKeywordFiltered => "add_keyword_filtered",
+            Self::RemoveKeyword => "remove_keyword",
+            Self::RemoveKeywordFiltered => "remove_keyword_filtered",
+            Self::RenameKeyword => "rename_keyword",
+            Self::FilterAnd => "filter_and",
+            Self::FilterOr => "filter_or",
+            Self::FilterNot => "filter_not",
+            Self::FilterPop => "filter_pop",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|a| a.name() == name)
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::ToggleArea => "Toggle areas (Entries, Keywords)",
+            Self::Search => "Enter search mode",
+            Self::AddEntry => "Add new entry",
+            Self::ShowHelp => "Show help",
+            Self::Diagnostics => "Show problems found while loading the bibfiles",
+            Self::Quit => "Quit bibiman",
+            Self::Confirm => "Filter by selected keyword / Confirm selection",
+            Self::Reset => "Reset all lists / Close popup",
+            Self::NextRow => "Select next entry/keyword",
+            Self::PrevRow => "Select previous entry/keyword",
+            Self::NextRowFast => "Select fifth-next entry/keyword",
+            Self::PrevRowFast => "Select fifth-previous entry/keyword",
+            Self::NextColumn => "Select next column",
+            Self::PrevColumn => "Select previous column",
+            Self::Top => "Go to first entry/keyword",
+            Self::Bottom => "Go to last entry/keyword",
+            Self::ScrollInfoDown => "Scroll info area down",
+            Self::ScrollInfoUp => "Scroll info area up",
+            Self::Sort => "Sort entries by selected column (toggles reversed)",
+            Self::Yank => "Yank/copy citekey of selected/marked entries to clipboard",
+            Self::YankReference => "Yank/copy formatted reference of selected/marked entries",
+            Self::ToggleMark => "Mark/unmark selected entry",
+            Self::ToggleVisual => "Start/finish marking a range of entries",
+            Self::Delete => "Delete selected/marked entries from bibfiles",
+            Self::Move => "Move selected/marked entries to another bibfile",
+            Self::Copy => "Copy selected/marked entries to another bibfile",
+            Self::Duplicates => "Show duplicate entries and merge them",
+            Self::Lint => "Check entries for missing fields, dates, crossrefs and files",
+            Self::RegenerateCitekeys => {
+                "Regenerate citekeys of selected/marked entries from pattern"
+            }
+            Self::Edit => "Open editor at selected entry",
+            Self::EditField => "Edit a field of selected entry inside bibiman",
+            Self::AttachFile => "Attach a file to selected entry",
+            Self::Open => "Open PDF or DOI/URL of selected entry",
+            Self::Export => "Export marked or all listed entries to file",
+            Self::AddKeyword => "Add keywords to selected/marked entries",
+            Self::AddKeywordFiltered => "Add keywords to all listed entries",
+            Self::RemoveKeyword => "Remove keyword from selected/marked entries",
+            Self::RemoveKeywordFiltered => "Remove keyword from all listed entries",
+            Self::RenameKeyword => "Rename/merge keyword in all entries",
+            Self::FilterAnd => "Add selected keyword to filter (AND)",
+            Self::FilterOr => "Add selected keyword to filter (OR)",
+            Self::FilterNot => "Add selected keyword to filter (AND NOT)",
+            Self::FilterPop => "Remove last keyword from filter",
+        }
+    }
+
+    /// Heading of the help popup section the action is listed under
+    pub fn section(&self) -> &'static str {
+        match self {
+            Self::ToggleArea
+            | Self::Search
+            | Self::AddEntry
+            | Self::ShowHelp
+            | Self::Diagnostics
+            | Self::Quit
+            | Self::Confirm
+            | Self::Reset => "General",
+            Self::NextRow
+            | Self::PrevRow
+            | Self::NextRowFast
+            | Self::PrevRowFast
+            | Self::NextColumn
+            | Self::PrevColumn
+            | Self::Top
+            | Self::Bottom
+            | Self::ScrollInfoDown
+            | Self::ScrollInfoUp => "Movement",
+            Self::Sort
+            | Self::Yank
+            | Self::YankReference
+            | Self::ToggleMark
+            | Self::ToggleVisual
+            | Self::Delete
+            | Self::Move
+            | Self::Copy
+            | Self::Duplicates
+            | Self::Lint
+            | Self::RegenerateCitekeys
+            | Self::Edit
+            | Self::EditField
+            | Self::AttachFile
+            | Self::Open
+            | Self::Export => "Entry Table",
+            Self::AddKeyword
+            | Self::AddKeywordFiltered
+            | Self::RemoveKeyword
+            | Self::RemoveKeywordFiltered
+            | Self::RenameKeyword
+            | Self::FilterAnd
+            | Self::FilterOr
+            | Self::FilterNot
+            | Self::FilterPop => "Keywords",
+        }
+    }
+
+    fn default_keys(&self) -> &'static [&'static str] {
+        match self {
+            Self::ToggleArea => &["tab", "backtab"],
+            Self::Search => &["/", "ctrl-f"],
+            Self::AddEntry => &["a"],
+            Self::ShowHelp => &["?"],
+            Self::Diagnostics => &["P"],
+            Self::Quit => &["q", "ctrl-c"],
+            Self::Confirm => &["enter"],
+            Self::Reset => &["esc"],
+            Self::NextRow => &["j", "down"],
+            Self::PrevRow => &["k", "up"],
+            Self::NextRowFast => &["ctrl-d"],
+            Self::PrevRowFast => &["ctrl-u"],
+            Self::NextColumn => &["l", "right"],
+            Self::PrevColumn => &["h", "left"],
+            Self::Top => &["g", "home"],
+            Self::Bottom => &["G", "end"],
+            Self::ScrollInfoDown => &["alt-j", "alt-down", "pagedown"],
+            Self::ScrollInfoUp => &["alt-k", "alt-up", "pageup"],
+            Self::Sort => &["s"],
+            Self::Yank => &["y"],
+            Self::YankReference => &["Y"],
+            Self::ToggleMark => &["space"],
+            Self::ToggleVisual => &["v"],
+            Self::Delete => &["D"],
+            Self::Move => &["M"],
+            Self::Copy => &["C"],
+            Self::Duplicates => &["="],
+            Self::Lint => &["L"],
+            Self::RegenerateCitekeys => &["R"],
+            Self::Edit => &["e"],
+            Self::EditField => &["E"],
+            Self::AttachFile => &["A"],
+            Self::Open => &["o"],
+            Self::Export => &["x"],
+            Self::AddKeyword => &["K a"],
+            Self::AddKeywordFiltered => &["K A"],
+            Self::RemoveKeyword => &["K d"],
+            Self::RemoveKeywordFiltered => &["K D"],
+            Self::RenameKeyword => &["K r"],
+            Self::FilterAnd => &["+"],
+            Self::FilterOr => &["|"],
+            Self::FilterNot => &["!"],
+            Self::FilterPop => &["backspace"],
+        }
+    }
+}
+
+impl From<KeyAction> for CmdAction {
+    fn from(action: KeyAction) -> Self {
+        match action {
+            KeyAction::ToggleArea => Self::ToggleArea,
+            KeyAction::Search => Self::Input(InputCmdAction::Enter),
+            KeyAction::AddEntry => Self::AddEntry,
+            KeyAction::ShowHelp => Self::ShowHelp,
+            KeyAction::Diagnostics => Self::ShowDiagnostics,
+            KeyAction::Quit => Self::Exit,
+            KeyAction::Confirm => Self::Confirm,
+            KeyAction::Reset => Self::Reset,
+            KeyAction::NextRow => Self::SelectNextRow(1),
+            KeyAction::PrevRow => Self::SelectPrevRow(1),
+            KeyAction::NextRowFast => Self::SelectNextRow(5),
+            KeyAction::PrevRowFast => Self::SelectPrevRow(5),
+            KeyAction::NextColumn => Self::SelectNextCol,
+            KeyAction::PrevColumn => Self::SelectPrevCol,
+            KeyAction::Top => Self::Top,
+            KeyAction::Bottom => Self::Bottom,
+            KeyAction::ScrollInfoDown => Self::ScrollInfoDown,
+            KeyAction::ScrollInfoUp => Self::ScrollInfoUp,
+            KeyAction::Sort => Self::SortList,
+            KeyAction::Yank => Self::YankItem,
+            KeyAction::YankReference => Self::YankReference,
+            KeyAction::ToggleMark => Self::ToggleMark,
+            KeyAction::ToggleVisual => Self::ToggleVisual,
+            KeyAction::Delete => Self::DeleteEntries,
+            KeyAction::Move => Self::MoveEntries,
+            KeyAction::Copy => Self::CopyEntries,
+            KeyAction::Duplicates => Self::ShowDuplicates,
+            KeyAction::Lint => Self::ShowLint,
+            KeyAction::RegenerateCitekeys => Self::RegenerateCitekeys,
+            KeyAction::Edit => Self::EditFile,
+            KeyAction::EditField => Self::EditField,
+            KeyAction::AttachFile => Self::AttachFile,
+            KeyAction::Open => Self::Open,
+            KeyAction::Export => Self::Export,
+            KeyAction::AddKeyword => Self::AddKeyword(KeywordScope::Selected),
+            KeyAction::AddKeywordFiltered => Self::AddKeyword(KeywordScope::Filtered),
+            KeyAction::RemoveKeyword => Self::RemoveKeyword(KeywordScope::Selected),
+            KeyAction::RemoveKeywordFiltered => Self::RemoveKeyword(KeywordScope::Filtered),
+            KeyAction::RenameKeyword => Self::RenameKeyword,
+            KeyAction::FilterAnd => Self::FilterTags(FilterOp::And),
+            KeyAction::FilterOr => Self::FilterTags(FilterOp::Or),
+            KeyAction::FilterNot => Self::FilterTags(FilterOp::Not),
+            KeyAction::FilterPop => Self::PopTagFilter,
+        }
+    }
+}
+
+/// Result of looking up the currently pressed keys in the keymap
+#[derive(Debug, PartialEq, Eq)]
+pub enum KeyLookup {
+    // Keys match a binding completely
+    Action(KeyAction),
+    // Keys are the beginning of at least one multi-key binding
+    Pending,
+    // Keys don't match any binding
+    NoMatch,
+}
+
+/// Table which maps actions to one or more key sequences
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: Vec<(KeyAction, Vec<Vec<KeyChord>>)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let bindings = KeyAction::ALL
+            .into_iter()
+            .map(|action| {
+                let sequences = action
+                    .default_keys()
+                    .iter()
+                    .map(|k| KeyChord::parse_sequence(k).unwrap())
+                    .collect();
+                (action, sequences)
+            })
+            .collect();
+        Self { bindings }
+    }
+}
+
+impl Keymap {
+    /// Create the default keymap and replace the bindings of all actions
+    /// listed in the `[keybindings]` section of the config file
+    pub fn new(custom: &BTreeMap<String, Vec<String>>) -> Result<Self> {
+        let mut keymap = Self::default();
+        for (name, sequences) in custom {
+            let action = KeyAction::from_name(name)
+                .ok_or_else(|| eyre!("Unknown action \"{}\" in keybindings", name))?;
+            let sequences = sequences
+                .iter()
+                .map(|s| KeyChord::parse_sequence(s))
+                .collect::<Result<Vec<_>>>()?;
+            if let Some(binding) = keymap.bindings.iter_mut().find(|(a, _)| *a == action) {
+                binding.1 = sequences;
+            }
+        }
+        keymap.check_conflicts()?;
+        Ok(keymap)
+    }
+
+    // Each key sequence must trigger a single action. A sequence which is the
+    // beginning of a longer one would make the longer one unreachable
+    fn check_conflicts(&self) -> Result<()> {
+        let sequences: Vec<(KeyAction, &Vec<KeyChord>)> = self
+            .bindings
+            .iter()
+            .flat_map(|(action, sequences)| sequences.iter().map(move |seq| (*action, seq)))
+            .collect();
+        let format = |seq: &[KeyChord]| seq.iter().map(|c| c.to_string()).join(" ");
+        for (i, (action, seq)) in sequences.iter().enumerate() {
+            for (other_action, other) in &sequences[i + 1..] {
+                if seq == other && action != other_action {
+                    return Err(eyre!(
+                        "Keys \"{}\" are bound to both {} and {}",
+                        format(seq),
+                        action.name(),
+                        other_action.name()
+                    ));
+                }
+                let (short, short_action, long, long_action) = if seq.len() < other.len() {
+                    (seq, action, other, other_action)
+                } else {
+                    (other, other_action, seq, action)
+                };
+                if short.len() < long.len() && long.starts_with(short) {
+                    return Err(eyre!(
+                        "Keys \"{}\" of {} hide \"{}\" of {}",
+                        format(short),
+                        short_action.name(),
+                        format(long),
+                        long_action.name()
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Look up the keys pressed so far
+    pub fn lookup(&self, keys: &[KeyChord]) -> KeyLookup {
+        let mut pending = false;
+        for (action, sequences) in &self.bindings {
+            for sequence in sequences {
+                if sequence.as_slice() == keys {
+                    return KeyLookup::Action(*action);
+                } else if sequence.len() > keys.len() && sequence.starts_with(keys) {
+                    pending = true;
+                }
+            }
+        }
+        if pending {
+            KeyLookup::Pending
+        } else {
+            KeyLookup::NoMatch
+        }
+    }
+
+    /// Keys bound to the action formatted for the help popup, e.g. `j|↓`
+    pub fn keys_for(&self, action: KeyAction) -> String {
+        self.bindings
+            .iter()
+            .find(|(a, _)| *a == action)
+            .map(|(_, sequences)| {
+                sequences
+                    .iter()
+                    .map(|seq| seq.iter().map(|c| c.to_string()).join(" "))
+                    .collect::<Vec<String>>()
+                    .join("|")
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_chords() {
+        assert_eq!(
+            KeyChord::parse("ctrl-d").unwrap(),
+            KeyChord::new(KeyCode::Char('d'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(
+            KeyChord::parse("G").unwrap(),
+            KeyChord::from(KeyEvent::new(KeyCode::Char('G'), KeyModifiers::SHIFT))
+        );
+        assert_eq!(
+            KeyChord::parse("-").unwrap(),
+            KeyChord::new(KeyCode::Char('-'), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            KeyChord::parse("alt-PageDown").unwrap(),
+            KeyChord::new(KeyCode::PageDown, KeyModifiers::ALT)
+        );
+        assert_eq!(
+            KeyChord::parse("shift-a").unwrap(),
+            KeyChord::from(KeyEvent::new(KeyCode::Char('A'), KeyModifiers::SHIFT))
+        );
+        assert_eq!(
+            KeyChord::parse("ctrl-shift-a").unwrap(),
+            KeyChord::new(KeyCode::Char('A'), KeyModifiers::CONTROL)
+        );
+        assert!(KeyChord::parse("shift-1").is_err());
+        assert!(KeyChord::parse("hyper-x").is_err());
+        assert!(KeyChord::parse("foo").is_err());
+    }
+
+    #[test]
+    fn lookup_sequences() {
+        let custom = BTreeMap::from([
+            ("top".to_string(), vec!["g g".to_string()]),
+            ("quit".to_string(), vec!["ctrl-x ctrl-c".to_string()]),
+        ]);
+        let keymap = Keymap::new(&custom).unwrap();
+        let g = KeyChord::parse("g").unwrap();
+        let ctrl_x = KeyChord::parse("ctrl-x").unwrap();
+        let ctrl_c = KeyChord::parse("ctrl-c").unwrap();
+
+        assert_eq!(keymap.lookup(&[g]), KeyLookup::Pending);
+        assert_eq!(keymap.lookup(&[g, g]), KeyLookup::Action(KeyAction::Top));
+        assert_eq!(keymap.lookup(&[ctrl_x]), KeyLookup::Pending);
+        assert_eq!(
+            keymap.lookup(&[ctrl_x, ctrl_c]),
+            KeyLookup::Action(KeyAction::Quit)
+        );
+        // Default binding of quit is replaced
+        assert_eq!(keymap.lookup(&[ctrl_c]), KeyLookup::NoMatch);
+        assert_eq!(keymap.keys_for(KeyAction::Top), "g g");
+        assert_eq!(keymap.keys_for(KeyAction::NextRow), "j|↓");
+    }
+
+    #[test]
+    fn unknown_action() {
+        let custom = BTreeMap::from([("fly".to_string(), vec!["f".to_string()])]);
+        assert!(Keymap::new(&custom).is_err());
+    }
+
+    #[test]
+    fn conflicting_keys() {
+        // `x` is bound to export by default
+        let custom = BTreeMap::from([("quit".to_string(), vec!["x".to_string()])]);
+        let err = Keymap::new(&custom).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Keys \"x\" are bound to both quit and export"
+        );
+
+        // `g` of top would hide `g x`
+        let custom = BTreeMap::from([("bottom".to_string(), vec!["g x".to_string()])]);
+        let err = Keymap::new(&custom).unwrap_err();
+        assert_eq!(err.to_string(), "Keys \"g\" of top hide \"g x\" of bottom");
+    }
+}
@@ -21,15 +21,125 @@ use ratatui::{
     widgets::ListState,
 };
 
-use crate::cliargs::CLIArgs;
+use tui_input::Input;
 
-#[derive(Debug)]
+use crate::bibiman::export::ExportFormat;
+use crate::config::BibiConfig;
+use crate::tui::keymap::KeyAction;
+
+#[derive(Debug, Clone)]
 pub enum PopupKind {
     Help,
     MessageConfirm,
     MessageError,
     Selection,
     AddEntry,
+    // List of items, the action is run for the selected one
+    ListSelection(ListAction),
+    // Single line input, the action is run with its value
+    Input(InputAction),
+    // Lookup of a new entry in progress, can be cancelled
+    Lookup,
+}
+
+/// Entries affected by keyword changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeywordScope {
+    // Marked entries, or the selected entry if none is marked
+    Selected,
+    // All entries currently listed in the entry table
+    Filtered,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListAction {
+    EditField,
+    RemoveKeyword(KeywordScope),
+    RenameKeyword,
+    Export,
+    // Confirm deleting the marked or selected entries
+    DeleteEntries,
+    // Choose the bibfile the marked or selected entries are moved/copied to
+    MoveEntries,
+    CopyEntries,
+    // Groups of duplicate entries, the selected one is merged
+    Duplicates,
+    // Choose the entry which is kept when merging duplicates
+    MergeBase,
+    // Choose the value of the named field for the merged entry
+    MergeField(String),
+    // Problems found while loading the bibfiles, confirming closes the list
+    Diagnostics,
+    // Issues found by the lint rules, the entry of the selected one is shown
+    Lint,
+    // Choose the bibfile a new entry is added to
+    NewEntryFile,
+    // Choose the bibfile imported entries are added to
+    ImportFile,
+    // Path of the attached file, the entry already has other files
+    AttachFile(String),
+    // Confirm overwriting the existing file with the exported entries
+    OverwriteExport(ExportFormat, String),
+    // Citekeys changed by the pattern, confirming renames all entries
+    RegenerateCitekeys,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputAction {
+    // Name of the edited field
+    EditField(String),
+    AddKeyword(KeywordScope),
+    // Keyword which is renamed
+    RenameKeyword(String),
+    // Path of the file the listed entries are exported to
+    Export(ExportFormat),
+    // Citekey of a new entry, which is shown for review
+    NewEntryCitekey,
+    // Path of the file attached to the selected entry
+    AttachFile,
+}
+
+impl ListAction {
+    pub fn title(&self) -> String {
+        match self {
+            Self::EditField => " Edit Field ".to_string(),
+            Self::RemoveKeyword(KeywordScope::Selected) => " Remove Keyword ".to_string(),
+            Self::RemoveKeyword(KeywordScope::Filtered) => {
+                " Remove Keyword (all listed entries) ".to_string()
+            }
+            Self::RenameKeyword => " Rename Keyword ".to_string(),
+            Self::Export => " Export Entries As ".to_string(),
+            Self::DeleteEntries => " Delete Entries? ".to_string(),
+            Self::MoveEntries => " Move Entries To ".to_string(),
+            Self::CopyEntries => " Copy Entries To ".to_string(),
+            Self::Duplicates => " Duplicates: Select Group To Merge ".to_string(),
+            Self::MergeBase => " Merge: Entry To Keep ".to_string(),
+            Self::MergeField(field) => format!(" Merge: Value Of {} ", field),
+            Self::Diagnostics => " Problems In Bibfiles ".to_string(),
+            Self::Lint => " Lint: Select Issue To Show Entry ".to_string(),
+            Self::NewEntryFile => " Add Entry To ".to_string(),
+            Self::ImportFile => " Import Entries To ".to_string(),
+            Self::AttachFile(_) => " Attach File: Entry Has Files ".to_string(),
+            Self::OverwriteExport(..) => " Export: File Exists ".to_string(),
+            Self::RegenerateCitekeys => " Regenerate Citekeys: Enter Renames All ".to_string(),
+        }
+    }
+}
+
+impl InputAction {
+    pub fn title(&self) -> String {
+        match self {
+            Self::EditField(field) => format!(" Edit Field: {} ", field),
+            Self::AddKeyword(KeywordScope::Selected) => " Add Keywords ".to_string(),
+            Self::AddKeyword(KeywordScope::Filtered) => {
+                " Add Keywords (all listed entries) ".to_string()
+            }
+            Self::RenameKeyword(keyword) => format!(" Rename/Merge Keyword: {} ", keyword),
+            Self::Export(format) => format!(" Export as {} to file ", format.name()),
+            Self::NewEntryCitekey => " New Entry: Citekey ".to_string(),
+            Self::AttachFile => " Attach File ".to_string(),
+        }
+    }
 }
 
 #[derive(Debug, Default)]
@@ -42,41 +152,51 @@ pub struct PopupArea {
     pub popup_state: ListState,
     pub add_entry_input: String,
     pub add_entry_cursor_position: usize,
+    pub popup_input: Input,
+    // Text shown above the input, e.g. the reviewed entry
+    pub popup_input_info: Vec<String>,
+    // Problems shown above the info text
+    pub popup_input_warnings: Vec<String>,
 }
 
 impl PopupArea {
-    pub fn popup_help<'a>(args: &CLIArgs) -> Text<'a> {
-        let help = [
-            ("General", "first"),
-            ("TAB: ", "Toggle areas (Entries, Keywords)"),
-            ("/|Ctrl+f: ", "Enter search mode"),
-            ("q|Ctrl+c: ", "Quit bibiman"),
-            ("a: ", "Add new entry"),
-            ("?: ", "Show help"),
-            ("Entry Table", "sub"),
-            ("j,k|↓,↑: ", "Select next/previous entry"),
-            ("Ctrl+d,Ctrl+u: ", "Select fifth-next/previous entry"),
-            ("h,l|←,→: ", "Select next/previous column"),
-            ("g|Home: ", "Go to first entry"),
-            ("G|End: ", "Go to last entry"),
-            ("s: ", "sort entries by selected column (toggles reversed)"),
-            ("y: ", "yank/copy citekey of selected entry to clipboard"),
-            ("e: ", "Open editor at selected entry"),
-            ("o: ", "Open with selected entry associated PDF"),
-            ("u: ", "Open DOI/URL of selected entry"),
-            ("ESC: ", "Reset all lists"),
-            ("Keyword List", "sub"),
-            ("j,k|↓,↑: ", "Select next/previous item"),
-            ("Ctrl+d,Ctrl+u: ", "Select fifth-next/previous entry"),
-            ("g|Home: ", "Go to first keyword"),
-            ("G|End: ", "Go to last keyword"),
-            ("ENTER: ", "Filter by selected keyword"),
+    pub fn popup_help<'a>(cfg: &BibiConfig) -> Text<'a> {
+        // Keybindings are taken from the keymap, thus, changes through the
+        // config file are reflected here. The section headings are inserted
+        // whenever the section of the actions changes
+        let mut help: Vec<(String, String)> = vec![];
+        let mut section = "";
+        for action in KeyAction::ALL {
+            if action.section() != section {
+                section = action.section();
+                let kind = if help.is_empty() { "first" } else { "sub" };
+                help.push((section.to_string(), kind.to_string()));
+            }
+            help.push((
+                format!("{}: ", cfg.keymap.keys_for(action)),
+                action.description().to_string(),
+            ));
+        }
+
+        // Keys of the search input can't be changed
+        let search_help = [
             ("Search", "sub"),
             ("↓,↑,←,→: ", "Move cursor"),
             ("BACKSPACE: ", "Delete Character"),
             ("ENTER: ", "Confirm search"),
             ("ESC: ", "Abort search"),
+            ("field:value: ", "Search single field, e.g. author:miller"),
+            (
+                "year:>2015: ",
+                "Search years, also <, >=, <= and 2010..2015",
+            ),
+            ("-term: ", "Exclude entries matching the term"),
         ];
+        help.extend(
+            search_help
+                .into_iter()
+                .map(|(keys, text)| (keys.to_string(), text.to_string())),
+        );
 
         let mut helptext: Vec<Line<'_>> = vec![];
 
@@ -85,20 +205,20 @@ impl PopupArea {
                 helptext.push(Line::from(
                     Span::raw(keys)
                         .bold()
-                        .fg(Color::Indexed(args.colors.main_text_color)),
+                        .fg(Color::Indexed(cfg.colors.main_text_color)),
                 ))
             } else if help == "sub" {
                 helptext.push(Line::from(""));
                 helptext.push(Line::from(
                     Span::raw(keys)
                         .bold()
-                        .fg(Color::Indexed(args.colors.main_text_color)),
+                        .fg(Color::Indexed(cfg.colors.main_text_color)),
                 ))
             } else {
                 helptext.push(Line::from(vec![
                     Span::raw(keys)
                         .bold()
-                        .fg(Color::Indexed(args.colors.keyword_color)),
+                        .fg(Color::Indexed(cfg.colors.keyword_color)),
                     Span::raw(help),
                 ]))
             }
@@ -127,6 +247,27 @@ impl PopupArea {
         self.is_popup = true;
     }
 
+    pub fn popup_list_selection(&mut self, items: Vec<String>, action: ListAction) {
+        self.popup_list = items;
+        self.popup_kind = Some(PopupKind::ListSelection(action));
+        self.popup_state.select(Some(0));
+        self.is_popup = true;
+    }
+
+    pub fn popup_input(&mut self, value: &str, action: InputAction) {
+        self.popup_input = Input::new(value.to_owned());
+        self.popup_kind = Some(PopupKind::Input(action));
+        self.is_popup = true;
+    }
+
+    /// Selected item of a list selection popup
+    pub fn selected_item(&self) -> Option<&str> {
+        self.popup_state
+            .selected()
+            .and_then(|idx| self.popup_list.get(idx))
+            .map(|item| item.as_str())
+    }
+
     pub fn popup_scroll_down(&mut self) {
         self.popup_scroll_pos = self.popup_scroll_pos.saturating_add(1)
     }
@@ -19,10 +19,14 @@ use std::path::PathBuf;
 
 use super::colors::AppColorScheme;
 use super::popup::PopupArea;
+use crate::bibiman::attach;
 use crate::bibiman::entries::EntryTableColumn;
+use crate::bibiman::fulltext::snippet;
+use crate::bibiman::keywords::{format_keyword_filter, FilterOp};
 use crate::bibiman::{CurrentArea, FormerArea};
 use crate::cliargs::CLIArgs;
-use crate::tui::popup::PopupKind;
+use crate::config::BibiConfig;
+use crate::tui::popup::{InputAction, PopupKind};
 use crate::App;
 use ratatui::layout::{Direction, Position};
 use ratatui::widgets::Clear;
@@ -42,17 +46,18 @@ use walkdir::WalkDir;
 // Symbols
 static SORTED_ENTRIES: &str = "▼";
 static SORTED_ENTRIES_REVERSED: &str = "▲";
+static MARKED_ENTRY: &str = "● ";
 static SCROLLBAR_UPPER_CORNER: Option<&str> = Some("┓");
 static SCROLLBAR_LOWER_CORNER: Option<&str> = Some("┛");
 
 pub fn color_list(
-    args: &CLIArgs,
+    cfg: &BibiConfig,
     list_item: i32,
     sel_item: i32,
     highlight: u8,
     max_diff: i32,
 ) -> Color {
-    match args.colors.color_scheme {
+    match cfg.colors.color_scheme {
         AppColorScheme::Dark => {
             if list_item == sel_item {
                 Color::Indexed(highlight)
@@ -103,7 +108,7 @@ fn count_files(files: &[PathBuf]) -> u16 {
     count
 }
 
-pub fn render_ui(app: &mut App, args: &CLIArgs, frame: &mut Frame) {
+pub fn render_ui(app: &mut App, args: &CLIArgs, cfg: &BibiConfig, frame: &mut Frame) {
     let [header_area, main_area, footer_area] = Layout::new(
         Direction::Vertical,
         [
@@ -128,20 +133,34 @@ pub fn render_ui(app: &mut App, args: &CLIArgs, frame: &mut Frame) {
     let [tag_area, info_area] =
         Layout::horizontal([Constraint::Max(25), Constraint::Min(35)]).areas(item_area);
 
-    render_header(args, frame, header_area);
+    let reference = app
+        .bibiman
+        .selected_reference()
+        .map(|r| reference_paragraph(r, app, cfg));
+    let reference_height = reference.as_ref().map_or(0, |p| {
+        (p.line_count(info_area.width) as u16).min(info_area.height / 2)
+    });
+    let [info_area, reference_area] =
+        Layout::vertical([Constraint::Fill(1), Constraint::Length(reference_height)])
+            .areas(info_area);
+
+    render_header(cfg, frame, header_area);
     if let CurrentArea::SearchArea = app.bibiman.current_area {
-        render_footer(app, args, frame, footer_area);
+        render_footer(app, cfg, frame, footer_area);
+    }
+    render_entrytable(app, cfg, frame, entry_area);
+    render_selected_item(app, cfg, frame, info_area);
+    if let Some(reference) = reference {
+        frame.render_widget(reference, reference_area);
     }
-    render_entrytable(app, args, frame, entry_area);
-    render_selected_item(app, args, frame, info_area);
-    render_taglist(app, args, frame, tag_area);
-    render_file_info(app, args, frame, entry_info_area);
+    render_taglist(app, cfg, frame, tag_area);
+    render_file_info(app, args, cfg, frame, entry_info_area);
     if app.bibiman.popup_area.is_popup {
-        render_popup(app, args, frame);
+        render_popup(app, cfg, frame);
     }
 }
 
-pub fn render_popup(app: &mut App, args: &CLIArgs, frame: &mut Frame) {
+pub fn render_popup(app: &mut App, cfg: &BibiConfig, frame: &mut Frame) {
     match app.bibiman.popup_area.popup_kind {
         Some(PopupKind::Help) => {
             let block = Block::bordered()
@@ -150,13 +169,13 @@ pub fn render_popup(app: &mut App, args: &CLIArgs, frame: &mut Frame) {
                 .title_alignment(Alignment::Center)
                 .style(
                     Style::new()
-                        .fg(Color::Indexed(args.colors.main_text_color))
-                        .bg(Color::Indexed(args.colors.popup_bg_color)),
+                        .fg(Color::Indexed(cfg.colors.main_text_color))
+                        .bg(Color::Indexed(cfg.colors.popup_bg_color)),
                 )
                 .border_set(symbols::border::THICK)
-                .border_style(Style::new().fg(Color::Indexed(args.colors.entry_color)));
+                .border_style(Style::new().fg(Color::Indexed(cfg.colors.entry_color)));
 
-            let text: Text = PopupArea::popup_help(args);
+            let text: Text = PopupArea::popup_help(cfg);
 
             // Calculate max scroll position depending on hight of terminal window
             // Needed length is number of text lines plus two for borders at bottom and top
@@ -191,23 +210,23 @@ pub fn render_popup(app: &mut App, args: &CLIArgs, frame: &mut Frame) {
                 .title_alignment(Alignment::Center)
                 .style(
                     Style::new()
-                        .fg(Color::Indexed(args.colors.main_text_color))
-                        .bg(Color::Indexed(args.colors.popup_bg_color)),
+                        .fg(Color::Indexed(cfg.colors.main_text_color))
+                        .bg(Color::Indexed(cfg.colors.popup_bg_color)),
                 )
                 .border_set(symbols::border::THICK)
-                .border_style(Style::new().fg(Color::Indexed(args.colors.entry_color)));
+                .border_style(Style::new().fg(Color::Indexed(cfg.colors.entry_color)));
 
             // Prepare the input fields
             let content = vec![
                 Line::from(vec![Span::styled(
-                    "DOI: ",
-                    Style::new().fg(Color::Indexed(args.colors.entry_color)),
+                    "DOI, ISBN, arXiv ID, PubMed ID or pasted BibTeX/RIS: ",
+                    Style::new().fg(Color::Indexed(cfg.colors.entry_color)),
                 )]),
                 Line::from(app.bibiman.popup_area.add_entry_input.clone()),
             ];
             let paragraph = Paragraph::new(content)
                 .block(block.clone())
-                .style(Style::new().fg(Color::Indexed(args.colors.main_text_color)))
+                .style(Style::new().fg(Color::Indexed(cfg.colors.main_text_color)))
                 .wrap(Wrap { trim: false });
 
             // Calculate popup size
@@ -230,6 +249,40 @@ pub fn render_popup(app: &mut App, args: &CLIArgs, frame: &mut Frame) {
                 frame.set_cursor_position(Position::new(cursor_x, cursor_y));
             }
         }
+        Some(PopupKind::Lookup) => {
+            let Some(lookup) = &app.bibiman.lookup else {
+                return;
+            };
+            let block = Block::bordered()
+                .title_top(" Add Entry ".bold())
+                .title_bottom(" (ESC) Cancel ".bold())
+                .title_alignment(Alignment::Center)
+                .style(
+                    Style::new()
+                        .fg(Color::Indexed(cfg.colors.main_text_color))
+                        .bg(Color::Indexed(cfg.colors.popup_bg_color)),
+                )
+                .border_set(symbols::border::THICK)
+                .border_style(Style::new().fg(Color::Indexed(cfg.colors.entry_color)));
+
+            let message = format!(
+                "{} Looking up {} {}",
+                lookup.spinner(),
+                lookup.name,
+                lookup.id
+            );
+            let popup_area = popup_area(
+                frame.area(),
+                (message.chars().count() + 4).max(20) as u16,
+                3,
+            );
+            let content = Paragraph::new(message)
+                .block(block)
+                .style(Style::new().fg(Color::Indexed(cfg.colors.entry_color)));
+
+            frame.render_widget(Clear, popup_area);
+            frame.render_widget(content, popup_area)
+        }
         Some(PopupKind::MessageConfirm) => {
             let area = frame.area();
 
@@ -237,18 +290,18 @@ pub fn render_popup(app: &mut App, args: &CLIArgs, frame: &mut Frame) {
                 .title_top(
                     " Message "
                         .bold()
-                        .fg(Color::Indexed(args.colors.confirm_color)),
+                        .fg(Color::Indexed(cfg.colors.confirm_color)),
                 )
-                .border_style(Style::new().fg(Color::Indexed(args.colors.confirm_color)))
+                .border_style(Style::new().fg(Color::Indexed(cfg.colors.confirm_color)))
                 .style(
                     Style::new()
-                        .fg(Color::Indexed(args.colors.main_text_color))
-                        .bg(Color::Indexed(args.colors.popup_bg_color)),
+                        .fg(Color::Indexed(cfg.colors.main_text_color))
+                        .bg(Color::Indexed(cfg.colors.popup_bg_color)),
                 );
 
             let content = Paragraph::new(app.bibiman.popup_area.popup_message.clone())
                 .block(block)
-                .style(Style::new().fg(Color::Indexed(args.colors.confirm_color)));
+                .style(Style::new().fg(Color::Indexed(cfg.colors.confirm_color)));
 
             // Calculate popup size. Width is number of string chars plus 2 for border
             let popup_area = popup_area(
@@ -265,21 +318,17 @@ pub fn render_popup(app: &mut App, args: &CLIArgs, frame: &mut Frame) {
             let area = frame.area();
 
             let block = Block::bordered()
-                .title_top(
-                    " Warning "
-                        .bold()
-                        .fg(Color::Indexed(args.colors.warn_color)),
-                )
+                .title_top(" Warning ".bold().fg(Color::Indexed(cfg.colors.warn_color)))
                 .border_style(Style::new().fg(Color::Red))
                 .style(
                     Style::new()
-                        .fg(Color::Indexed(args.colors.main_text_color))
-                        .bg(Color::Indexed(args.colors.popup_bg_color)),
+                        .fg(Color::Indexed(cfg.colors.main_text_color))
+                        .bg(Color::Indexed(cfg.colors.popup_bg_color)),
                 );
 
             let content = Paragraph::new(app.bibiman.popup_area.popup_message.clone())
                 .block(block)
-                .style(Style::new().fg(Color::Indexed(args.colors.warn_color)));
+                .style(Style::new().fg(Color::Indexed(cfg.colors.warn_color)));
 
             // Calculate popup size. Width is number of string chars plus 2 for border
             let popup_area = popup_area(
@@ -292,7 +341,11 @@ pub fn render_popup(app: &mut App, args: &CLIArgs, frame: &mut Frame) {
             frame.render_widget(Clear, popup_area);
             frame.render_widget(&content, popup_area)
         }
-        Some(PopupKind::Selection) => {
+        Some(PopupKind::Selection | PopupKind::ListSelection(_)) => {
+            let title = match &app.bibiman.popup_area.popup_kind {
+                Some(PopupKind::ListSelection(action)) => action.title(),
+                _ => " Open ".to_string(),
+            };
             let list_items: Vec<ListItem> = app
                 .bibiman
                 .popup_area
@@ -302,20 +355,20 @@ pub fn render_popup(app: &mut App, args: &CLIArgs, frame: &mut Frame) {
                 .collect();
 
             let block = Block::bordered()
-                .title_top(" Open ".bold())
+                .title_top(title.bold())
                 .title_bottom(" (j,k|↓,↑) ━ (ENTER) ━ (ESC) ".bold())
                 .title_alignment(Alignment::Center)
                 .style(
                     Style::new()
-                        .fg(Color::Indexed(args.colors.main_text_color))
-                        .bg(Color::Indexed(args.colors.popup_bg_color)),
+                        .fg(Color::Indexed(cfg.colors.main_text_color))
+                        .bg(Color::Indexed(cfg.colors.popup_bg_color)),
                 )
                 .border_set(symbols::border::THICK)
-                .border_style(Style::new().fg(Color::Indexed(args.colors.keyword_color)));
+                .border_style(Style::new().fg(Color::Indexed(cfg.colors.keyword_color)));
 
             let list = List::new(list_items).block(block).highlight_style(
                 Style::new()
-                    // .fg(Color::Indexed(args.colors.entry_color))
+                    // .fg(Color::Indexed(cfg.colors.entry_color))
                     .add_modifier(Modifier::BOLD)
                     .add_modifier(Modifier::REVERSED),
             );
@@ -327,19 +380,81 @@ pub fn render_popup(app: &mut App, args: &CLIArgs, frame: &mut Frame) {
             frame.render_widget(Clear, popup_area);
             frame.render_stateful_widget(list, popup_area, &mut app.bibiman.popup_area.popup_state)
         }
+        Some(PopupKind::Input(ref action)) => {
+            let area = frame.area();
+
+            let keys = if let InputAction::AttachFile = action {
+                " (ESC) ━ (TAB) ━ (ENTER) "
+            } else {
+                " (ESC) ━ (ENTER) "
+            };
+            let block = Block::bordered()
+                .title_top(action.title().bold())
+                .title_bottom(keys.bold())
+                .title_alignment(Alignment::Center)
+                .style(
+                    Style::new()
+                        .fg(Color::Indexed(cfg.colors.main_text_color))
+                        .bg(Color::Indexed(cfg.colors.popup_bg_color)),
+                )
+                .border_set(symbols::border::THICK)
+                .border_style(Style::new().fg(Color::Indexed(cfg.colors.entry_color)));
+
+            // Warnings and info text are shown above the input line
+            let popup = &app.bibiman.popup_area;
+            let mut info: Vec<Line> = popup
+                .popup_input_warnings
+                .iter()
+                .map(|w| {
+                    Line::from(w.as_str())
+                        .bold()
+                        .fg(Color::Indexed(cfg.colors.warn_color))
+                })
+                .collect();
+            info.extend(
+                popup
+                    .popup_input_info
+                    .iter()
+                    .map(|l| Line::from(l.as_str())),
+            );
+            if !info.is_empty() {
+                info.push(Line::from(""));
+            }
+
+            let popup_width = area.width * 3 / 4;
+            let popup_height = (info.len() as u16 + 3).min(area.height.saturating_sub(2));
+            let popup_area = popup_area(area, popup_width, popup_height);
+            let [info_area, input_area] =
+                Layout::vertical([Constraint::Min(0), Constraint::Length(1)])
+                    .areas(block.inner(popup_area));
+
+            // Scroll the input horizontally if the value is wider than the popup
+            let input = &popup.popup_input;
+            let inner_width = popup_width.saturating_sub(2) as usize;
+            let scroll = input.visual_scroll(inner_width.saturating_sub(1));
+            let paragraph = Paragraph::new(input.value()).scroll((0, scroll as u16));
+
+            frame.render_widget(Clear, popup_area);
+            frame.render_widget(block, popup_area);
+            frame.render_widget(Paragraph::new(info), info_area);
+            frame.render_widget(paragraph, input_area);
+
+            let cursor_x = input_area.x + (input.visual_cursor().max(scroll) - scroll) as u16;
+            frame.set_cursor_position(Position::new(cursor_x, input_area.y));
+        }
         None => {}
     }
 }
 
-pub fn render_header(args: &CLIArgs, frame: &mut Frame, rect: Rect) {
+pub fn render_header(cfg: &BibiConfig, frame: &mut Frame, rect: Rect) {
     let main_header = Paragraph::new("BIBIMAN – BibLaTeX manager TUI")
         .bold()
-        .fg(Color::Indexed(args.colors.entry_color))
+        .fg(Color::Indexed(cfg.colors.entry_color))
         .centered();
     frame.render_widget(main_header, rect)
 }
 
-pub fn render_footer(app: &mut App, args: &CLIArgs, frame: &mut Frame, rect: Rect) {
+pub fn render_footer(app: &mut App, cfg: &BibiConfig, frame: &mut Frame, rect: Rect) {
     let search_title = {
         match app.bibiman.former_area {
             Some(FormerArea::EntryArea) => "Search Entries: ".to_string(),
@@ -352,27 +467,27 @@ pub fn render_footer(app: &mut App, args: &CLIArgs, frame: &mut Frame, rect: Rec
 
     let block = Block::new()
         .padding(Padding::horizontal(1))
-        .bg(Color::Indexed(args.colors.bar_bg_color));
+        .bg(Color::Indexed(cfg.colors.bar_bg_color));
 
     let search_string = Paragraph::new(Line::from(vec![
         Span::styled(
             search_title,
             if let Some(FormerArea::EntryArea) = app.bibiman.former_area {
                 Style::new()
-                    .fg(Color::Indexed(args.colors.entry_color))
+                    .fg(Color::Indexed(cfg.colors.entry_color))
                     .add_modifier(Modifier::BOLD)
             } else if let Some(FormerArea::TagArea) = app.bibiman.former_area {
                 Style::new()
-                    .fg(Color::Indexed(args.colors.keyword_color))
+                    .fg(Color::Indexed(cfg.colors.keyword_color))
                     .add_modifier(Modifier::BOLD)
             } else {
                 Style::new()
-                    .fg(Color::Indexed(args.colors.highlight_text_color))
+                    .fg(Color::Indexed(cfg.colors.highlight_text_color))
                     .add_modifier(Modifier::BOLD)
             },
         ),
         Span::raw(app.bibiman.search_struct.search_string.clone())
-            .fg(Color::Indexed(args.colors.highlight_text_color)),
+            .fg(Color::Indexed(cfg.colors.highlight_text_color)),
     ]))
     .block(block);
 
@@ -384,7 +499,13 @@ pub fn render_footer(app: &mut App, args: &CLIArgs, frame: &mut Frame, rect: Rec
 // 1. Basename of the currently loaded file
 // 2. Keyword by which the entries are filtered at the moment
 // 3. Currently selected entry and total count of entries
-pub fn render_file_info(app: &mut App, args: &CLIArgs, frame: &mut Frame, rect: Rect) {
+pub fn render_file_info(
+    app: &mut App,
+    args: &CLIArgs,
+    cfg: &BibiConfig,
+    frame: &mut Frame,
+    rect: Rect,
+) {
     let block = Block::new() // can also be Block::new
         // Leave Top empty to simulate one large box with borders of entry list
         .borders(Borders::LEFT | Borders::RIGHT | Borders::BOTTOM)
@@ -394,10 +515,10 @@ pub fn render_file_info(app: &mut App, args: &CLIArgs, frame: &mut Frame, rect:
             symbols::border::PLAIN
         })
         .border_style(if let CurrentArea::EntryArea = app.bibiman.current_area {
-            Style::new().fg(Color::Indexed(args.colors.highlight_text_color))
+            Style::new().fg(Color::Indexed(cfg.colors.highlight_text_color))
         } else {
             Style::new()
-                .fg(Color::Indexed(args.colors.entry_color))
+                .fg(Color::Indexed(cfg.colors.entry_color))
                 .add_modifier(Modifier::BOLD)
         });
 
@@ -414,54 +535,53 @@ pub fn render_file_info(app: &mut App, args: &CLIArgs, frame: &mut Frame, rect:
     let file_info = if args.pos_args.len() == 1 && args.pos_args.first().unwrap().is_file() {
         Line::from(vec![
             Span::raw("File: ")
-                .fg(Color::Indexed(args.colors.main_text_color))
+                .fg(Color::Indexed(cfg.colors.main_text_color))
                 .bold(),
             Span::raw(args.pos_args[0].file_name().unwrap().to_string_lossy())
-                .fg(Color::Indexed(args.colors.main_text_color))
+                .fg(Color::Indexed(cfg.colors.main_text_color))
                 .bold(),
         ])
-        .bg(Color::Indexed(args.colors.bar_bg_color))
+        .bg(Color::Indexed(cfg.colors.bar_bg_color))
     } else if args.pos_args.len() == 1 && args.pos_args.first().unwrap().is_dir() {
         Line::from(vec![
             Span::raw("Directory: ")
                 .bold()
-                .fg(Color::Indexed(args.colors.main_text_color)),
+                .fg(Color::Indexed(cfg.colors.main_text_color)),
             Span::raw(args.pos_args[0].file_name().unwrap().to_string_lossy())
-                .fg(Color::Indexed(args.colors.main_text_color))
+                .fg(Color::Indexed(cfg.colors.main_text_color))
                 .bold(),
             Span::raw("/*.bib")
-                .fg(Color::Indexed(args.colors.main_text_color))
+                .fg(Color::Indexed(cfg.colors.main_text_color))
                 .bold(),
         ])
-        .bg(Color::Indexed(args.colors.bar_bg_color))
+        .bg(Color::Indexed(cfg.colors.bar_bg_color))
     } else {
         Line::from(vec![
             Span::raw("Multiple files (")
-                .fg(Color::Indexed(args.colors.main_text_color))
+                .fg(Color::Indexed(cfg.colors.main_text_color))
                 .bold(),
             Span::raw(count_files(&args.files).to_string())
-                .fg(Color::Indexed(args.colors.main_text_color))
+                .fg(Color::Indexed(cfg.colors.main_text_color))
                 .bold(),
             Span::raw(")")
-                .fg(Color::Indexed(args.colors.main_text_color))
+                .fg(Color::Indexed(cfg.colors.main_text_color))
                 .bold(),
         ])
-        .bg(Color::Indexed(args.colors.bar_bg_color))
+        .bg(Color::Indexed(cfg.colors.bar_bg_color))
     };
 
-    let cur_keywords = Line::from(if !app.bibiman.tag_list.selected_keywords.is_empty() {
+    let cur_keywords = Line::from(if !app.bibiman.tag_list.keyword_filter.is_empty() {
         vec![
-            Span::raw("Selected keywords: ").fg(Color::Indexed(args.colors.main_text_color)),
-            // Show all keywords in correct order if list is filtered
-            // successively by multiple keywords
-            Span::raw(app.bibiman.tag_list.selected_keywords.join(" → "))
+            Span::raw("Keyword filter: ").fg(Color::Indexed(cfg.colors.main_text_color)),
+            // Show the whole filter chain in the order it is applied
+            Span::raw(format_keyword_filter(&app.bibiman.tag_list.keyword_filter))
                 .bold()
                 .green(),
         ]
     } else {
         vec![Span::raw(" ")]
     })
-    .bg(Color::Indexed(args.colors.bar_bg_color));
+    .bg(Color::Indexed(cfg.colors.bar_bg_color));
     // .render(keyword_area, buf);
 
     let item_count = Line::from(
@@ -472,7 +592,7 @@ pub fn render_file_info(app: &mut App, args: &CLIArgs, frame: &mut Frame, rect:
             .selected()
             .is_some()
         {
-            vec![
+            let count = vec![
                 Span::raw(
                     // Because method scroll_down_by() of TableState lets numbers
                     // printed overflow for short moment, we have to check manually
@@ -497,38 +617,69 @@ pub fn render_file_info(app: &mut App, args: &CLIArgs, frame: &mut Frame, rect:
                         .to_string()
                     },
                 )
-                .fg(Color::Indexed(args.colors.main_text_color))
+                .fg(Color::Indexed(cfg.colors.main_text_color))
                 .bold(),
-                Span::raw("/").fg(Color::Indexed(args.colors.main_text_color)),
+                Span::raw("/").fg(Color::Indexed(cfg.colors.main_text_color)),
                 Span::raw(app.bibiman.entry_table.entry_table_items.len().to_string())
-                    .fg(Color::Indexed(args.colors.main_text_color)),
-            ]
+                    .fg(Color::Indexed(cfg.colors.main_text_color)),
+            ];
+            problem_count(app, cfg)
+                .into_iter()
+                .chain(marked_count(app, cfg))
+                .chain(count)
+                .collect()
         } else {
             vec![Span::raw("No entries")]
         },
     )
     .right_aligned()
-    .bg(Color::Indexed(args.colors.bar_bg_color));
+    .bg(Color::Indexed(cfg.colors.bar_bg_color));
     frame.render_widget(file_info, file_area);
     frame.render_widget(cur_keywords, keyword_area);
     frame.render_widget(item_count, count_area);
 }
 
-pub fn render_entrytable(app: &mut App, args: &CLIArgs, frame: &mut Frame, rect: Rect) {
+// Number of marked entries shown in front of the entry count
+fn marked_count<'a>(app: &App, cfg: &BibiConfig) -> Option<Span<'a>> {
+    let count = app.bibiman.entry_table.marked_citekeys().len();
+    if count == 0 {
+        return None;
+    }
+    Some(
+        Span::raw(format!("{} marked  ", count))
+            .fg(Color::Indexed(cfg.colors.entry_color))
+            .bold(),
+    )
+}
+
+// Number of problems found while loading the bibfiles
+fn problem_count<'a>(app: &App, cfg: &BibiConfig) -> Option<Span<'a>> {
+    let count = app.bibiman.main_biblio.diagnostics.len();
+    if count == 0 {
+        return None;
+    }
+    Some(
+        Span::raw(format!("{} problem(s)  ", count))
+            .fg(Color::Indexed(cfg.colors.warn_color))
+            .bold(),
+    )
+}
+
+pub fn render_entrytable(app: &mut App, cfg: &BibiConfig, frame: &mut Frame, rect: Rect) {
     let entry_box_selected_border_style: Style =
-        Style::new().fg(Color::Indexed(args.colors.highlight_text_color));
+        Style::new().fg(Color::Indexed(cfg.colors.highlight_text_color));
     let entry_box_selected_title_style: Style = Style::new()
-        .fg(Color::Indexed(args.colors.entry_color))
+        .fg(Color::Indexed(cfg.colors.entry_color))
         .add_modifier(Modifier::BOLD);
     let entry_box_unselected_border_style: Style =
-        Style::new().fg(Color::Indexed(args.colors.main_text_color));
+        Style::new().fg(Color::Indexed(cfg.colors.main_text_color));
     let entry_box_unselected_title_style: Style = Style::new()
-        .fg(Color::Indexed(args.colors.entry_color))
+        .fg(Color::Indexed(cfg.colors.entry_color))
         .add_modifier(Modifier::BOLD);
     let selected_table_col_style: Style = Style::new().add_modifier(Modifier::BOLD);
     let selectec_table_cell_style: Style = Style::new().add_modifier(Modifier::REVERSED);
     let entry_selected_row_style: Style = Style::new()
-        .fg(Color::Indexed(args.colors.entry_color))
+        .fg(Color::Indexed(cfg.colors.entry_color))
         .add_modifier(Modifier::BOLD)
         .add_modifier(Modifier::REVERSED);
 
@@ -558,8 +709,8 @@ pub fn render_entrytable(app: &mut App, args: &CLIArgs, frame: &mut Frame, rect:
 
     let header_style = Style::default()
         .bold()
-        .fg(Color::Indexed(args.colors.main_text_color))
-        .bg(Color::Indexed(args.colors.bar_bg_color));
+        .fg(Color::Indexed(cfg.colors.main_text_color))
+        .bg(Color::Indexed(cfg.colors.bar_bg_color));
 
     let header = Row::new(vec![
         Cell::from(
@@ -582,9 +733,9 @@ pub fn render_entrytable(app: &mut App, args: &CLIArgs, frame: &mut Frame, rect:
                 if let EntryTableColumn::Authors =
                     app.bibiman.entry_table.entry_table_selected_column
                 {
-                    Color::Indexed(args.colors.selected_row_bg_color)
+                    Color::Indexed(cfg.colors.selected_row_bg_color)
                 } else {
-                    Color::Indexed(args.colors.bar_bg_color)
+                    Color::Indexed(cfg.colors.bar_bg_color)
                 },
             ),
         ),
@@ -606,9 +757,9 @@ pub fn render_entrytable(app: &mut App, args: &CLIArgs, frame: &mut Frame, rect:
             .bg(
                 if let EntryTableColumn::Title = app.bibiman.entry_table.entry_table_selected_column
                 {
-                    Color::Indexed(args.colors.selected_row_bg_color)
+                    Color::Indexed(cfg.colors.selected_row_bg_color)
                 } else {
-                    Color::Indexed(args.colors.bar_bg_color)
+                    Color::Indexed(cfg.colors.bar_bg_color)
                 },
             ),
         ),
@@ -630,9 +781,9 @@ pub fn render_entrytable(app: &mut App, args: &CLIArgs, frame: &mut Frame, rect:
             .bg(
                 if let EntryTableColumn::Year = app.bibiman.entry_table.entry_table_selected_column
                 {
-                    Color::Indexed(args.colors.selected_row_bg_color)
+                    Color::Indexed(cfg.colors.selected_row_bg_color)
                 } else {
-                    Color::Indexed(args.colors.bar_bg_color)
+                    Color::Indexed(cfg.colors.bar_bg_color)
                 },
             ),
         ),
@@ -656,9 +807,9 @@ pub fn render_entrytable(app: &mut App, args: &CLIArgs, frame: &mut Frame, rect:
                 if let EntryTableColumn::Pubtype =
                     app.bibiman.entry_table.entry_table_selected_column
                 {
-                    Color::Indexed(args.colors.selected_row_bg_color)
+                    Color::Indexed(cfg.colors.selected_row_bg_color)
                 } else {
-                    Color::Indexed(args.colors.bar_bg_color)
+                    Color::Indexed(cfg.colors.bar_bg_color)
                 },
             ),
         ),
@@ -666,6 +817,17 @@ pub fn render_entrytable(app: &mut App, args: &CLIArgs, frame: &mut Frame, rect:
     .style(header_style)
     .height(1);
 
+    // Check marks before iterating mutably over the entries
+    let marked: Vec<bool> = (0..app.bibiman.entry_table.entry_table_items.len())
+        .map(|i| app.bibiman.entry_table.is_marked(i))
+        .collect();
+    let selected = app
+        .bibiman
+        .entry_table
+        .entry_table_state
+        .selected()
+        .unwrap_or(0) as i32;
+
     // Iterate over vector storing each entries data fields
     let rows = app
         .bibiman
@@ -675,23 +837,38 @@ pub fn render_entrytable(app: &mut App, args: &CLIArgs, frame: &mut Frame, rect:
         .enumerate()
         .map(|(i, data)| {
             let item = data.ref_vec();
-            item.into_iter()
-                .map(|content| Cell::from(Text::from(content.to_string())))
-                .collect::<Row>()
-                .style(
-                    Style::new().fg(color_list(
-                        args,
-                        i as i32,
-                        app.bibiman
-                            .entry_table
-                            .entry_table_state
-                            .selected()
-                            .unwrap_or(0) as i32,
-                        args.colors.highlight_text_color,
-                        20,
-                    )),
-                )
-                .height(1)
+            let row = item
+                .into_iter()
+                .enumerate()
+                .map(|(col, content)| {
+                    // Marked entries are prefixed with a dot in the first column
+                    if col == 0 && marked[i] {
+                        Cell::from(Line::from(vec![
+                            Span::raw(MARKED_ENTRY)
+                                .fg(Color::Indexed(cfg.colors.entry_color))
+                                .bold(),
+                            Span::raw(content.to_string()),
+                        ]))
+                    } else {
+                        Cell::from(Text::from(content.to_string()))
+                    }
+                })
+                .collect::<Row>();
+            let style = Style::new().fg(color_list(
+                cfg,
+                i as i32,
+                selected,
+                cfg.colors.highlight_text_color,
+                20,
+            ));
+            row.style(if marked[i] {
+                style
+                    .bg(Color::Indexed(cfg.colors.selected_row_bg_color))
+                    .bold()
+            } else {
+                style
+            })
+            .height(1)
         });
     let entry_table = Table::new(
         rows,
@@ -740,11 +917,11 @@ pub fn render_entrytable(app: &mut App, args: &CLIArgs, frame: &mut Frame, rect:
     }
 }
 
-pub fn render_selected_item(app: &mut App, args: &CLIArgs, frame: &mut Frame, rect: Rect) {
+pub fn render_selected_item(app: &mut App, cfg: &BibiConfig, frame: &mut Frame, rect: Rect) {
     // We get the info depending on the item's state.
     let style_value = Style::new()
         .bold()
-        .fg(Color::Indexed(args.colors.main_text_color));
+        .fg(Color::Indexed(cfg.colors.main_text_color));
     let lines = {
         if app
             .bibiman
@@ -766,7 +943,7 @@ pub fn render_selected_item(app: &mut App, args: &CLIArgs, frame: &mut Frame, re
                 // Span::styled(cur_entry.authors.clone(), Style::new().green()),
                 Span::styled(
                     cur_entry.authors(),
-                    Style::new().fg(Color::Indexed(args.colors.info_color)),
+                    Style::new().fg(Color::Indexed(cfg.colors.info_color)),
                 ),
             ]));
             if cur_entry.subtitle.is_some() {
@@ -775,19 +952,19 @@ pub fn render_selected_item(app: &mut App, args: &CLIArgs, frame: &mut Frame, re
                     Span::styled(
                         cur_entry.title(),
                         Style::new()
-                            .fg(Color::Indexed(args.colors.entry_color))
+                            .fg(Color::Indexed(cfg.colors.entry_color))
                             .add_modifier(Modifier::ITALIC),
                     ),
                     Span::styled(
                         ": ",
                         Style::new()
-                            .fg(Color::Indexed(args.colors.entry_color))
+                            .fg(Color::Indexed(cfg.colors.entry_color))
                             .add_modifier(Modifier::ITALIC),
                     ),
                     Span::styled(
                         cur_entry.subtitle(),
                         Style::new()
-                            .fg(Color::Indexed(args.colors.entry_color))
+                            .fg(Color::Indexed(cfg.colors.entry_color))
                             .add_modifier(Modifier::ITALIC),
                     ),
                 ]));
@@ -797,7 +974,7 @@ pub fn render_selected_item(app: &mut App, args: &CLIArgs, frame: &mut Frame, re
                     Span::styled(
                         cur_entry.title(),
                         Style::new()
-                            .fg(Color::Indexed(args.colors.entry_color))
+                            .fg(Color::Indexed(cfg.colors.entry_color))
                             .add_modifier(Modifier::ITALIC),
                     ),
                 ]));
@@ -806,7 +983,7 @@ pub fn render_selected_item(app: &mut App, args: &CLIArgs, frame: &mut Frame, re
                 Span::styled("Year: ", style_value),
                 Span::styled(
                     cur_entry.year(),
-                    Style::new().fg(Color::Indexed(args.colors.keyword_color)),
+                    Style::new().fg(Color::Indexed(cfg.colors.keyword_color)),
                 ),
             ]));
             // Render keywords in info box in Markdown code style
@@ -820,31 +997,34 @@ pub fn render_selected_item(app: &mut App, args: &CLIArgs, frame: &mut Frame, re
                 let mut content = vec![Span::styled("Keywords: ", style_value)];
                 for k in kw {
                     // Add half block highlighted in bg color to enlarge block
-                    content.push(Span::raw("▐").fg(Color::Indexed(args.colors.bar_bg_color)));
+                    content.push(Span::raw("▐").fg(Color::Indexed(cfg.colors.bar_bg_color)));
                     content.push(Span::styled(
                         k,
                         Style::default()
-                            .bg(Color::Indexed(args.colors.bar_bg_color))
+                            .bg(Color::Indexed(cfg.colors.bar_bg_color))
                             .fg(
                                 // Highlight selected keyword green
                                 if app
                                     .bibiman
                                     .tag_list
-                                    .selected_keywords
+                                    .keyword_filter
                                     .iter()
-                                    .any(|e| e == k)
+                                    .any(|f| f.keyword == k && f.op != FilterOp::Not)
                                 {
                                     Color::Green
                                 } else {
-                                    Color::Indexed(args.colors.main_text_color)
+                                    Color::Indexed(cfg.colors.main_text_color)
                                 },
                             ),
                     ));
-                    content.push(Span::raw("▌").fg(Color::Indexed(args.colors.bar_bg_color)));
+                    content.push(Span::raw("▌").fg(Color::Indexed(cfg.colors.bar_bg_color)));
                 }
                 lines.push(Line::from(content))
             }
-            if cur_entry.doi_url.is_some() || cur_entry.filepath.is_some() {
+            if cur_entry.doi_url.is_some()
+                || cur_entry.filepath.is_some()
+                || cur_entry.source.is_some()
+            {
                 lines.push(Line::raw(""));
             }
             if cur_entry.doi_url.is_some() {
@@ -853,24 +1033,56 @@ pub fn render_selected_item(app: &mut App, args: &CLIArgs, frame: &mut Frame, re
                     Span::styled(
                         cur_entry.doi_url(),
                         Style::new()
-                            .fg(Color::Indexed(args.colors.main_text_color))
+                            .fg(Color::Indexed(cfg.colors.main_text_color))
                             .underlined(),
                     ),
                 ]));
             }
-            if cur_entry.filepath.is_some() {
-                lines.push(Line::from(vec![
+            // Files which don't exist are flagged
+            for file in cur_entry.files() {
+                let mut spans = vec![
                     Span::styled("File: ", style_value),
                     Span::styled(
-                        cur_entry.filepath().to_string_lossy(),
-                        Style::new().fg(Color::Indexed(args.colors.main_text_color)),
+                        file.clone(),
+                        Style::new().fg(Color::Indexed(cfg.colors.main_text_color)),
+                    ),
+                ];
+                if !attach::file_exists(&file) {
+                    spans.push(
+                        Span::raw(" (missing)")
+                            .bold()
+                            .fg(Color::Indexed(cfg.colors.warn_color)),
+                    );
+                }
+                lines.push(Line::from(spans));
+            }
+            if let Some(source) = &cur_entry.source {
+                lines.push(Line::from(vec![
+                    Span::styled("Source: ", style_value),
+                    Span::styled(
+                        source.location(),
+                        Style::new().fg(Color::Indexed(cfg.colors.main_text_color)),
                     ),
                 ]));
             }
+            // Show where the phrases of a full-text search were found
+            if let Some(text) = &cur_entry.fulltext {
+                for phrase in &app.bibiman.search_struct.fulltext_phrases {
+                    if let Some(snippet) = snippet(text, phrase, 40) {
+                        lines.push(Line::from(vec![
+                            Span::styled("Found in PDF: ", style_value),
+                            Span::styled(
+                                snippet,
+                                Style::new().fg(Color::Indexed(cfg.colors.info_color)),
+                            ),
+                        ]));
+                    }
+                }
+            }
             lines.push(Line::from(""));
             lines.push(Line::from(vec![Span::styled(
                 cur_entry.abstract_text.clone(),
-                Style::new().fg(Color::Indexed(args.colors.main_text_color)),
+                Style::new().fg(Color::Indexed(cfg.colors.main_text_color)),
             )]));
             lines
         } else {
@@ -887,7 +1099,7 @@ pub fn render_selected_item(app: &mut App, args: &CLIArgs, frame: &mut Frame, re
     let block = Block::bordered()
         .title(Line::raw(" Entry Information ").centered().bold())
         .border_set(symbols::border::PLAIN)
-        .border_style(Style::new().fg(Color::Indexed(args.colors.main_text_color)))
+        .border_style(Style::new().fg(Color::Indexed(cfg.colors.main_text_color)))
         .padding(Padding::horizontal(1));
 
     // INFO: '.line_count' method only possible with unstable-rendered-line-info feature -> API might change: https://github.com/ratatui/ratatui/issues/293#ref-pullrequest-2027056434
@@ -940,19 +1152,50 @@ pub fn render_selected_item(app: &mut App, args: &CLIArgs, frame: &mut Frame, re
     frame.render_widget(item_info, rect);
 }
 
-pub fn render_taglist(app: &mut App, args: &CLIArgs, frame: &mut Frame, rect: Rect) {
+// Formatted reference of the selected entry, rendered with the CSL style
+// set in the config
+fn reference_paragraph<'a>(
+    reference: Result<String, String>,
+    app: &App,
+    cfg: &BibiConfig,
+) -> Paragraph<'a> {
+    let block = Block::bordered()
+        .title(
+            Line::raw(format!(
+                " Reference ({}) ",
+                app.bibiman.citation_preview.style
+            ))
+            .centered()
+            .bold(),
+        )
+        .border_set(symbols::border::PLAIN)
+        .border_style(Style::new().fg(Color::Indexed(cfg.colors.main_text_color)))
+        .padding(Padding::horizontal(1));
+    let text = match reference {
+        Ok(reference) => Span::styled(
+            reference,
+            Style::new().fg(Color::Indexed(cfg.colors.main_text_color)),
+        ),
+        Err(e) => Span::styled(e, Style::new().fg(Color::Indexed(cfg.colors.warn_color))),
+    };
+    Paragraph::new(Line::from(text))
+        .block(block)
+        .wrap(Wrap { trim: false })
+}
+
+pub fn render_taglist(app: &mut App, cfg: &BibiConfig, frame: &mut Frame, rect: Rect) {
     let keyword_box_selected_border_style: Style =
-        Style::new().fg(Color::Indexed(args.colors.highlight_text_color));
+        Style::new().fg(Color::Indexed(cfg.colors.highlight_text_color));
     let keyword_box_selected_title_style: Style = Style::new()
-        .fg(Color::Indexed(args.colors.keyword_color))
+        .fg(Color::Indexed(cfg.colors.keyword_color))
         .add_modifier(Modifier::BOLD);
     let keyword_box_unselected_border_style: Style =
-        Style::new().fg(Color::Indexed(args.colors.main_text_color));
+        Style::new().fg(Color::Indexed(cfg.colors.main_text_color));
     let keyword_box_unselected_title_style: Style = Style::new()
-        .fg(Color::Indexed(args.colors.keyword_color))
+        .fg(Color::Indexed(cfg.colors.keyword_color))
         .add_modifier(Modifier::BOLD);
     let keyword_selected_row_style: Style = Style::new()
-        .fg(Color::Indexed(args.colors.keyword_color))
+        .fg(Color::Indexed(cfg.colors.keyword_color))
         .add_modifier(Modifier::BOLD)
         .add_modifier(Modifier::REVERSED);
 
@@ -990,14 +1233,14 @@ pub fn render_taglist(app: &mut App, args: &CLIArgs, frame: &mut Frame, rect: Re
             ListItem::from(keyword.to_owned()).style(Style::new().fg(
                 if app.bibiman.tag_list.tag_list_state.selected().is_some() {
                     color_list(
-                        args,
+                        cfg,
                         i as i32,
                         app.bibiman.tag_list.tag_list_state.selected().unwrap() as i32,
-                        args.colors.highlight_text_color,
+                        cfg.colors.highlight_text_color,
                         20,
                     )
                 } else {
-                    Color::Indexed(args.colors.main_text_color)
+                    Color::Indexed(cfg.colors.main_text_color)
                 },
             )) //.bg(color)
         })
@@ -0,0 +1,157 @@
+// bibiman - a TUI for managing BibLaTeX databases
+// Copyright (C) 2024  lukeflo
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+/////
+
+//! Detect changes of the bibfiles made by other programs.
+//!
+//! The passed files and directories are polled, which works the same on all
+//! platforms and for network drives. Directories are walked again on every
+//! poll, thus, new bibfiles are found too. Polling runs in a thread of its
+//! own, which doesn't block the event loop.
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_util::sync::CancellationToken;
+
+use super::Event;
+use crate::cliargs::collect_bibfiles;
+
+// Path, modification time and size of a bibfile
+type Stamp = (PathBuf, Option<SystemTime>, u64);
+
+#[derive(Debug)]
+pub struct FileWatcher {
+    // Files and directories passed as positional args or in the config
+    paths: Vec<PathBuf>,
+    // State of the files when they were loaded the last time
+    known: Vec<Stamp>,
+    // Changed state which wasn't reported yet
+    pending: Option<Vec<Stamp>>,
+}
+
+impl FileWatcher {
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        let known = snapshot(&paths);
+        Self {
+            paths,
+            known,
+            pending: None,
+        }
+    }
+
+    /// Check the files for changes. A change is only reported if the files
+    /// didn't change again since the last poll, thus, files which are still
+    /// being written aren't loaded. Returns the current list of bibfiles
+    pub fn poll(&mut self) -> Option<Vec<PathBuf>> {
+        let current = snapshot(&self.paths);
+        if current == self.known {
+            self.pending = None;
+            return None;
+        }
+        if self.pending.as_ref() != Some(&current) {
+            self.pending = Some(current);
+            return None;
+        }
+        self.pending = None;
+        self.known = current;
+        Some(self.known.iter().map(|(file, _, _)| file.clone()).collect())
+    }
+
+    /// Take the current state of the files as loaded, thus, changes made
+    /// before aren't reported
+    pub fn resync(&mut self) {
+        self.known = snapshot(&self.paths);
+        self.pending = None;
+    }
+}
+
+/// Poll the files every 500 ms and send [`Event::FilesChanged`] until the
+/// token is cancelled. `loaded` is set by bibiman whenever it loaded the
+/// files itself, e.g. after writing to them. Such changes aren't reported
+pub fn spawn(
+    paths: Vec<PathBuf>,
+    sender: UnboundedSender<Event>,
+    cancellation_token: CancellationToken,
+    loaded: Arc<AtomicBool>,
+) {
+    thread::spawn(move || {
+        let mut watcher = FileWatcher::new(paths);
+        while !cancellation_token.is_cancelled() {
+            thread::sleep(Duration::from_millis(500));
+            if loaded.swap(false, Ordering::Relaxed) {
+                watcher.resync();
+            } else if let Some(files) = watcher.poll() {
+                if sender.send(Event::FilesChanged(files)).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+}
+
+fn snapshot(paths: &[PathBuf]) -> Vec<Stamp> {
+    collect_bibfiles(paths)
+        .into_iter()
+        .map(|file| {
+            let meta = file.metadata().ok();
+            let modified = meta.as_ref().and_then(|m| m.modified().ok());
+            let len = meta.map_or(0, |m| m.len());
+            (file, modified, len)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn report_settled_changes() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        let first = dir.join("first.bib");
+        fs::write(&first, "@misc{a,\n  title = {A},\n}\n").unwrap();
+
+        let mut watcher = FileWatcher::new(vec![dir.clone()]);
+        assert_eq!(watcher.poll(), None);
+
+        // Changed files are reported once they stayed the same for one poll
+        fs::write(&first, "@misc{a,\n  title = {Changed},\n}\n").unwrap();
+        assert_eq!(watcher.poll(), None);
+        assert_eq!(watcher.poll(), Some(vec![first.clone()]));
+        assert_eq!(watcher.poll(), None);
+
+        // New bibfiles in watched directories are found
+        let second = dir.join("second.bib");
+        fs::write(&second, "").unwrap();
+        assert_eq!(watcher.poll(), None);
+        let mut files = watcher.poll().unwrap();
+        files.sort();
+        assert_eq!(files, vec![first.clone(), second]);
+
+        // Changes loaded by bibiman itself aren't reported
+        fs::write(&first, "").unwrap();
+        watcher.resync();
+        assert_eq!(watcher.poll(), None);
+        assert_eq!(watcher.poll(), None);
+    }
+}